@@ -1,3 +1,4 @@
+use std::fmt;
 use std::ops::Range;
 
 pub type Span = Range<usize>;
@@ -23,6 +24,8 @@ pub struct Function {
     pub return_type: Type,
     pub name: String,
     pub params: Vec<Param>,
+    /// Takes more arguments after `params`: `T name(params, ...)`
+    pub is_variadic: bool,
     pub body: Option<Block>,
     pub attributes: Vec<Attribute>,
     pub span: Span,
@@ -47,6 +50,7 @@ pub struct Struct {
 pub struct Union {
     pub name: String,
     pub fields: Vec<Field>,
+    pub attributes: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -85,6 +89,7 @@ pub struct GlobalVar {
     pub ty: Type,
     pub name: String,
     pub init: Option<Expr>,
+    pub attributes: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -102,13 +107,18 @@ pub enum Type {
     I8, I16, I32, I64, ISize,
     U8, U16, U32, U64, USize,
     F32, F64,
+    /// The pointee and its qualifiers, `const` in `const char*`
     Pointer(Box<Type>, Vec<TypeQualifier>),
     Array(Box<Type>, Option<usize>),
-    Function(Box<Type>, Vec<Type>),
+    /// Return type, parameter types and whether it's variadic
+    Function(Box<Type>, Vec<Type>, bool),
     Struct(String),
     Union(String),
     Enum(String),
     Named(String),
+    /// The type of an expression that failed to type check. It is
+    /// compatible with everything so one mistake is only reported once.
+    Error,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -118,6 +128,8 @@ pub enum TypeQualifier {
     Restrict,
 }
 
+/// `[[name]]` or `[[name(args)]]` before an item. String arguments are
+/// stored without their quotes.
 #[derive(Debug, Clone)]
 pub struct Attribute {
     pub name: String,
@@ -202,19 +214,30 @@ pub struct SwitchCase {
     pub span: Span,
 }
 
+/// `asm [volatile] ("template" : outputs : inputs : clobbers);`
 #[derive(Debug, Clone)]
 pub struct InlineAsm {
     pub template: String,
-    pub outputs: Vec<String>,
-    pub inputs: Vec<String>,
+    pub outputs: Vec<AsmOperand>,
+    pub inputs: Vec<AsmOperand>,
     pub clobbers: Vec<String>,
     pub is_volatile: bool,
     pub span: Span,
 }
 
+/// An asm operand, `"constraint"(expr)`
+#[derive(Debug, Clone)]
+pub struct AsmOperand {
+    pub constraint: String,
+    pub expr: Expr,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub enum Expr {
-    IntLiteral(i64, Span),
+    /// An integer constant and its type, which follows from its suffix
+    /// and value as in C
+    IntLiteral(i64, Type, Span),
     FloatLiteral(f64, Span),
     StringLiteral(String, Span),
     CharLiteral(char, Span),
@@ -226,9 +249,20 @@ pub enum Expr {
     Index(Box<Expr>, Box<Expr>, Span),
     Field(Box<Expr>, String, Span),
     Cast(Type, Box<Expr>, Span),
+    /// `sizeof(type)`
     Sizeof(Type, Span),
+    /// `sizeof expr`: the size of the expression's type. The expression
+    /// itself is never evaluated.
+    SizeofValue(Box<Expr>, Span),
+    /// `alignof(type)`
+    Alignof(Type, Span),
     Assign(Box<Expr>, Box<Expr>, Span),
+    /// `lhs op= rhs`
+    CompoundAssign(BinaryOp, Box<Expr>, Box<Expr>, Span),
     Ternary(Box<Expr>, Box<Expr>, Box<Expr>, Span),
+    /// `{a, b, c}`: the elements of an array or the fields of a struct,
+    /// in order. Only allowed as the initializer of a global.
+    InitList(Vec<Expr>, Span),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -246,10 +280,36 @@ pub enum UnaryOp {
     PreInc, PreDec, PostInc, PostDec,
 }
 
+impl BinaryOp {
+    /// The operator as written in source
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::LeftShift => "<<",
+            BinaryOp::RightShift => ">>",
+            BinaryOp::LogicalAnd => "&&",
+            BinaryOp::LogicalOr => "||",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::Greater => ">",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::GreaterEqual => ">=",
+        }
+    }
+}
+
 impl Expr {
     pub fn span(&self) -> Span {
         match self {
-            Expr::IntLiteral(_, s) |
+            Expr::IntLiteral(_, _, s) |
             Expr::FloatLiteral(_, s) |
             Expr::StringLiteral(_, s) |
             Expr::CharLiteral(_, s) |
@@ -262,9 +322,101 @@ impl Expr {
             Expr::Field(_, _, s) |
             Expr::Cast(_, _, s) |
             Expr::Sizeof(_, s) |
+            Expr::SizeofValue(_, s) |
+            Expr::Alignof(_, s) |
             Expr::Assign(_, _, s) |
-            Expr::Ternary(_, _, _, s) => s.clone(),
+            Expr::CompoundAssign(_, _, _, s) |
+            Expr::Ternary(_, _, _, s) |
+            Expr::InitList(_, s) => s.clone(),
+        }
+    }
+}
+
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expr(e) => e.span(),
+            Stmt::Let(l) => l.span.clone(),
+            Stmt::Return(_, s) |
+            Stmt::Break(s) |
+            Stmt::Continue(s) |
+            Stmt::Label(_, s) |
+            Stmt::Goto(_, s) => s.clone(),
+            Stmt::If(i) => i.span.clone(),
+            Stmt::While(w) => w.span.clone(),
+            Stmt::For(f) => f.span.clone(),
+            Stmt::DoWhile(d) => d.span.clone(),
+            Stmt::Switch(s) => s.span.clone(),
+            Stmt::Block(b) => b.span.clone(),
+            Stmt::Asm(a) => a.span.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Void => write!(f, "void"),
+            Type::Bool => write!(f, "bool"),
+            Type::Char => write!(f, "char"),
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::ISize => write!(f, "isize"),
+            Type::U8 => write!(f, "u8"),
+            Type::U16 => write!(f, "u16"),
+            Type::U32 => write!(f, "u32"),
+            Type::U64 => write!(f, "u64"),
+            Type::USize => write!(f, "usize"),
+            Type::F32 => write!(f, "f32"),
+            Type::F64 => write!(f, "f64"),
+            // The qualifiers are those of the pointee: `const char*`, and
+            // `char* const*` for a pointer to a const pointer
+            Type::Pointer(inner, qualifiers) => match inner.as_ref() {
+                Type::Function(ret, params, variadic) => write!(f, "{} (*)({})", ret, param_list(params, *variadic)),
+                Type::Pointer(..) => {
+                    write!(f, "{}", inner)?;
+                    for qualifier in qualifiers {
+                        write!(f, " {}", qualifier)?;
+                    }
+                    write!(f, "*")
+                }
+                _ => {
+                    for qualifier in qualifiers {
+                        write!(f, "{} ", qualifier)?;
+                    }
+                    write!(f, "{}*", inner)
+                }
+            },
+            Type::Array(elem, Some(size)) => write!(f, "{}[{}]", elem, size),
+            Type::Array(elem, None) => write!(f, "{}[]", elem),
+            Type::Function(ret, params, variadic) => write!(f, "{}({})", ret, param_list(params, *variadic)),
+            Type::Struct(name) => write!(f, "struct {}", name),
+            Type::Union(name) => write!(f, "union {}", name),
+            Type::Enum(name) => write!(f, "enum {}", name),
+            Type::Named(name) => write!(f, "{}", name),
+            Type::Error => write!(f, "{{error}}"),
         }
     }
 }
 
+/// The parameters of a function type as written in C: `i32, char*, ...`
+fn param_list(params: &[Type], variadic: bool) -> String {
+    let mut params: Vec<_> = params.iter().map(|p| p.to_string()).collect();
+    if variadic {
+        params.push("...".to_string());
+    }
+    params.join(", ")
+}
+
+impl fmt::Display for TypeQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeQualifier::Const => write!(f, "const"),
+            TypeQualifier::Volatile => write!(f, "volatile"),
+            TypeQualifier::Restrict => write!(f, "restrict"),
+        }
+    }
+}
@@ -2,14 +2,14 @@ use anyhow::Result;
 use std::path::PathBuf;
 use std::fs;
 use inkwell::context::Context;
-use inkwell::targets::{Target, InitializationConfig, TargetMachine, RelocMode, CodeModel, FileType};
+use inkwell::targets::FileType;
 use inkwell::OptimizationLevel;
 
 use crate::lexer::Lexer;
-use crate::parser::Parser;
 use crate::types::TypeChecker;
 use crate::hir::HirBuilder;
-use crate::codegen::CodeGen;
+use crate::codegen::{self, CodeGen};
+use super::FrontendArgs;
 
 pub fn execute(
     input: PathBuf,
@@ -18,11 +18,13 @@ pub fn execute(
     target: Option<String>,
     link: Vec<String>,
     opt_level: u8,
+    frontend: FrontendArgs,
 ) -> Result<()> {
     println!("🔨 Building {}...", input.display());
     
-    // Read source file
-    let source = fs::read_to_string(&input)?;
+    // Preprocess
+    println!("  Preprocessing...");
+    let (source, sources) = super::preprocess(&input, &frontend)?;
     
     // Lex
     println!("  Lexing...");
@@ -31,21 +33,33 @@ pub fn execute(
     
     // Parse
     println!("  Parsing...");
-    let mut parser = Parser::new(tokens);
-    let ast = parser.parse_program()
-        .map_err(|e| anyhow::anyhow!("Parse error: {}", e))?;
+    let ast = super::parse(tokens, &input, &sources, &frontend)?;
+    
+    // Sizes of types depend on the target, so set it up before checking
+    let opt = if release || opt_level >= 2 {
+        OptimizationLevel::Aggressive
+    } else if opt_level == 1 {
+        OptimizationLevel::Less
+    } else {
+        OptimizationLevel::None
+    };
+    let target_machine = super::target_machine(target.as_deref(), opt)?;
+    let data_model = codegen::data_model(&target_machine);
     
     // Type check
     println!("  Type checking...");
     let mut type_checker = TypeChecker::new();
-    type_checker.check_program(&ast)
-        .map_err(|e| anyhow::anyhow!("Type error: {}", e))?;
+    type_checker.set_lints(super::lint_config(&frontend)?);
+    type_checker.set_data_model(data_model);
+    let diagnostics = type_checker.check_program(&ast);
+    super::report_diagnostics(&diagnostics, &input, &sources)?;
     
     // Lower to HIR
     println!("  Lowering to HIR...");
     let mut hir_builder = HirBuilder::new();
+    hir_builder.set_data_model(data_model);
     let hir = hir_builder.lower_program(&ast)
-        .map_err(|e| anyhow::anyhow!("HIR error: {}", e))?;
+        .map_err(|e| super::report(&[e], &input, &sources))?;
     
     // Generate LLVM IR
     println!("  Generating LLVM IR...");
@@ -54,8 +68,15 @@ pub fn execute(
         .and_then(|s| s.to_str())
         .unwrap_or("module");
     let mut codegen = CodeGen::new(&context, module_name);
+    codegen.set_target(&target_machine);
+    for option in &frontend.codegen {
+        match option.as_str() {
+            "wrapv" => codegen.set_wrapv(true),
+            other => anyhow::bail!("Unknown code generation option '-f{}'", other),
+        }
+    }
     codegen.codegen_program(&hir)
-        .map_err(|e| anyhow::anyhow!("Codegen error: {}", e))?;
+        .map_err(|e| super::report(&[e], &input, &sources))?;
     
     // Determine output path
     let output_path = output.unwrap_or_else(|| {
@@ -73,36 +94,6 @@ pub fn execute(
         println!("  LLVM IR written to: {}", ir_path.display());
     }
     
-    // Initialize LLVM target
-    Target::initialize_native(&InitializationConfig::default())
-        .map_err(|e| anyhow::anyhow!("Failed to initialize target: {}", e))?;
-    
-    let target_triple = if let Some(t) = target {
-        inkwell::targets::TargetTriple::create(&t)
-    } else {
-        TargetMachine::get_default_triple()
-    };
-    
-    let target = Target::from_triple(&target_triple)
-        .map_err(|e| anyhow::anyhow!("Failed to create target: {}", e))?;
-    
-    let opt = if release || opt_level >= 2 {
-        OptimizationLevel::Aggressive
-    } else if opt_level == 1 {
-        OptimizationLevel::Less
-    } else {
-        OptimizationLevel::None
-    };
-    
-    let target_machine = target.create_target_machine(
-        &target_triple,
-        "generic",
-        "",
-        opt,
-        RelocMode::Default,
-        CodeModel::Default,
-    ).ok_or_else(|| anyhow::anyhow!("Failed to create target machine"))?;
-    
     // Generate object file
     println!("  Generating object file...");
     let mut obj_path = output_path.clone();
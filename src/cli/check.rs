@@ -1,30 +1,32 @@
 use anyhow::Result;
 use std::path::PathBuf;
-use std::fs;
 
 use crate::lexer::Lexer;
-use crate::parser::Parser;
+use crate::codegen;
 use crate::types::TypeChecker;
+use inkwell::OptimizationLevel;
+use super::FrontendArgs;
 
-pub fn execute(input: PathBuf) -> Result<()> {
+pub fn execute(input: PathBuf, frontend: FrontendArgs) -> Result<()> {
     println!("🔍 Checking {}...", input.display());
     
-    // Read source file
-    let source = fs::read_to_string(&input)?;
+    // Preprocess
+    let (source, sources) = super::preprocess(&input, &frontend)?;
     
     // Lex
     let mut lexer = Lexer::new(&source);
     let tokens = lexer.tokenize();
     
     // Parse
-    let mut parser = Parser::new(tokens);
-    let ast = parser.parse_program()
-        .map_err(|e| anyhow::anyhow!("Parse error: {}", e))?;
+    let ast = super::parse(tokens, &input, &sources, &frontend)?;
     
     // Type check
     let mut type_checker = TypeChecker::new();
-    type_checker.check_program(&ast)
-        .map_err(|e| anyhow::anyhow!("Type error: {}", e))?;
+    type_checker.set_lints(super::lint_config(&frontend)?);
+    let target_machine = super::target_machine(None, OptimizationLevel::None)?;
+    type_checker.set_data_model(codegen::data_model(&target_machine));
+    let diagnostics = type_checker.check_program(&ast);
+    super::report_diagnostics(&diagnostics, &input, &sources)?;
     
     println!("✅ No errors found");
     
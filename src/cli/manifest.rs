@@ -0,0 +1,45 @@
+// The project manifest, fera.toml
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::lint::{LintConfig, LintLevel};
+
+/// The parts of `fera.toml` the compiler reads. Other sections are
+/// ignored here.
+#[derive(Debug, Default, Deserialize)]
+pub struct Manifest {
+    /// Lint name to level, e.g. `unused-variable = "deny"`
+    #[serde(default)]
+    pub lints: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Load `fera.toml` from `dir`, if there is one
+    pub fn load(dir: &Path) -> Result<Option<Manifest>> {
+        let path = dir.join("fera.toml");
+        if !path.exists() {
+            return Ok(None);
+        }
+        
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let manifest = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Some(manifest))
+    }
+    
+    /// Apply the `[lints]` table to `config`
+    pub fn apply_lints(&self, config: &mut LintConfig) -> Result<()> {
+        for (name, level) in &self.lints {
+            let level = LintLevel::from_name(level).ok_or_else(|| {
+                anyhow::anyhow!("Invalid level '{}' for lint '{}' in fera.toml (expected allow, warn or deny)", level, name)
+            })?;
+            config.set_by_name(name, level)
+                .map_err(|e| anyhow::anyhow!("{} in fera.toml", e))?;
+        }
+        Ok(())
+    }
+}
@@ -5,4 +5,221 @@ pub mod fmt;
 pub mod check;
 pub mod clean;
 pub mod doc;
+pub mod manifest;
 
+use anyhow::Result;
+use clap::{ArgMatches, Args, Command, FromArgMatches};
+use inkwell::targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple};
+use inkwell::OptimizationLevel;
+use std::path::{Path, PathBuf};
+
+use crate::ast::Program;
+use crate::error::FeraError;
+use crate::lexer::token::Token;
+use crate::lint::{LintConfig, LintLevel};
+use crate::lexer::preprocessor::Preprocessor;
+use crate::lexer::source_map::SourceMap;
+use crate::parser::Parser;
+use manifest::Manifest;
+
+/// Options shared by every command that runs the compiler frontend
+#[derive(Clone, Default)]
+pub struct FrontendArgs {
+    pub include_dirs: Vec<PathBuf>,
+    pub defines: Vec<String>,
+    pub error_limit: usize,
+    /// `-W`, `-A` and `-D` flags in command-line order
+    pub lint_levels: Vec<(String, LintLevel)>,
+    pub codegen: Vec<String>,
+}
+
+/// The flags behind `FrontendArgs`, as clap parses them
+#[derive(Args)]
+struct FrontendFlags {
+    /// Add a directory to the #include search path
+    #[arg(short = 'I', long = "include-dir", value_name = "DIR")]
+    include_dirs: Vec<PathBuf>,
+
+    /// Predefine a preprocessor macro
+    #[arg(long = "define", value_name = "NAME[=VALUE]")]
+    defines: Vec<String>,
+
+    /// Stop after this many errors (0 for no limit)
+    #[arg(long = "error-limit", value_name = "N", default_value_t = 20)]
+    error_limit: usize,
+
+    /// Report a lint as a warning (`all` for every lint)
+    #[arg(short = 'W', value_name = "LINT")]
+    warn: Vec<String>,
+
+    /// Silence a lint
+    #[arg(short = 'A', value_name = "LINT")]
+    allow: Vec<String>,
+
+    /// Report a lint as an error
+    #[arg(short = 'D', value_name = "LINT")]
+    deny: Vec<String>,
+
+    /// Enable a code generation option (`-fwrapv` makes signed overflow wrap)
+    #[arg(short = 'f', value_name = "OPTION")]
+    codegen: Vec<String>,
+}
+
+impl FromArgMatches for FrontendArgs {
+    fn from_arg_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        let flags = FrontendFlags::from_arg_matches(matches)?;
+        
+        // clap keeps each flag's values apart; their indices give back
+        // the order they were written in
+        let mut lint_levels = Vec::new();
+        for (id, names, level) in [
+            ("allow", flags.allow, LintLevel::Allow),
+            ("warn", flags.warn, LintLevel::Warn),
+            ("deny", flags.deny, LintLevel::Deny),
+        ] {
+            let indices = matches.indices_of(id).into_iter().flatten();
+            lint_levels.extend(indices.zip(names).map(|(index, name)| (index, name, level)));
+        }
+        lint_levels.sort_by_key(|(index, ..)| *index);
+        
+        Ok(Self {
+            include_dirs: flags.include_dirs,
+            defines: flags.defines,
+            error_limit: flags.error_limit,
+            lint_levels: lint_levels.into_iter().map(|(_, name, level)| (name, level)).collect(),
+            codegen: flags.codegen,
+        })
+    }
+    
+    fn update_from_arg_matches(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        *self = Self::from_arg_matches(matches)?;
+        Ok(())
+    }
+}
+
+impl Args for FrontendArgs {
+    fn augment_args(cmd: Command) -> Command {
+        FrontendFlags::augment_args(cmd)
+    }
+    
+    fn augment_args_for_update(cmd: Command) -> Command {
+        FrontendFlags::augment_args_for_update(cmd)
+    }
+}
+
+/// Lint levels from `fera.toml` and the command line. Flags override the
+/// manifest and apply in order, so `-D all -A shadowing` denies every
+/// lint but `shadowing`.
+pub fn lint_config(args: &FrontendArgs) -> Result<LintConfig> {
+    let mut config = LintConfig::new();
+    
+    if let Some(manifest) = Manifest::load(&std::env::current_dir()?)? {
+        manifest.apply_lints(&mut config)?;
+    }
+    
+    for (name, level) in &args.lint_levels {
+        config.set_by_name(name, *level).map_err(|e| anyhow::anyhow!(e))?;
+    }
+    
+    Ok(config)
+}
+
+/// The machine to compile for: `target` if given, otherwise the host.
+/// The system linker produces position independent executables by
+/// default, so jump tables and constants must be addressed PC-relative.
+pub fn target_machine(target: Option<&str>, opt: OptimizationLevel) -> Result<TargetMachine> {
+    Target::initialize_all(&InitializationConfig::default());
+    
+    let triple = match target {
+        Some(target) => TargetTriple::create(target),
+        None => TargetMachine::get_default_triple(),
+    };
+    let target = Target::from_triple(&triple)
+        .map_err(|e| anyhow::anyhow!("Failed to create target: {}", e))?;
+    
+    target.create_target_machine(&triple, "generic", "", opt, RelocMode::PIC, CodeModel::Default)
+        .ok_or_else(|| anyhow::anyhow!("Failed to create target machine"))
+}
+
+/// Run the preprocessor over `input`, returning the expanded source and
+/// the map from it back to the files it was read from
+pub fn preprocess(input: &Path, args: &FrontendArgs) -> Result<(String, SourceMap)> {
+    let mut preprocessor = Preprocessor::new();
+    
+    for dir in &args.include_dirs {
+        preprocessor.add_include_path(dir.clone());
+    }
+    
+    // Fera's own headers are always on the search path
+    let stdlib_path = std::env::current_dir()?.join("stdlib");
+    for dir in ["core", "embedded", "hosted"] {
+        preprocessor.add_include_path(stdlib_path.join(dir));
+    }
+    
+    for define in &args.defines {
+        let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+        preprocessor.add_define(name.to_string(), value.to_string());
+    }
+    
+    let source = preprocessor.process_file(input)
+        .map_err(|e| anyhow::anyhow!("Preprocessor error: {}", e))?;
+    
+    Ok((source, preprocessor.into_source_map()))
+}
+
+/// Print `errors` against the original sources and return the error the
+/// command should exit with
+pub fn report(errors: &[FeraError], input: &Path, sources: &SourceMap) -> anyhow::Error {
+    for error in errors {
+        error.report(sources);
+    }
+    
+    let count = errors.iter().filter(|e| e.is_error()).count();
+    let warnings = errors.len() - count;
+    let mut message = format!(
+        "could not compile {} due to {} previous error{}",
+        input.display(),
+        count,
+        plural(count)
+    );
+    if warnings > 0 {
+        message.push_str(&format!("; {} warning{} emitted", warnings, plural(warnings)));
+    }
+    anyhow::anyhow!(message)
+}
+
+/// Print errors and warnings, failing if there were any errors
+pub fn report_diagnostics(diagnostics: &[FeraError], input: &Path, sources: &SourceMap) -> Result<()> {
+    if diagnostics.iter().any(|d| d.is_error()) {
+        return Err(report(diagnostics, input, sources));
+    }
+    
+    for warning in diagnostics {
+        warning.report(sources);
+    }
+    if !diagnostics.is_empty() {
+        eprintln!("{} warning{} emitted", diagnostics.len(), plural(diagnostics.len()));
+    }
+    Ok(())
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+/// Parse `tokens`, reporting every syntax error found
+pub fn parse(tokens: Vec<Token>, input: &Path, sources: &SourceMap, args: &FrontendArgs) -> Result<Program> {
+    let mut parser = Parser::new(tokens);
+    parser.set_error_limit(args.error_limit);
+    let (program, errors) = parser.parse_program();
+    
+    if errors.is_empty() {
+        return Ok(program);
+    }
+    
+    let error = report(&errors, input, sources);
+    if parser.error_limit_reached() {
+        eprintln!("note: stopped after {} errors; use --error-limit to change this", errors.len());
+    }
+    Err(error)
+}
@@ -2,14 +2,14 @@ use anyhow::Result;
 use std::path::PathBuf;
 use std::process::Command;
 
-use super::build;
+use super::{build, FrontendArgs};
 
-pub fn execute(input: PathBuf, release: bool, args: Vec<String>) -> Result<()> {
+pub fn execute(input: PathBuf, release: bool, args: Vec<String>, frontend: FrontendArgs) -> Result<()> {
     // Build first
     let mut output = input.clone();
     output.set_extension("");
     
-    build::execute(input, release, Some(output.clone()), None, vec![], 0)?;
+    build::execute(input, release, Some(output.clone()), None, vec![], 0, frontend)?;
     
     // Run the binary
     println!("\n🚀 Running {}...\n", output.display());
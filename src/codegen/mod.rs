@@ -1,20 +1,64 @@
 use inkwell::context::Context;
 use inkwell::module::Module;
 use inkwell::builder::Builder;
-use inkwell::values::{FunctionValue, PointerValue, BasicValueEnum, BasicMetadataValueEnum, BasicValue};
-use inkwell::types::{BasicTypeEnum, BasicMetadataTypeEnum, BasicType};
+use inkwell::basic_block::BasicBlock;
+use inkwell::attributes::AttributeLoc;
+use inkwell::targets::TargetMachine;
+use inkwell::values::{ArrayValue, CallSiteValue, FunctionValue, GlobalValue, PointerValue, IntValue, BasicValueEnum, BasicMetadataValueEnum, BasicValue};
+use inkwell::types::{AnyType, BasicTypeEnum, BasicMetadataTypeEnum, BasicType, FunctionType, IntType, StructType};
 use inkwell::{AddressSpace, IntPredicate, FloatPredicate};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::hir::*;
-use crate::ast::{Type, BinaryOp, UnaryOp, Linkage};
+use crate::ast::{self, Type, BinaryOp, UnaryOp, Linkage, Span};
+use crate::error::FeraError;
+use crate::types::asm;
+use crate::types::attribute;
+use crate::types::constant::Constant;
+use crate::types::layout::{self, DataModel, Layout, RecordLayout};
+use crate::types::{function_signature, is_signed, pointee};
 
 pub struct CodeGen<'ctx> {
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
-    variables: HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    /// Local variables of the enclosing blocks, innermost last
+    scopes: Vec<HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>>,
+    /// Globals defined so far, with the type their storage is accessed as
+    globals: HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    /// Functions and globals marked `[[used]]`
+    used: Vec<GlobalValue<'ctx>>,
     current_function: Option<FunctionValue<'ctx>>,
+    records: HashMap<String, Record<'ctx>>,
+    /// Layouts of the records defined so far
+    layouts: HashMap<String, RecordLayout>,
+    /// Sizes and alignments of the target's scalar types
+    model: DataModel,
+    /// Signed overflow wraps instead of being undefined (`-fwrapv`)
+    wrapv: bool,
+    /// Loops and switches around the statement being generated,
+    /// innermost last
+    jump_targets: Vec<JumpTarget<'ctx>>,
+    /// Blocks of the labels in the current function, created by the
+    /// label or by the first `goto` that reaches it
+    labels: HashMap<String, BasicBlock<'ctx>>,
+}
+
+/// Where `break` and `continue` go inside a loop or switch
+struct JumpTarget<'ctx> {
+    break_block: BasicBlock<'ctx>,
+    /// None for a switch, which `continue` passes through
+    continue_block: Option<BasicBlock<'ctx>>,
+}
+
+/// A struct or union and the LLVM type it lowers to
+struct Record<'ctx> {
+    llvm_type: StructType<'ctx>,
+    fields: Vec<(String, Type)>,
+    /// Element of `llvm_type` each field is stored in; packed structs
+    /// have padding elements between fields
+    indices: Vec<u32>,
+    is_union: bool,
 }
 
 impl<'ctx> CodeGen<'ctx> {
@@ -26,8 +70,16 @@ impl<'ctx> CodeGen<'ctx> {
             context,
             module,
             builder,
-            variables: HashMap::new(),
+            scopes: Vec::new(),
+            globals: HashMap::new(),
+            used: Vec::new(),
             current_function: None,
+            records: HashMap::new(),
+            layouts: HashMap::new(),
+            model: DataModel::default(),
+            wrapv: false,
+            jump_targets: Vec::new(),
+            labels: HashMap::new(),
         };
         
         // Declare built-in functions
@@ -36,6 +88,20 @@ impl<'ctx> CodeGen<'ctx> {
         codegen
     }
     
+    /// Make signed arithmetic wrap on overflow rather than leaving it
+    /// undefined, so no `nsw` flags are emitted
+    pub fn set_wrapv(&mut self, wrapv: bool) {
+        self.wrapv = wrapv;
+    }
+    
+    /// Generate code for the target `machine` compiles to, with its
+    /// triple, data layout and pointer width
+    pub fn set_target(&mut self, machine: &TargetMachine) {
+        self.module.set_triple(&machine.get_triple());
+        self.module.set_data_layout(&machine.get_target_data().get_data_layout());
+        self.model = data_model(machine);
+    }
+    
     fn declare_builtins(&mut self) {
         let i32_type = self.context.i32_type();
         let i64_type = self.context.i64_type();
@@ -125,35 +191,177 @@ impl<'ctx> CodeGen<'ctx> {
         self.module.add_function("panic", void_type.fn_type(&[char_ptr_type.into()], false), None);
     }
     
-    pub fn codegen_program(&mut self, program: &HirProgram) -> Result<(), String> {
+    pub fn codegen_program(&mut self, program: &HirProgram) -> Result<(), FeraError> {
+        // Name every record first so fields can point to records defined later
+        for item in &program.items {
+            let (record, is_union) = match item {
+                HirItem::Struct(record) => (record, false),
+                HirItem::Union(record) => (record, true),
+                _ => continue,
+            };
+            let prefix = if is_union { "union" } else { "struct" };
+            let llvm_type = self.context.opaque_struct_type(&format!("{}.{}", prefix, record.name));
+            let indices = (0..record.fields.len() as u32).collect();
+            self.records.insert(record.name.clone(), Record { llvm_type, fields: record.fields.clone(), indices, is_union });
+        }
+        
+        // Then lay them all out, as functions and globals can use a
+        // record defined after them
+        let records: HashMap<&str, &HirRecord> = program.items.iter()
+            .filter_map(|item| match item {
+                HirItem::Struct(record) | HirItem::Union(record) => Some((record.name.as_str(), record)),
+                _ => None,
+            })
+            .collect();
+        let mut laid_out = HashSet::new();
+        for item in &program.items {
+            if let HirItem::Struct(record) | HirItem::Union(record) = item {
+                self.codegen_record_after_fields(record, &records, &mut laid_out)?;
+            }
+        }
+        
+        // Declare every function before any body, so calls and function
+        // pointers can refer to functions defined later or elsewhere
+        for item in &program.items {
+            if let HirItem::Function(f) = item {
+                self.declare_function(f)?;
+            }
+        }
+        
         for item in &program.items {
             match item {
                 HirItem::Function(f) => self.codegen_function(f)?,
                 HirItem::Global(g) => self.codegen_global(g)?,
+                HirItem::Struct(_) | HirItem::Union(_) => {}
             }
         }
+        self.codegen_used();
         Ok(())
     }
     
-    fn codegen_function(&mut self, func: &HirFunction) -> Result<(), String> {
-        let ret_type = self.llvm_type(&func.return_type)?;
+    /// Keep the functions and globals marked `[[used]]` alive through
+    /// optimization and linking by listing them in `llvm.used`
+    fn codegen_used(&mut self) {
+        if self.used.is_empty() {
+            return;
+        }
+        let ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+        let values: Vec<_> = self.used.iter().map(|global| global.as_pointer_value().const_cast(ptr_type)).collect();
+        let array = ptr_type.const_array(&values);
+        let used = self.module.add_global(array.get_type(), None, "llvm.used");
+        used.set_initializer(&array);
+        used.set_linkage(inkwell::module::Linkage::Appending);
+        used.set_section(Some("llvm.metadata"));
+    }
+    
+    /// Apply the attributes functions and globals share
+    fn apply_global_attributes(&mut self, global: GlobalValue<'ctx>, attributes: &[ast::Attribute]) {
+        if let Some(section) = attribute::find(attributes, "section").and_then(|a| a.args.first()) {
+            global.set_section(Some(section));
+        }
+        if attribute::find(attributes, "weak").is_some() {
+            global.set_linkage(inkwell::module::Linkage::WeakAny);
+        }
+        if attribute::find(attributes, "used").is_some() {
+            self.used.push(global);
+        }
+    }
+    
+    /// Lay out `record` after the records its fields contain by value,
+    /// whose layouts it's built from
+    fn codegen_record_after_fields<'a>(
+        &mut self,
+        record: &'a HirRecord,
+        records: &HashMap<&str, &'a HirRecord>,
+        laid_out: &mut HashSet<&'a str>,
+    ) -> Result<(), FeraError> {
+        if !laid_out.insert(&record.name) {
+            return Ok(());
+        }
+        for (_, field) in &record.fields {
+            let mut ty = field;
+            while let Type::Array(element, _) = ty {
+                ty = element;
+            }
+            if let Type::Struct(name) | Type::Union(name) = ty {
+                if let Some(field_record) = records.get(name.as_str()) {
+                    self.codegen_record_after_fields(field_record, records, laid_out)?;
+                }
+            }
+        }
+        self.codegen_record(record)
+    }
+    
+    /// Give a record its body, laid out like the equivalent C type. A
+    /// union becomes its most strictly aligned member padded with bytes
+    /// to the size of the largest member. Packed records become packed
+    /// LLVM structs with the padding their packing leaves made explicit.
+    fn codegen_record(&mut self, record: &HirRecord) -> Result<(), FeraError> {
+        let mut field_layouts = Vec::new();
+        let mut field_types = Vec::new();
+        for (name, ty) in &record.fields {
+            let incomplete = || FeraError::codegen(
+                format!("Field '{}' of '{}' has incomplete type '{}'", name, record.name, ty),
+                Some(record.span.clone()),
+            );
+            field_layouts.push(layout::layout_of(ty, &self.layouts, &self.model).ok_or_else(incomplete)?);
+            field_types.push(self.llvm_type(ty)?.ok_or_else(incomplete)?);
+        }
         
-        let param_types: Vec<BasicMetadataTypeEnum> = func.params
-            .iter()
-            .map(|(_, ty)| {
-                self.llvm_type(ty)?
-                    .ok_or_else(|| "Function parameters cannot be void".to_string())
-                    .map(|t| t.into())
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+        let Record { llvm_type, is_union, .. } = &self.records[&record.name];
+        let record_layout = layout::record_layout(&field_layouts, *is_union, record.packing);
+        let padding = |bytes: u64| self.context.i8_type().array_type(bytes as u32).as_basic_type_enum();
+        if let (Some(_), false) = (record.packing, *is_union) {
+            let mut body = Vec::new();
+            let mut indices = Vec::new();
+            let mut end = 0;
+            for ((field_type, field_layout), offset) in field_types.iter().zip(&field_layouts).zip(&record_layout.offsets) {
+                if *offset > end {
+                    body.push(padding(offset - end));
+                }
+                indices.push(body.len() as u32);
+                body.push(*field_type);
+                end = offset + field_layout.size;
+            }
+            if record_layout.layout.size > end {
+                body.push(padding(record_layout.layout.size - end));
+            }
+            llvm_type.set_body(&body, true);
+            if let Some(record) = self.records.get_mut(&record.name) {
+                record.indices = indices;
+            }
+        } else if *is_union {
+            let mut body = Vec::new();
+            // Ties go to the first member, like clang
+            let widest = (0..field_layouts.len()).rev()
+                .max_by_key(|&i| (field_layouts[i].align, field_layouts[i].size));
+            let mut size = 0;
+            if let Some(i) = widest {
+                body.push(field_types[i]);
+                size = field_layouts[i].size;
+            }
+            if record_layout.layout.size > size {
+                body.push(padding(record_layout.layout.size - size));
+            }
+            llvm_type.set_body(&body, record.packing.is_some());
+        } else {
+            llvm_type.set_body(&field_types, false);
+        }
         
-        let fn_type = match ret_type {
-            Some(ty) => ty.fn_type(&param_types, false),
-            None => self.context.void_type().fn_type(&param_types, false),
+        self.layouts.insert(record.name.clone(), record_layout);
+        Ok(())
+    }
+    
+    fn declare_function(&mut self, func: &HirFunction) -> Result<(), FeraError> {
+        let function = match self.module.get_function(&func.name) {
+            Some(function) => function,
+            None => {
+                let params: Vec<_> = func.params.iter().map(|(_, ty)| ty.clone()).collect();
+                let fn_type = self.llvm_fn_type(&func.return_type, &params, func.is_variadic)?;
+                self.module.add_function(&func.name, fn_type, None)
+            }
         };
         
-        let function = self.module.add_function(&func.name, fn_type, None);
-        
         // Set linkage
         match func.linkage {
             Linkage::Export => {
@@ -164,38 +372,61 @@ impl<'ctx> CodeGen<'ctx> {
             }
         }
         
+        for (name, llvm_name) in FUNCTION_ATTRIBUTES {
+            if attribute::find(&func.attributes, name).is_some() {
+                let kind = inkwell::attributes::Attribute::get_named_enum_kind_id(llvm_name);
+                function.add_attribute(AttributeLoc::Function, self.context.create_enum_attribute(kind, 0));
+            }
+        }
+        self.apply_global_attributes(function.as_global_value(), &func.attributes);
+        Ok(())
+    }
+    
+    fn codegen_function(&mut self, func: &HirFunction) -> Result<(), FeraError> {
+        let Some(body) = &func.body else { return Ok(()) };
+        let function = self.module.get_function(&func.name)
+            .ok_or_else(|| FeraError::codegen(format!("Unknown function: {}", func.name), Some(func.span.clone())))?;
+        let ret_type = self.llvm_type(&func.return_type)?;
+        
         let entry = self.context.append_basic_block(function, "entry");
         self.builder.position_at_end(entry);
         
         self.current_function = Some(function);
-        self.variables.clear();
+        self.scopes = vec![HashMap::new()];
+        self.labels.clear();
         
         // Allocate and store parameters
         for (i, (name, ty)) in func.params.iter().enumerate() {
             let param_value = function.get_nth_param(i as u32)
-                .ok_or("Parameter index out of range")?;
+                .ok_or_else(|| FeraError::codegen("Parameter index out of range", Some(func.span.clone())))?;
             
             let llvm_ty = self.llvm_type(ty)?
-                .ok_or("Cannot create pointer to void type")?;
+                .ok_or_else(|| FeraError::codegen("Cannot create pointer to void type", Some(func.span.clone())))?;
             let alloca = self.builder.build_alloca(llvm_ty, name)
-                .map_err(|e| format!("Failed to build alloca: {:?}", e))?;
+                .map_err(|e| llvm_error("Failed to build alloca", e))?;
             
             self.builder.build_store(alloca, param_value)
-                .map_err(|e| format!("Failed to store parameter: {:?}", e))?;
+                .map_err(|e| llvm_error("Failed to store parameter", e))?;
             
-            self.variables.insert(name.clone(), (alloca, llvm_ty));
+            self.declare(name, alloca, llvm_ty);
         }
         
         // Generate function body
-        self.codegen_block(&func.body)?;
+        self.codegen_block(body)?;
         
         // Add implicit return if missing
-        if let Some(block) = function.get_last_basic_block() {
+        if let Some(block) = self.builder.get_insert_block() {
             if block.get_terminator().is_none() {
                 if func.return_type == Type::Void {
                     self.builder.build_return(None)
-                        .map_err(|e| format!("Failed to build return: {:?}", e))?;
+                        .map_err(|e| llvm_error("Failed to build return", e))?;
+                } else if func.name != "main" {
+                    // Falling off the end of a function that returns a
+                    // value is undefined; the missing-return lint warns
+                    self.builder.build_unreachable()
+                        .map_err(|e| llvm_error("Failed to build unreachable", e))?;
                 } else {
+                    // main returns 0 when it reaches its end
                     // Return zero/null as default
                     let default_val = match ret_type {
                         Some(BasicTypeEnum::IntType(int_ty)) => {
@@ -207,10 +438,10 @@ impl<'ctx> CodeGen<'ctx> {
                         Some(BasicTypeEnum::PointerType(ptr_ty)) => {
                             ptr_ty.const_null().as_basic_value_enum()
                         }
-                        _ => return Err("Cannot create default return value".to_string()),
+                        _ => return Err(FeraError::codegen("Cannot create default return value", Some(func.span.clone()))),
                     };
                     self.builder.build_return(Some(&default_val))
-                        .map_err(|e| format!("Failed to build return: {:?}", e))?;
+                        .map_err(|e| llvm_error("Failed to build return", e))?;
                 }
             }
         }
@@ -218,11 +449,23 @@ impl<'ctx> CodeGen<'ctx> {
         Ok(())
     }
     
-    fn codegen_global(&mut self, global: &HirGlobal) -> Result<(), String> {
+    fn codegen_global(&mut self, global: &HirGlobal) -> Result<(), FeraError> {
         let llvm_ty = self.llvm_type(&global.ty)?
-            .ok_or("Cannot create global of void type")?;
+            .ok_or_else(|| FeraError::codegen("Cannot create global of void type", Some(global.span.clone())))?;
         
-        let global_var = self.module.add_global(llvm_ty, None, &global.name);
+        // Globals without an initializer start out zeroed, as in C
+        let init = match &global.init {
+            Some(constant) => self.const_value(constant, &global.ty, &global.span)?,
+            None => llvm_ty.const_zero(),
+        };
+        // A union initialized through a member narrower than the one its
+        // type is built from gets the type of the initializer instead
+        let global_var = self.module.add_global(init.get_type(), None, &global.name);
+        global_var.set_initializer(&init);
+        if let Some(layout) = layout::layout_of(&global.ty, &self.layouts, &self.model) {
+            global_var.set_alignment(layout.align as u32);
+        }
+        self.globals.insert(global.name.clone(), (global_var.as_pointer_value(), llvm_ty));
         
         match global.linkage {
             Linkage::Export => {
@@ -233,287 +476,1120 @@ impl<'ctx> CodeGen<'ctx> {
             }
         }
         
-        if let Some(init) = &global.init {
-            let init_val = self.codegen_expr(init)?;
-            if let BasicValueEnum::IntValue(int_val) = init_val {
-                global_var.set_initializer(&int_val);
-            }
-        }
+        self.apply_global_attributes(global_var, &global.attributes);
         
         Ok(())
     }
     
-    fn codegen_block(&mut self, block: &HirBlock) -> Result<(), String> {
+    /// The LLVM constant for the value of a global of type `ty`
+    fn const_value(&self, constant: &Constant, ty: &Type, span: &Span) -> Result<BasicValueEnum<'ctx>, FeraError> {
+        let llvm_ty = self.llvm_type(ty)?
+            .ok_or_else(|| FeraError::codegen("Cannot create constant of void type", Some(span.clone())))?;
+        let value = match (constant, ty) {
+            (Constant::Int(value), _) => llvm_ty.into_int_type().const_int(*value as u64, is_signed(ty)).as_basic_value_enum(),
+            (Constant::Float(value), _) => llvm_ty.into_float_type().const_float(*value).as_basic_value_enum(),
+            (Constant::String(value), Type::Array(_, Some(len))) => {
+                let mut bytes = value.as_bytes().to_vec();
+                bytes.resize(*len, 0);
+                self.context.const_string(&bytes, false).as_basic_value_enum()
+            }
+            (Constant::String(value), _) => self.string_constant(value).as_basic_value_enum(),
+            (Constant::Address(name), _) => self.globals.get(name)
+                .map(|(ptr, _)| *ptr)
+                .or_else(|| self.module.get_function(name).map(|f| f.as_global_value().as_pointer_value()))
+                .ok_or_else(|| FeraError::codegen(
+                    format!("Global '{}' is used before its declaration", name),
+                    Some(span.clone()),
+                ))?
+                .as_basic_value_enum(),
+            (Constant::Null | Constant::Zero, _) => llvm_ty.const_zero(),
+            (Constant::Aggregate(values), Type::Array(element, _)) => {
+                let values = values.iter()
+                    .map(|value| self.const_value(value, element, span))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let element_ty = llvm_ty.into_array_type().get_element_type();
+                const_array(element_ty, &values)
+                    .ok_or_else(|| FeraError::codegen("Cannot initialize an array of unions through a narrower member", Some(span.clone())))?
+                    .as_basic_value_enum()
+            }
+            (Constant::Aggregate(values), Type::Struct(name) | Type::Union(name)) => {
+                let record = self.records.get(name)
+                    .ok_or_else(|| FeraError::codegen(format!("Unknown type '{}'", ty), Some(span.clone())))?;
+                let values = values.iter().zip(&record.fields)
+                    .map(|(value, (_, field))| self.const_value(value, field, span))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let field_types = record.llvm_type.get_field_types();
+                let mut body: Vec<_> = field_types.iter().map(|ty| ty.const_zero()).collect();
+                for (value, index) in values.iter().zip(&record.indices) {
+                    body[*index as usize] = *value;
+                }
+                if record.is_union {
+                    self.const_union(name, values, span)?
+                } else if body.iter().zip(&field_types).all(|(value, ty)| value.get_type() == *ty) {
+                    record.llvm_type.const_named_struct(&body).as_basic_value_enum()
+                } else {
+                    return Err(FeraError::codegen(
+                        "Cannot initialize a union inside a struct through a narrower member",
+                        Some(span.clone()),
+                    ));
+                }
+            }
+            (Constant::Aggregate(_), _) => {
+                return Err(FeraError::codegen(format!("Cannot initialize '{}' with a braced list", ty), Some(span.clone())));
+            }
+        };
+        Ok(value)
+    }
+    
+    /// A union constant initialized through its first member, padded with
+    /// zero bytes to the size of the union
+    fn const_union(&self, name: &str, values: Vec<BasicValueEnum<'ctx>>, span: &Span) -> Result<BasicValueEnum<'ctx>, FeraError> {
+        let record = &self.records[name];
+        let Some(first) = values.into_iter().next() else {
+            return Ok(record.llvm_type.const_zero().as_basic_value_enum());
+        };
+        let size = self.layouts.get(name).map_or(0, |layout| layout.layout.size);
+        let first_size = layout::layout_of(&record.fields[0].1, &self.layouts, &self.model)
+            .ok_or_else(|| FeraError::codegen(format!("Union '{}' has an incomplete member", name), Some(span.clone())))?
+            .size;
+        if record.llvm_type.get_field_type_at_index(0) == Some(first.get_type()) {
+            let mut body = vec![first];
+            if let Some(padding) = record.llvm_type.get_field_type_at_index(1) {
+                body.push(padding.const_zero());
+            }
+            return Ok(record.llvm_type.const_named_struct(&body).as_basic_value_enum());
+        }
+        let mut body = vec![first];
+        if size > first_size {
+            body.push(self.context.i8_type().array_type((size - first_size) as u32).const_zero().as_basic_value_enum());
+        }
+        Ok(self.context.const_struct(&body, false).as_basic_value_enum())
+    }
+    
+    /// A private global holding `value` with a terminating null
+    fn string_constant(&self, value: &str) -> PointerValue<'ctx> {
+        let string_val = self.context.const_string(value.as_bytes(), true);
+        let global = self.module.add_global(string_val.get_type(), None, ".str");
+        global.set_initializer(&string_val);
+        global.set_constant(true);
+        global.set_linkage(inkwell::module::Linkage::Private);
+        global.as_pointer_value()
+    }
+    
+    fn codegen_block(&mut self, block: &HirBlock) -> Result<(), FeraError> {
+        self.scopes.push(HashMap::new());
         for stmt in &block.stmts {
             self.codegen_stmt(stmt)?;
         }
+        self.scopes.pop();
         Ok(())
     }
     
-    fn codegen_stmt(&mut self, stmt: &HirStmt) -> Result<(), String> {
-        match stmt {
-            HirStmt::Let(name, ty, init) => {
+    fn codegen_stmt(&mut self, stmt: &HirStmt) -> Result<(), FeraError> {
+        // Statements after a jump can't be reached from it; they get a
+        // block of their own so nothing follows the terminator
+        let func = self.current_function
+            .ok_or_else(|| FeraError::codegen("No current function", Some(stmt.span.clone())))?;
+        let is_label = matches!(stmt.kind, HirStmtKind::Label(_));
+        if !is_label && self.insert_block(&stmt.span)?.get_terminator().is_some() {
+            let block = self.context.append_basic_block(func, "unreachable");
+            self.builder.position_at_end(block);
+        }
+        
+        match &stmt.kind {
+            HirStmtKind::Let(name, ty, init) => {
                 let llvm_ty = self.llvm_type(ty)?
-                    .ok_or("Cannot create variable of void type")?;
+                    .ok_or_else(|| FeraError::codegen("Cannot create variable of void type", Some(stmt.span.clone())))?;
                 
-                let alloca = self.builder.build_alloca(llvm_ty, name)
-                    .map_err(|e| format!("Failed to build alloca: {:?}", e))?;
+                // In the entry block, so the slot exists even when a jump
+                // skips the declaration
+                let alloca = self.build_entry_alloca(llvm_ty, name)?;
                 
                 if let Some(init_expr) = init {
                     let init_val = self.codegen_expr(init_expr)?;
                     self.builder.build_store(alloca, init_val)
-                        .map_err(|e| format!("Failed to store: {:?}", e))?;
+                        .map_err(|e| llvm_error("Failed to store", e))?;
                 }
                 
-                self.variables.insert(name.clone(), (alloca, llvm_ty));
+                self.declare(name, alloca, llvm_ty);
                 Ok(())
             }
-            HirStmt::Expr(expr) => {
+            HirStmtKind::Expr(expr) => {
                 self.codegen_expr(expr)?;
                 Ok(())
             }
-            HirStmt::Return(expr) => {
+            HirStmtKind::VoidCall(call) => {
+                self.codegen_call(call, &stmt.span)?;
+                Ok(())
+            }
+            HirStmtKind::Return(expr) => {
                 if let Some(ret_expr) = expr {
                     let ret_val = self.codegen_expr(ret_expr)?;
                     self.builder.build_return(Some(&ret_val))
-                        .map_err(|e| format!("Failed to build return: {:?}", e))?;
+                        .map_err(|e| llvm_error("Failed to build return", e))?;
                 } else {
                     self.builder.build_return(None)
-                        .map_err(|e| format!("Failed to build return: {:?}", e))?;
+                        .map_err(|e| llvm_error("Failed to build return", e))?;
                 }
                 Ok(())
             }
-            HirStmt::If(cond, then_stmt, else_stmt) => {
-                let func = self.current_function.ok_or("No current function")?;
-                
+            HirStmtKind::If(cond, then_stmt, else_stmt) => {
                 let then_block = self.context.append_basic_block(func, "then");
                 let else_block = self.context.append_basic_block(func, "else");
                 let merge_block = self.context.append_basic_block(func, "ifcont");
                 
-                let cond_val = self.codegen_expr(cond)?;
-                let cond_int = if let BasicValueEnum::IntValue(iv) = cond_val {
-                    iv
-                } else {
-                    return Err("Condition must be an integer".to_string());
-                };
+                let cond_int = self.codegen_condition(cond)?;
                 
                 self.builder.build_conditional_branch(cond_int, then_block, else_block)
-                    .map_err(|e| format!("Failed to build conditional branch: {:?}", e))?;
+                    .map_err(|e| llvm_error("Failed to build conditional branch", e))?;
                 
                 // Then block
                 self.builder.position_at_end(then_block);
                 self.codegen_stmt(then_stmt)?;
-                if then_block.get_terminator().is_none() {
-                    self.builder.build_unconditional_branch(merge_block)
-                        .map_err(|e| format!("Failed to build branch: {:?}", e))?;
-                }
+                self.branch_if_open(merge_block)?;
                 
                 // Else block
                 self.builder.position_at_end(else_block);
                 if let Some(else_s) = else_stmt {
                     self.codegen_stmt(else_s)?;
                 }
-                if else_block.get_terminator().is_none() {
-                    self.builder.build_unconditional_branch(merge_block)
-                        .map_err(|e| format!("Failed to build branch: {:?}", e))?;
-                }
+                self.branch_if_open(merge_block)?;
                 
                 self.builder.position_at_end(merge_block);
                 Ok(())
             }
-            HirStmt::While(cond, body) => {
-                let func = self.current_function.ok_or("No current function")?;
-                
+            HirStmtKind::While(cond, body) => {
                 let cond_block = self.context.append_basic_block(func, "while.cond");
                 let body_block = self.context.append_basic_block(func, "while.body");
                 let end_block = self.context.append_basic_block(func, "while.end");
                 
                 self.builder.build_unconditional_branch(cond_block)
-                    .map_err(|e| format!("Failed to build branch: {:?}", e))?;
+                    .map_err(|e| llvm_error("Failed to build branch", e))?;
                 
                 self.builder.position_at_end(cond_block);
-                let cond_val = self.codegen_expr(cond)?;
-                let cond_int = if let BasicValueEnum::IntValue(iv) = cond_val {
-                    iv
-                } else {
-                    return Err("Condition must be an integer".to_string());
-                };
+                let cond_int = self.codegen_condition(cond)?;
+                
+                self.builder.build_conditional_branch(cond_int, body_block, end_block)
+                    .map_err(|e| llvm_error("Failed to build conditional branch", e))?;
+                
+                self.builder.position_at_end(body_block);
+                self.codegen_loop_body(body, end_block, cond_block)?;
+                self.branch_if_open(cond_block)?;
+                
+                self.builder.position_at_end(end_block);
+                Ok(())
+            }
+            HirStmtKind::DoWhile(body, cond) => {
+                let body_block = self.context.append_basic_block(func, "do.body");
+                let cond_block = self.context.append_basic_block(func, "do.cond");
+                let end_block = self.context.append_basic_block(func, "do.end");
+                
+                self.builder.build_unconditional_branch(body_block)
+                    .map_err(|e| llvm_error("Failed to build branch", e))?;
+                
+                self.builder.position_at_end(body_block);
+                self.codegen_loop_body(body, end_block, cond_block)?;
+                self.branch_if_open(cond_block)?;
                 
+                self.builder.position_at_end(cond_block);
+                let cond_int = self.codegen_condition(cond)?;
                 self.builder.build_conditional_branch(cond_int, body_block, end_block)
-                    .map_err(|e| format!("Failed to build conditional branch: {:?}", e))?;
+                    .map_err(|e| llvm_error("Failed to build conditional branch", e))?;
+                
+                self.builder.position_at_end(end_block);
+                Ok(())
+            }
+            HirStmtKind::For(init, cond, increment, body) => {
+                // The loop variable is only visible inside the loop
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.codegen_stmt(init)?;
+                }
+                
+                let cond_block = self.context.append_basic_block(func, "for.cond");
+                let body_block = self.context.append_basic_block(func, "for.body");
+                let inc_block = self.context.append_basic_block(func, "for.inc");
+                let end_block = self.context.append_basic_block(func, "for.end");
+                
+                self.builder.build_unconditional_branch(cond_block)
+                    .map_err(|e| llvm_error("Failed to build branch", e))?;
+                
+                self.builder.position_at_end(cond_block);
+                match cond {
+                    Some(cond) => {
+                        let cond_int = self.codegen_condition(cond)?;
+                        self.builder.build_conditional_branch(cond_int, body_block, end_block)
+                    }
+                    None => self.builder.build_unconditional_branch(body_block),
+                }
+                .map_err(|e| llvm_error("Failed to build branch", e))?;
                 
                 self.builder.position_at_end(body_block);
-                self.codegen_stmt(body)?;
-                if body_block.get_terminator().is_none() {
-                    self.builder.build_unconditional_branch(cond_block)
-                        .map_err(|e| format!("Failed to build branch: {:?}", e))?;
+                self.codegen_loop_body(body, end_block, inc_block)?;
+                self.branch_if_open(inc_block)?;
+                
+                self.builder.position_at_end(inc_block);
+                if let Some(increment) = increment {
+                    self.codegen_stmt(increment)?;
                 }
+                self.builder.build_unconditional_branch(cond_block)
+                    .map_err(|e| llvm_error("Failed to build branch", e))?;
+                self.scopes.pop();
                 
                 self.builder.position_at_end(end_block);
                 Ok(())
             }
-            HirStmt::Block(block) => self.codegen_block(block),
+            HirStmtKind::Switch(value, cases) => self.codegen_switch(value, cases, &stmt.span),
+            HirStmtKind::Break => {
+                let target = self.jump_targets.last()
+                    .ok_or_else(|| FeraError::codegen("'break' outside of a loop or switch", Some(stmt.span.clone())))?;
+                self.builder.build_unconditional_branch(target.break_block)
+                    .map_err(|e| llvm_error("Failed to build branch", e))?;
+                Ok(())
+            }
+            HirStmtKind::Continue => {
+                let target = self.jump_targets.iter().rev()
+                    .find_map(|target| target.continue_block)
+                    .ok_or_else(|| FeraError::codegen("'continue' outside of a loop", Some(stmt.span.clone())))?;
+                self.builder.build_unconditional_branch(target)
+                    .map_err(|e| llvm_error("Failed to build branch", e))?;
+                Ok(())
+            }
+            HirStmtKind::Label(name) => {
+                let block = self.label_block(name, func);
+                self.branch_if_open(block)?;
+                self.builder.position_at_end(block);
+                Ok(())
+            }
+            HirStmtKind::Goto(name) => {
+                let block = self.label_block(name, func);
+                self.builder.build_unconditional_branch(block)
+                    .map_err(|e| llvm_error("Failed to build branch", e))?;
+                Ok(())
+            }
+            HirStmtKind::Block(block) => self.codegen_block(block),
+            HirStmtKind::Asm(inline_asm) => self.codegen_asm(inline_asm),
+        }
+    }
+    
+    /// Emit an asm statement as a call to LLVM inline asm. Register
+    /// outputs are its return values, stored to their lvalues afterwards;
+    /// memory operands are passed by address.
+    fn codegen_asm(&mut self, inline_asm: &HirAsm) -> Result<(), FeraError> {
+        let mut constraints = Vec::new();
+        let mut args: Vec<BasicMetadataValueEnum> = Vec::new();
+        let mut arg_types: Vec<BasicMetadataTypeEnum> = Vec::new();
+        // Arguments passed by address and the type they point to
+        let mut indirect = Vec::new();
+        let mut results = Vec::new();
+        let mut read_write = Vec::new();
+        
+        for (index, output) in inline_asm.outputs.iter().enumerate() {
+            let (ptr, ty) = self.codegen_lvalue(&output.expr)?;
+            constraints.push(output.constraint.llvm(true));
+            if output.constraint.kind == asm::Kind::Memory {
+                indirect.push((args.len(), ty));
+                arg_types.push(ptr.get_type().into());
+                args.push(ptr.into());
+            } else {
+                if output.constraint.read_write {
+                    read_write.push((index, ptr, ty));
+                }
+                results.push((ptr, ty));
+            }
+        }
+        for input in &inline_asm.inputs {
+            constraints.push(input.constraint.llvm(false));
+            if input.constraint.kind == asm::Kind::Memory {
+                let (ptr, ty) = self.codegen_lvalue(&input.expr)?;
+                indirect.push((args.len(), ty));
+                arg_types.push(ptr.get_type().into());
+                args.push(ptr.into());
+            } else {
+                let value = self.codegen_expr(&input.expr)?;
+                arg_types.push(value.get_type().into());
+                args.push(value.into());
+            }
+        }
+        // A `+` register output is read through an input tied to it
+        for (index, ptr, ty) in read_write {
+            constraints.push(index.to_string());
+            let value = self.builder.build_load(ty, ptr, "asm.in")
+                .map_err(|e| llvm_error("Failed to build load", e))?;
+            arg_types.push(ty.into());
+            args.push(value.into());
+        }
+        for clobber in &inline_asm.clobbers {
+            constraints.push(format!("~{{{}}}", clobber.trim_start_matches('%')));
+        }
+        
+        let fn_type = match results.as_slice() {
+            [] => self.context.void_type().fn_type(&arg_types, false),
+            [(_, ty)] => ty.fn_type(&arg_types, false),
+            _ => {
+                let types: Vec<_> = results.iter().map(|(_, ty)| *ty).collect();
+                self.context.struct_type(&types, false).fn_type(&arg_types, false)
+            }
+        };
+        let asm_fn = self.context.create_inline_asm(
+            fn_type,
+            inline_asm.template.clone(),
+            constraints.join(","),
+            inline_asm.side_effects,
+            false,
+            None,
+            false,
+        );
+        let call = self.builder.build_indirect_call(fn_type, asm_fn, &args, "asm")
+            .map_err(|e| llvm_error("Failed to build inline asm", e))?;
+        let element_type = inkwell::attributes::Attribute::get_named_enum_kind_id("elementtype");
+        for (index, ty) in indirect {
+            let attribute = self.context.create_type_attribute(element_type, ty.as_any_type_enum());
+            call.add_attribute(AttributeLoc::Param(index as u32), attribute);
+        }
+        
+        let Some(value) = call.try_as_basic_value().left() else { return Ok(()) };
+        if let [(ptr, _)] = results.as_slice() {
+            self.builder.build_store(*ptr, value).map_err(|e| llvm_error("Failed to build store", e))?;
+            return Ok(());
+        }
+        for (index, (ptr, _)) in results.iter().enumerate() {
+            let field = self.builder.build_extract_value(value.into_struct_value(), index as u32, "asm.out")
+                .map_err(|e| llvm_error("Failed to build extract", e))?;
+            self.builder.build_store(*ptr, field).map_err(|e| llvm_error("Failed to build store", e))?;
         }
+        Ok(())
     }
     
-    fn codegen_expr(&mut self, expr: &HirExpr) -> Result<BasicValueEnum<'ctx>, String> {
-        match expr {
-            HirExpr::IntLiteral(val) => {
-                Ok(self.context.i32_type().const_int(*val as u64, true).as_basic_value_enum())
+    fn label_block(&mut self, name: &str, func: FunctionValue<'ctx>) -> BasicBlock<'ctx> {
+        *self.labels.entry(name.to_string())
+            .or_insert_with(|| self.context.append_basic_block(func, name))
+    }
+    
+    /// Generate a loop body in which `break` and `continue` jump to the
+    /// given blocks
+    fn codegen_loop_body(
+        &mut self,
+        body: &HirStmt,
+        break_block: BasicBlock<'ctx>,
+        continue_block: BasicBlock<'ctx>,
+    ) -> Result<(), FeraError> {
+        self.jump_targets.push(JumpTarget { break_block, continue_block: Some(continue_block) });
+        self.codegen_stmt(body)?;
+        self.jump_targets.pop();
+        Ok(())
+    }
+    
+    /// Generate a switch. Each label gets a block that falls through into
+    /// the next one. Dense case values become an LLVM `switch`, which can
+    /// be lowered to a jump table; sparse ones are compared one by one.
+    fn codegen_switch(&mut self, value: &HirExpr, cases: &[HirSwitchCase], span: &Span) -> Result<(), FeraError> {
+        let func = self.current_function
+            .ok_or_else(|| FeraError::codegen("No current function", Some(span.clone())))?;
+        let BasicValueEnum::IntValue(value_int) = self.codegen_expr(value)? else {
+            return Err(FeraError::codegen("Switch value must be an integer", Some(value.span.clone())));
+        };
+        
+        let case_blocks: Vec<_> = cases.iter()
+            .map(|case| {
+                let name = if case.value.is_some() { "switch.case" } else { "switch.default" };
+                self.context.append_basic_block(func, name)
+            })
+            .collect();
+        let end_block = self.context.append_basic_block(func, "switch.end");
+        
+        let default_block = cases.iter().position(|case| case.value.is_none())
+            .map_or(end_block, |i| case_blocks[i]);
+        let int_ty = value_int.get_type();
+        let signed = is_signed(&value.ty);
+        let targets: Vec<_> = cases.iter().zip(&case_blocks)
+            .filter_map(|(case, block)| Some((int_ty.const_int(case.value? as u64, signed), *block)))
+            .collect();
+        
+        let values = cases.iter().filter_map(|case| case.value);
+        let range = values.clone().max().zip(values.min()).map_or(0, |(max, min)| max.abs_diff(min));
+        if range < 3 * targets.len() as u64 {
+            self.builder.build_switch(value_int, default_block, &targets)
+                .map_err(|e| llvm_error("Failed to build switch", e))?;
+        } else {
+            for (case_value, block) in targets {
+                let next = self.context.append_basic_block(func, "switch.next");
+                let matches = self.builder.build_int_compare(IntPredicate::EQ, value_int, case_value, "case")
+                    .map_err(|e| llvm_error("Failed to build comparison", e))?;
+                self.builder.build_conditional_branch(matches, block, next)
+                    .map_err(|e| llvm_error("Failed to build conditional branch", e))?;
+                self.builder.position_at_end(next);
+            }
+            self.builder.build_unconditional_branch(default_block)
+                .map_err(|e| llvm_error("Failed to build branch", e))?;
+        }
+        
+        self.jump_targets.push(JumpTarget { break_block: end_block, continue_block: None });
+        self.scopes.push(HashMap::new());
+        for (i, case) in cases.iter().enumerate() {
+            self.builder.position_at_end(case_blocks[i]);
+            for stmt in &case.stmts {
+                self.codegen_stmt(stmt)?;
             }
-            HirExpr::FloatLiteral(val) => {
+            // Fall through into the next label's statements
+            self.branch_if_open(case_blocks.get(i + 1).copied().unwrap_or(end_block))?;
+        }
+        self.scopes.pop();
+        self.jump_targets.pop();
+        
+        self.builder.position_at_end(end_block);
+        Ok(())
+    }
+    
+    fn codegen_expr(&mut self, expr: &HirExpr) -> Result<BasicValueEnum<'ctx>, FeraError> {
+        // An array used as a value decays to a pointer to its first element
+        if matches!(expr.ty, Type::Array(..)) && is_lvalue(expr) {
+            return Ok(self.codegen_address(expr)?.as_basic_value_enum());
+        }
+        
+        match &expr.kind {
+            HirExprKind::IntLiteral(val) => match self.llvm_type(&expr.ty)? {
+                Some(BasicTypeEnum::IntType(int_ty)) => {
+                    Ok(int_ty.const_int(*val as u64, is_signed(&expr.ty)).as_basic_value_enum())
+                }
+                // Only the null constant is typed as a pointer
+                Some(BasicTypeEnum::PointerType(ptr_ty)) => Ok(ptr_ty.const_null().as_basic_value_enum()),
+                _ => Err(FeraError::codegen(format!("Integer literal of type '{}'", expr.ty), Some(expr.span.clone()))),
+            },
+            HirExprKind::FloatLiteral(val) => {
                 Ok(self.context.f64_type().const_float(*val).as_basic_value_enum())
             }
-            HirExpr::StringLiteral(s) => {
-                let string_val = self.context.const_string(s.as_bytes(), true);
-                let global = self.module.add_global(string_val.get_type(), None, ".str");
-                global.set_initializer(&string_val);
-                global.set_constant(true);
-                global.set_linkage(inkwell::module::Linkage::Private);
+            HirExprKind::StringLiteral(s) => Ok(self.string_constant(s).as_basic_value_enum()),
+            HirExprKind::BoolLiteral(val) => {
+                Ok(self.context.bool_type().const_int(*val as u64, false).as_basic_value_enum())
+            }
+            HirExprKind::Variable(name) => {
+                let (ptr, ty) = self.variable(name, &expr.span)?;
                 
-                Ok(global.as_pointer_value().as_basic_value_enum())
+                self.builder.build_load(ty, ptr, name)
+                    .map_err(|e| llvm_error("Failed to load variable", e))
             }
-            HirExpr::BoolLiteral(val) => {
-                Ok(self.context.bool_type().const_int(*val as u64, false).as_basic_value_enum())
+            HirExprKind::Binary(op @ (BinaryOp::LogicalAnd | BinaryOp::LogicalOr), left, right) => {
+                let func = self.current_function
+                    .ok_or_else(|| FeraError::codegen("No current function", Some(expr.span.clone())))?;
+                let is_and = *op == BinaryOp::LogicalAnd;
+                let rhs_block = self.context.append_basic_block(func, if is_and { "and.rhs" } else { "or.rhs" });
+                let end_block = self.context.append_basic_block(func, if is_and { "and.end" } else { "or.end" });
+                
+                // Only evaluate the right operand when the left doesn't decide the result
+                let lhs = self.codegen_condition(left)?;
+                let lhs_block = self.insert_block(&expr.span)?;
+                if is_and {
+                    self.builder.build_conditional_branch(lhs, rhs_block, end_block)
+                } else {
+                    self.builder.build_conditional_branch(lhs, end_block, rhs_block)
+                }.map_err(|e| llvm_error("Failed to build conditional branch", e))?;
+                
+                self.builder.position_at_end(rhs_block);
+                let rhs = self.codegen_condition(right)?;
+                let rhs_end = self.insert_block(&expr.span)?;
+                self.builder.build_unconditional_branch(end_block)
+                    .map_err(|e| llvm_error("Failed to build branch", e))?;
+                
+                self.builder.position_at_end(end_block);
+                let bool_type = self.context.bool_type();
+                let phi = self.builder.build_phi(bool_type, if is_and { "and" } else { "or" })
+                    .map_err(|e| llvm_error("Failed to build phi", e))?;
+                let short_circuit = bool_type.const_int(!is_and as u64, false);
+                phi.add_incoming(&[(&short_circuit, lhs_block), (&rhs, rhs_end)]);
+                Ok(phi.as_basic_value())
             }
-            HirExpr::Variable(name) => {
-                let (ptr, ty) = self.variables.get(name)
-                    .ok_or_else(|| format!("Unknown variable: {}", name))?;
+            HirExprKind::Ternary(cond, then_expr, else_expr) => {
+                let func = self.current_function
+                    .ok_or_else(|| FeraError::codegen("No current function", Some(expr.span.clone())))?;
+                let then_block = self.context.append_basic_block(func, "cond.then");
+                let else_block = self.context.append_basic_block(func, "cond.else");
+                let merge_block = self.context.append_basic_block(func, "cond.end");
                 
-                self.builder.build_load(*ty, *ptr, name)
-                    .map_err(|e| format!("Failed to load variable: {:?}", e))
+                let cond_int = self.codegen_condition(cond)?;
+                self.builder.build_conditional_branch(cond_int, then_block, else_block)
+                    .map_err(|e| llvm_error("Failed to build conditional branch", e))?;
+                
+                self.builder.position_at_end(then_block);
+                let then_val = self.codegen_expr(then_expr)?;
+                let then_end = self.insert_block(&expr.span)?;
+                
+                self.builder.position_at_end(else_block);
+                let else_val = self.codegen_expr(else_expr)?;
+                let else_end = self.insert_block(&expr.span)?;
+                
+                // Both arms were converted to the result type, so they
+                // can meet in a phi
+                for block in [then_end, else_end] {
+                    self.builder.position_at_end(block);
+                    self.builder.build_unconditional_branch(merge_block)
+                        .map_err(|e| llvm_error("Failed to build branch", e))?;
+                }
+                
+                self.builder.position_at_end(merge_block);
+                let phi = self.builder.build_phi(then_val.get_type(), "cond")
+                    .map_err(|e| llvm_error("Failed to build phi", e))?;
+                phi.add_incoming(&[(&then_val, then_end), (&else_val, else_end)]);
+                Ok(phi.as_basic_value())
             }
-            HirExpr::Binary(op, left, right) => {
+            HirExprKind::Binary(op @ (BinaryOp::Add | BinaryOp::Sub), left, right)
+                if pointee(&left.ty).is_some() || pointee(&right.ty).is_some() =>
+            {
                 let lhs = self.codegen_expr(left)?;
                 let rhs = self.codegen_expr(right)?;
-                
                 match (lhs, rhs) {
-                    (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
-                        let result = match op {
-                            BinaryOp::Add => self.builder.build_int_add(l, r, "add"),
-                            BinaryOp::Sub => self.builder.build_int_sub(l, r, "sub"),
-                            BinaryOp::Mul => self.builder.build_int_mul(l, r, "mul"),
-                            BinaryOp::Div => self.builder.build_int_signed_div(l, r, "div"),
-                            BinaryOp::Mod => self.builder.build_int_signed_rem(l, r, "mod"),
-                            BinaryOp::Equal => self.builder.build_int_compare(IntPredicate::EQ, l, r, "eq"),
-                            BinaryOp::NotEqual => self.builder.build_int_compare(IntPredicate::NE, l, r, "ne"),
-                            BinaryOp::Less => self.builder.build_int_compare(IntPredicate::SLT, l, r, "lt"),
-                            BinaryOp::Greater => self.builder.build_int_compare(IntPredicate::SGT, l, r, "gt"),
-                            BinaryOp::LessEqual => self.builder.build_int_compare(IntPredicate::SLE, l, r, "le"),
-                            BinaryOp::GreaterEqual => self.builder.build_int_compare(IntPredicate::SGE, l, r, "ge"),
-                            BinaryOp::BitAnd => self.builder.build_and(l, r, "and"),
-                            BinaryOp::BitOr => self.builder.build_or(l, r, "or"),
-                            BinaryOp::BitXor => self.builder.build_xor(l, r, "xor"),
-                            BinaryOp::LeftShift => self.builder.build_left_shift(l, r, "shl"),
-                            BinaryOp::RightShift => self.builder.build_right_shift(l, r, true, "shr"),
-                            _ => return Err(format!("Unsupported binary operator: {:?}", op)),
-                        };
-                        result.map(|v| v.as_basic_value_enum())
-                            .map_err(|e| format!("Failed to build binary op: {:?}", e))
+                    (BasicValueEnum::PointerValue(a), BasicValueEnum::PointerValue(b)) => {
+                        // Distance in elements, as an isize
+                        let element = self.element_type(&left.ty)?;
+                        let distance = self.builder.build_ptr_diff(element, a, b, "ptrdiff")
+                            .map_err(|e| llvm_error("Failed to build pointer difference", e))?;
+                        self.builder.build_int_cast_sign_flag(distance, self.address_type(), true, "ptrdiff")
+                            .map(|v| v.as_basic_value_enum())
+                            .map_err(|e| llvm_error("Failed to build pointer difference", e))
                     }
-                    (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
-                        match op {
-                            BinaryOp::Add => self.builder.build_float_add(l, r, "fadd")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float op: {:?}", e)),
-                            BinaryOp::Sub => self.builder.build_float_sub(l, r, "fsub")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float op: {:?}", e)),
-                            BinaryOp::Mul => self.builder.build_float_mul(l, r, "fmul")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float op: {:?}", e)),
-                            BinaryOp::Div => self.builder.build_float_div(l, r, "fdiv")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float op: {:?}", e)),
-                            BinaryOp::Equal => self.builder.build_float_compare(FloatPredicate::OEQ, l, r, "feq")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float compare: {:?}", e)),
-                            BinaryOp::NotEqual => self.builder.build_float_compare(FloatPredicate::ONE, l, r, "fne")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float compare: {:?}", e)),
-                            BinaryOp::Less => self.builder.build_float_compare(FloatPredicate::OLT, l, r, "flt")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float compare: {:?}", e)),
-                            BinaryOp::Greater => self.builder.build_float_compare(FloatPredicate::OGT, l, r, "fgt")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float compare: {:?}", e)),
-                            BinaryOp::LessEqual => self.builder.build_float_compare(FloatPredicate::OLE, l, r, "fle")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float compare: {:?}", e)),
-                            BinaryOp::GreaterEqual => self.builder.build_float_compare(FloatPredicate::OGE, l, r, "fge")
-                                .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build float compare: {:?}", e)),
-                            _ => Err(format!("Unsupported float operator: {:?}", op)),
-                        }
+                    (BasicValueEnum::PointerValue(ptr), BasicValueEnum::IntValue(offset)) => {
+                        let element = self.element_type(&left.ty)?;
+                        let negate = *op == BinaryOp::Sub;
+                        self.build_pointer_offset(ptr, element, offset, is_signed(&right.ty), negate)
+                            .map(|v| v.as_basic_value_enum())
+                    }
+                    (BasicValueEnum::IntValue(offset), BasicValueEnum::PointerValue(ptr)) => {
+                        let element = self.element_type(&right.ty)?;
+                        self.build_pointer_offset(ptr, element, offset, is_signed(&left.ty), false)
+                            .map(|v| v.as_basic_value_enum())
+                    }
+                    _ => Err(FeraError::codegen("Invalid operands to pointer arithmetic", Some(expr.span.clone()))),
+                }
+            }
+            HirExprKind::Binary(op, left, right) => {
+                let lhs = self.codegen_expr(left)?;
+                let rhs = self.codegen_expr(right)?;
+                self.build_binary(op, lhs, rhs, &left.ty, &expr.span)
+            }
+            HirExprKind::Unary(op @ (UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec), operand) => {
+                let (ptr, ty) = self.codegen_lvalue(operand)?;
+                let old = self.build_lvalue_load(operand, ptr, ty, "old")?;
+                let is_inc = matches!(op, UnaryOp::PreInc | UnaryOp::PostInc);
+                
+                let new = match old {
+                    BasicValueEnum::IntValue(old_int) => {
+                        let one = old_int.get_type().const_int(1, false);
+                        let nsw = is_signed(&operand.ty) && !self.wrapv;
+                        match (is_inc, nsw) {
+                            (true, true) => self.builder.build_int_nsw_add(old_int, one, "inc"),
+                            (true, false) => self.builder.build_int_add(old_int, one, "inc"),
+                            (false, true) => self.builder.build_int_nsw_sub(old_int, one, "dec"),
+                            (false, false) => self.builder.build_int_sub(old_int, one, "dec"),
+                        }.map_err(|e| llvm_error("Failed to build increment", e))?.as_basic_value_enum()
+                    }
+                    BasicValueEnum::PointerValue(old_ptr) => {
+                        let element = self.element_type(&operand.ty)?;
+                        let one = self.address_type().const_int(1, false);
+                        self.build_pointer_offset(old_ptr, element, one, true, !is_inc)?.as_basic_value_enum()
                     }
-                    _ => Err("Type mismatch in binary operation".to_string()),
+                    _ => return Err(FeraError::codegen(
+                        "Increment and decrement only supported for integers and pointers",
+                        Some(expr.span.clone()),
+                    )),
+                };
+                self.build_lvalue_store(operand, ptr, new)?;
+                
+                if matches!(op, UnaryOp::PreInc | UnaryOp::PreDec) {
+                    Ok(new)
+                } else {
+                    Ok(old)
                 }
             }
-            HirExpr::Unary(op, expr) => {
-                let val = self.codegen_expr(expr)?;
+            HirExprKind::Unary(UnaryOp::Deref, _) | HirExprKind::Field(..) | HirExprKind::Index(..) => {
+                let (ptr, ty) = self.codegen_lvalue(expr)?;
+                self.build_lvalue_load(expr, ptr, ty, "load")
+            }
+            HirExprKind::Unary(UnaryOp::AddrOf, operand) => {
+                let (ptr, _) = self.codegen_lvalue(operand)?;
+                Ok(ptr.as_basic_value_enum())
+            }
+            HirExprKind::Unary(UnaryOp::Not, operand) => {
+                // The operand is tested like a condition, so `!` works on
+                // floats and pointers too
+                let value = self.codegen_condition(operand)?;
+                self.builder.build_not(value, "not")
+                    .map(|v| v.as_basic_value_enum())
+                    .map_err(|e| llvm_error("Failed to build not", e))
+            }
+            HirExprKind::Unary(op, operand) => {
+                let val = self.codegen_expr(operand)?;
                 
                 match op {
-                    UnaryOp::Neg => {
-                        if let BasicValueEnum::IntValue(iv) = val {
-                            self.builder.build_int_neg(iv, "neg")
+                    UnaryOp::Neg => match val {
+                        BasicValueEnum::IntValue(iv) => {
+                            if is_signed(&operand.ty) && !self.wrapv {
+                                self.builder.build_int_nsw_neg(iv, "neg")
+                            } else {
+                                self.builder.build_int_neg(iv, "neg")
+                            }
                                 .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build neg: {:?}", e))
-                        } else {
-                            Err("Negation only supported for integers".to_string())
+                                .map_err(|e| llvm_error("Failed to build neg", e))
                         }
-                    }
-                    UnaryOp::Not => {
+                        BasicValueEnum::FloatValue(fv) => self.builder.build_float_neg(fv, "neg")
+                            .map(|v| v.as_basic_value_enum())
+                            .map_err(|e| llvm_error("Failed to build neg", e)),
+                        _ => Err(FeraError::codegen("Negation only supported for numbers", Some(expr.span.clone()))),
+                    },
+                    UnaryOp::BitNot => {
                         if let BasicValueEnum::IntValue(iv) = val {
-                            let zero = iv.get_type().const_zero();
-                            self.builder.build_int_compare(IntPredicate::EQ, iv, zero, "not")
+                            self.builder.build_not(iv, "bitnot")
                                 .map(|v| v.as_basic_value_enum())
-                                .map_err(|e| format!("Failed to build not: {:?}", e))
+                                .map_err(|e| llvm_error("Failed to build bitwise not", e))
                         } else {
-                            Err("Not only supported for integers".to_string())
+                            Err(FeraError::codegen("Bitwise not only supported for integers", Some(expr.span.clone())))
                         }
                     }
-                    _ => Err(format!("Unsupported unary operator: {:?}", op)),
+                    _ => Err(FeraError::codegen(format!("Unsupported unary operator: {:?}", op), Some(expr.span.clone()))),
                 }
             }
-            HirExpr::Call(name, args) => {
+            HirExprKind::Function(name) => {
                 let func = self.module.get_function(name)
-                    .ok_or_else(|| format!("Unknown function: {}", name))?;
-                
-                let arg_vals: Vec<BasicMetadataValueEnum> = args.iter()
-                    .map(|a| self.codegen_expr(a).map(|v| v.into()))
-                    .collect::<Result<Vec<_>, _>>()?;
+                    .ok_or_else(|| FeraError::codegen(format!("Unknown function: {}", name), Some(expr.span.clone())))?;
+                Ok(func.as_global_value().as_pointer_value().as_basic_value_enum())
+            }
+            HirExprKind::Call(call) => self.codegen_call(call, &expr.span)?
+                .try_as_basic_value()
+                .left()
+                .ok_or_else(|| FeraError::codegen("A void call has no value", Some(expr.span.clone()))),
+            HirExprKind::Convert(operand) => {
+                let value = self.codegen_expr(operand)?;
+                self.build_conversion(value, &operand.ty, &expr.ty)
+            }
+            HirExprKind::Cast(ty, operand) => {
+                let value = self.codegen_expr(operand)?;
+                self.build_conversion(value, &operand.ty, ty)
+            }
+            HirExprKind::Assign(lhs, rhs) => {
+                let rhs_val = self.codegen_expr(rhs)?;
+                let (ptr, _) = self.codegen_lvalue(lhs)?;
+                self.build_lvalue_store(lhs, ptr, rhs_val)?;
+                Ok(rhs_val)
+            }
+            HirExprKind::CompoundAssign(op, lhs, rhs) => {
+                let (ptr, ty) = self.codegen_lvalue(lhs)?;
+                let current = self.build_lvalue_load(lhs, ptr, ty, "cur")?;
+                let rhs_val = self.codegen_expr(rhs)?;
                 
-                let call_site = self.builder.build_call(func, &arg_vals, "call")
-                    .map_err(|e| format!("Failed to build call: {:?}", e))?;
+                let result = match (current, rhs_val) {
+                    (BasicValueEnum::PointerValue(current), BasicValueEnum::IntValue(offset)) => {
+                        let element = self.element_type(&lhs.ty)?;
+                        let negate = *op == BinaryOp::Sub;
+                        self.build_pointer_offset(current, element, offset, is_signed(&rhs.ty), negate)?
+                            .as_basic_value_enum()
+                    }
+                    // Done in the right operand's type, then converted back
+                    _ => {
+                        let current = self.build_conversion(current, &lhs.ty, &rhs.ty)?;
+                        let result = self.build_binary(op, current, rhs_val, &rhs.ty, &expr.span)?;
+                        self.build_conversion(result, &rhs.ty, &lhs.ty)?
+                    }
+                };
+                self.build_lvalue_store(lhs, ptr, result)?;
+                Ok(result)
+            }
+        }
+    }
+    
+    /// Call a function directly or through a pointer
+    fn codegen_call(&mut self, call: &HirCall, span: &Span) -> Result<CallSiteValue<'ctx>, FeraError> {
+        let mut arg_vals: Vec<BasicMetadataValueEnum> = Vec::new();
+        for arg in &call.args {
+            arg_vals.push(self.codegen_expr(arg)?.into());
+        }
+        
+        match &call.callee {
+            HirCallee::Function(name) => {
+                let func = self.module.get_function(name)
+                    .ok_or_else(|| FeraError::codegen(format!("Unknown function: {}", name), Some(span.clone())))?;
+                self.builder.build_call(func, &arg_vals, "call")
+            }
+            HirCallee::Pointer(callee) => {
+                let (ret, params, variadic) = function_signature(&callee.ty)
+                    .ok_or_else(|| FeraError::codegen(format!("Cannot call '{}'", callee.ty), Some(callee.span.clone())))?;
+                let fn_type = self.llvm_fn_type(ret, params, variadic)?;
+                let BasicValueEnum::PointerValue(func) = self.codegen_expr(callee)? else {
+                    return Err(FeraError::codegen("Callee is not a pointer", Some(callee.span.clone())));
+                };
+                self.builder.build_indirect_call(fn_type, func, &arg_vals, "call")
+            }
+        }
+        .map_err(|e| llvm_error("Failed to build call", e))
+    }
+    
+    /// Evaluate `expr` as an `i1` truth value, comparing against zero
+    fn codegen_condition(&mut self, expr: &HirExpr) -> Result<IntValue<'ctx>, FeraError> {
+        let value = self.codegen_expr(expr)?;
+        let result = match value {
+            BasicValueEnum::IntValue(iv) if iv.get_type().get_bit_width() == 1 => return Ok(iv),
+            BasicValueEnum::IntValue(iv) => {
+                self.builder.build_int_compare(IntPredicate::NE, iv, iv.get_type().const_zero(), "tobool")
+            }
+            BasicValueEnum::FloatValue(fv) => {
+                self.builder.build_float_compare(FloatPredicate::ONE, fv, fv.get_type().const_zero(), "tobool")
+            }
+            BasicValueEnum::PointerValue(pv) => self.builder.build_is_not_null(pv, "tobool"),
+            _ => return Err(FeraError::codegen("Condition must be a scalar", Some(expr.span.clone()))),
+        };
+        result.map_err(|e| llvm_error("Failed to build condition", e))
+    }
+    
+    /// The block the builder is currently inserting into
+    fn insert_block(&self, span: &Span) -> Result<BasicBlock<'ctx>, FeraError> {
+        self.builder.get_insert_block()
+            .ok_or_else(|| FeraError::codegen("No current block", Some(span.clone())))
+    }
+    
+    /// Branch to `target` unless the current block already ends in a
+    /// terminator, e.g. because its last statement returned
+    fn branch_if_open(&self, target: BasicBlock<'ctx>) -> Result<(), FeraError> {
+        let open = self.builder.get_insert_block()
+            .is_some_and(|block| block.get_terminator().is_none());
+        if open {
+            self.builder.build_unconditional_branch(target)
+                .map_err(|e| llvm_error("Failed to build branch", e))?;
+        }
+        Ok(())
+    }
+    
+    /// Make `name` refer to `storage` until the end of the current scope
+    fn declare(&mut self, name: &str, storage: PointerValue<'ctx>, ty: BasicTypeEnum<'ctx>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), (storage, ty));
+        }
+    }
+    
+    /// Storage of the innermost local variable called `name` or, failing
+    /// that, a global
+    fn variable(&self, name: &str, span: &Span) -> Result<(PointerValue<'ctx>, BasicTypeEnum<'ctx>), FeraError> {
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(name))
+            .copied()
+            .ok_or_else(|| FeraError::codegen(format!("Unknown variable: {}", name), Some(span.clone())))
+    }
+    
+    /// Address and type of the storage an assignable expression refers to
+    fn codegen_lvalue(&mut self, expr: &HirExpr) -> Result<(PointerValue<'ctx>, BasicTypeEnum<'ctx>), FeraError> {
+        match &expr.kind {
+            HirExprKind::Variable(name) => self.variable(name, &expr.span),
+            HirExprKind::Unary(UnaryOp::Deref, operand) => {
+                let BasicValueEnum::PointerValue(ptr) = self.codegen_expr(operand)? else {
+                    return Err(FeraError::codegen("Cannot dereference a non-pointer", Some(operand.span.clone())));
+                };
+                let ty = self.llvm_type(&expr.ty)?
+                    .ok_or_else(|| FeraError::codegen("Cannot dereference a void pointer", Some(expr.span.clone())))?;
+                Ok((ptr, ty))
+            }
+            HirExprKind::Field(base, field) => {
+                let base_ptr = self.codegen_address(base)?;
+                let record = match &base.ty {
+                    Type::Struct(name) | Type::Union(name) => self.records.get(name),
+                    _ => None,
+                }.ok_or_else(|| FeraError::codegen(format!("Type '{}' has no fields", base.ty), Some(base.span.clone())))?;
+                let index = record.fields.iter().position(|(name, _)| name == field)
+                    .ok_or_else(|| FeraError::codegen(format!("No field '{}' on type '{}'", field, base.ty), Some(expr.span.clone())))?;
                 
-                // If function returns void, return a dummy i32 value
-                // This is a workaround for void function calls used in expression position
-                if let Some(val) = call_site.try_as_basic_value().left() {
-                    Ok(val)
-                } else {
-                    // Return a dummy value for void functions
-                    Ok(self.context.i32_type().const_zero().as_basic_value_enum())
+                let field_ty = self.llvm_type(&record.fields[index].1)?
+                    .ok_or_else(|| FeraError::codegen("Field of void type", Some(expr.span.clone())))?;
+                if record.is_union {
+                    // Every union member starts at the union's address
+                    return Ok((base_ptr, field_ty));
                 }
+                let ptr = self.builder.build_struct_gep(record.llvm_type, base_ptr, record.indices[index], field)
+                    .map_err(|e| llvm_error("Failed to build field address", e))?;
+                Ok((ptr, field_ty))
             }
-            HirExpr::Assign(lhs, rhs) => {
-                let rhs_val = self.codegen_expr(rhs)?;
-                
-                if let HirExpr::Variable(name) = lhs.as_ref() {
-                    let (ptr, _) = self.variables.get(name)
-                        .ok_or_else(|| format!("Unknown variable: {}", name))?;
-                    
-                    self.builder.build_store(*ptr, rhs_val)
-                        .map_err(|e| format!("Failed to store: {:?}", e))?;
-                    
-                    Ok(rhs_val)
-                } else {
-                    Err("Can only assign to variables".to_string())
+            HirExprKind::Index(base, index) => {
+                // An array's address is also the address of its first element
+                let base_ptr = match self.codegen_expr(base)? {
+                    BasicValueEnum::PointerValue(ptr) => ptr,
+                    _ => return Err(FeraError::codegen("Cannot index a non-pointer", Some(base.span.clone()))),
+                };
+                let BasicValueEnum::IntValue(offset) = self.codegen_expr(index)? else {
+                    return Err(FeraError::codegen("Array index must be an integer", Some(index.span.clone())));
+                };
+                let element = self.llvm_type(&expr.ty)?
+                    .ok_or_else(|| FeraError::codegen("Cannot index a void pointer", Some(expr.span.clone())))?;
+                let ptr = self.build_pointer_offset(base_ptr, element, offset, is_signed(&index.ty), false)?;
+                Ok((ptr, element))
+            }
+            _ => Err(FeraError::codegen("Expression is not assignable", Some(expr.span.clone()))),
+        }
+    }
+    
+    /// Load `lvalue` from its address `ptr`. Accesses through a pointer
+    /// to volatile are volatile loads, which LLVM never merges or drops.
+    fn build_lvalue_load(
+        &self,
+        lvalue: &HirExpr,
+        ptr: PointerValue<'ctx>,
+        ty: BasicTypeEnum<'ctx>,
+        name: &str,
+    ) -> Result<BasicValueEnum<'ctx>, FeraError> {
+        let value = self.builder.build_load(ty, ptr, name)
+            .map_err(|e| llvm_error("Failed to load", e))?;
+        if lvalue.is_volatile() {
+            if let Some(load) = value.as_instruction_value() {
+                load.set_volatile(true).map_err(|e| llvm_error("Failed to make load volatile", e))?;
+            }
+        }
+        Ok(value)
+    }
+    
+    /// Store `value` to `lvalue` at its address `ptr`, as a volatile
+    /// store when `lvalue` is reached through a pointer to volatile
+    fn build_lvalue_store(&self, lvalue: &HirExpr, ptr: PointerValue<'ctx>, value: BasicValueEnum<'ctx>) -> Result<(), FeraError> {
+        let store = self.builder.build_store(ptr, value)
+            .map_err(|e| llvm_error("Failed to store", e))?;
+        if lvalue.is_volatile() {
+            store.set_volatile(true).map_err(|e| llvm_error("Failed to make store volatile", e))?;
+        }
+        Ok(())
+    }
+    
+    /// `ptr + offset` (or `ptr - offset`), scaled by the size of `element`
+    fn build_pointer_offset(
+        &self,
+        ptr: PointerValue<'ctx>,
+        element: BasicTypeEnum<'ctx>,
+        offset: IntValue<'ctx>,
+        signed: bool,
+        negate: bool,
+    ) -> Result<PointerValue<'ctx>, FeraError> {
+        let mut offset = self.builder.build_int_cast_sign_flag(offset, self.address_type(), signed, "idx")
+            .map_err(|e| llvm_error("Failed to extend index", e))?;
+        if negate {
+            offset = self.builder.build_int_neg(offset, "neg")
+                .map_err(|e| llvm_error("Failed to negate index", e))?;
+        }
+        unsafe { self.builder.build_in_bounds_gep(element, ptr, &[offset], "elem") }
+            .map_err(|e| llvm_error("Failed to build element address", e))
+    }
+    
+    /// The integer type as wide as a pointer, which `isize`, `usize`
+    /// and address arithmetic use
+    fn address_type(&self) -> IntType<'ctx> {
+        self.context.custom_width_int_type(self.model.pointer_bits())
+    }
+    
+    /// LLVM type of what a pointer or array of type `ty` points to.
+    /// `void*` arithmetic steps by bytes, as GCC does.
+    fn element_type(&self, ty: &Type) -> Result<BasicTypeEnum<'ctx>, FeraError> {
+        let element = pointee(ty)
+            .ok_or_else(|| FeraError::codegen(format!("'{}' is not a pointer", ty), None))?;
+        Ok(self.llvm_type(&element)?.unwrap_or_else(|| self.context.i8_type().as_basic_type_enum()))
+    }
+    
+    /// Address of `expr`'s value. Values that don't live in memory, like
+    /// a struct returned from a call, are first spilled to the stack.
+    fn codegen_address(&mut self, expr: &HirExpr) -> Result<PointerValue<'ctx>, FeraError> {
+        if is_lvalue(expr) {
+            return Ok(self.codegen_lvalue(expr)?.0);
+        }
+        
+        let value = self.codegen_expr(expr)?;
+        let temp = self.build_entry_alloca(value.get_type(), "tmp")?;
+        self.builder.build_store(temp, value)
+            .map_err(|e| llvm_error("Failed to store", e))?;
+        Ok(temp)
+    }
+    
+    /// Allocate stack space in the entry block, so loops don't grow the stack
+    fn build_entry_alloca(&self, ty: BasicTypeEnum<'ctx>, name: &str) -> Result<PointerValue<'ctx>, FeraError> {
+        let entry = self.current_function
+            .and_then(|f| f.get_first_basic_block())
+            .ok_or_else(|| FeraError::codegen("No current function", None))?;
+        
+        let builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(first) => builder.position_before(&first),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, name)
+            .map_err(|e| llvm_error("Failed to build alloca", e))
+    }
+    
+    /// Build `lhs op rhs` for two already generated operands of type
+    /// `operand_ty`. Unsigned operations use unsigned division, comparisons
+    /// and logical right shifts.
+    fn build_binary(
+        &self,
+        op: &BinaryOp,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+        operand_ty: &Type,
+        span: &Span,
+    ) -> Result<BasicValueEnum<'ctx>, FeraError> {
+        let signed = is_signed(operand_ty);
+        let nsw = signed && !self.wrapv;
+        
+        // Pointers compare by address
+        let (lhs, rhs) = match (lhs, rhs) {
+            (BasicValueEnum::PointerValue(_), _) | (_, BasicValueEnum::PointerValue(_)) => {
+                (self.pointer_to_int(lhs)?, self.pointer_to_int(rhs)?)
+            }
+            _ => (lhs, rhs),
+        };
+        
+        match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                let predicate = |signed_pred, unsigned_pred| if signed { signed_pred } else { unsigned_pred };
+                let result = match op {
+                    BinaryOp::Add if nsw => self.builder.build_int_nsw_add(l, r, "add"),
+                    BinaryOp::Add => self.builder.build_int_add(l, r, "add"),
+                    BinaryOp::Sub if nsw => self.builder.build_int_nsw_sub(l, r, "sub"),
+                    BinaryOp::Sub => self.builder.build_int_sub(l, r, "sub"),
+                    BinaryOp::Mul if nsw => self.builder.build_int_nsw_mul(l, r, "mul"),
+                    BinaryOp::Mul => self.builder.build_int_mul(l, r, "mul"),
+                    BinaryOp::Div if signed => self.builder.build_int_signed_div(l, r, "div"),
+                    BinaryOp::Div => self.builder.build_int_unsigned_div(l, r, "div"),
+                    BinaryOp::Mod if signed => self.builder.build_int_signed_rem(l, r, "mod"),
+                    BinaryOp::Mod => self.builder.build_int_unsigned_rem(l, r, "mod"),
+                    BinaryOp::Equal => self.builder.build_int_compare(IntPredicate::EQ, l, r, "eq"),
+                    BinaryOp::NotEqual => self.builder.build_int_compare(IntPredicate::NE, l, r, "ne"),
+                    BinaryOp::Less => self.builder.build_int_compare(predicate(IntPredicate::SLT, IntPredicate::ULT), l, r, "lt"),
+                    BinaryOp::Greater => self.builder.build_int_compare(predicate(IntPredicate::SGT, IntPredicate::UGT), l, r, "gt"),
+                    BinaryOp::LessEqual => self.builder.build_int_compare(predicate(IntPredicate::SLE, IntPredicate::ULE), l, r, "le"),
+                    BinaryOp::GreaterEqual => self.builder.build_int_compare(predicate(IntPredicate::SGE, IntPredicate::UGE), l, r, "ge"),
+                    BinaryOp::BitAnd => self.builder.build_and(l, r, "and"),
+                    BinaryOp::BitOr => self.builder.build_or(l, r, "or"),
+                    BinaryOp::BitXor => self.builder.build_xor(l, r, "xor"),
+                    BinaryOp::LeftShift => self.builder.build_left_shift(l, r, "shl"),
+                    BinaryOp::RightShift => self.builder.build_right_shift(l, r, signed, "shr"),
+                    _ => return Err(FeraError::codegen(format!("Unsupported binary operator: {:?}", op), Some(span.clone()))),
+                };
+                result.map(|v| v.as_basic_value_enum())
+                    .map_err(|e| llvm_error("Failed to build binary op", e))
+            }
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
+                match op {
+                    BinaryOp::Add => self.builder.build_float_add(l, r, "fadd")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float op", e)),
+                    BinaryOp::Sub => self.builder.build_float_sub(l, r, "fsub")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float op", e)),
+                    BinaryOp::Mul => self.builder.build_float_mul(l, r, "fmul")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float op", e)),
+                    BinaryOp::Div => self.builder.build_float_div(l, r, "fdiv")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float op", e)),
+                    BinaryOp::Equal => self.builder.build_float_compare(FloatPredicate::OEQ, l, r, "feq")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float compare", e)),
+                    BinaryOp::NotEqual => self.builder.build_float_compare(FloatPredicate::ONE, l, r, "fne")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float compare", e)),
+                    BinaryOp::Less => self.builder.build_float_compare(FloatPredicate::OLT, l, r, "flt")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float compare", e)),
+                    BinaryOp::Greater => self.builder.build_float_compare(FloatPredicate::OGT, l, r, "fgt")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float compare", e)),
+                    BinaryOp::LessEqual => self.builder.build_float_compare(FloatPredicate::OLE, l, r, "fle")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float compare", e)),
+                    BinaryOp::GreaterEqual => self.builder.build_float_compare(FloatPredicate::OGE, l, r, "fge")
+                        .map(|v| v.as_basic_value_enum())
+                        .map_err(|e| llvm_error("Failed to build float compare", e)),
+                    _ => Err(FeraError::codegen(format!("Unsupported float operator: {:?}", op), Some(span.clone()))),
                 }
             }
-            _ => Err("Expression codegen not fully implemented".to_string()),
+            _ => Err(FeraError::codegen("Type mismatch in binary operation", Some(span.clone()))),
         }
     }
     
+    fn pointer_to_int(&self, value: BasicValueEnum<'ctx>) -> Result<BasicValueEnum<'ctx>, FeraError> {
+        match value {
+            BasicValueEnum::PointerValue(ptr) => self.builder.build_ptr_to_int(ptr, self.address_type(), "addr")
+                .map(|v| v.as_basic_value_enum())
+                .map_err(|e| llvm_error("Failed to convert pointer", e)),
+            _ => Ok(value),
+        }
+    }
+    
+    /// Convert `value` from one arithmetic type to another, or between
+    /// integers and pointers. Integers are extended according to the
+    /// signedness of `from`; conversions to `bool` compare against zero.
+    /// Pointers all share one LLVM type, so converting between them
+    /// leaves the value alone.
+    fn build_conversion(&self, value: BasicValueEnum<'ctx>, from: &Type, to: &Type) -> Result<BasicValueEnum<'ctx>, FeraError> {
+        let target = self.llvm_type(to)?
+            .ok_or_else(|| FeraError::codegen(format!("Cannot convert to '{}'", to), None))?;
+        let result = match (value, target) {
+            (BasicValueEnum::IntValue(int_val), BasicTypeEnum::IntType(_)) if *to == Type::Bool => {
+                self.builder.build_int_compare(IntPredicate::NE, int_val, int_val.get_type().const_zero(), "tobool")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::IntValue(int_val), BasicTypeEnum::IntType(int_ty)) => {
+                self.builder.build_int_cast_sign_flag(int_val, int_ty, is_signed(from), "conv")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::IntValue(int_val), BasicTypeEnum::FloatType(float_ty)) if is_signed(from) => {
+                self.builder.build_signed_int_to_float(int_val, float_ty, "conv")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::IntValue(int_val), BasicTypeEnum::FloatType(float_ty)) => {
+                self.builder.build_unsigned_int_to_float(int_val, float_ty, "conv")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::FloatValue(float_val), BasicTypeEnum::IntType(_)) if *to == Type::Bool => {
+                self.builder.build_float_compare(FloatPredicate::UNE, float_val, float_val.get_type().const_zero(), "tobool")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::FloatValue(float_val), BasicTypeEnum::IntType(int_ty)) if is_signed(to) => {
+                self.builder.build_float_to_signed_int(float_val, int_ty, "conv")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::FloatValue(float_val), BasicTypeEnum::IntType(int_ty)) => {
+                self.builder.build_float_to_unsigned_int(float_val, int_ty, "conv")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::FloatValue(float_val), BasicTypeEnum::FloatType(float_ty)) => {
+                self.builder.build_float_cast(float_val, float_ty, "conv")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::IntValue(int_val), BasicTypeEnum::PointerType(ptr_ty)) => {
+                // Extend to the width of an address first, so `(T*)-1`
+                // is all ones
+                let address = self.builder.build_int_cast_sign_flag(int_val, self.address_type(), is_signed(from), "addr")
+                    .map_err(|e| llvm_error("Failed to build conversion", e))?;
+                self.builder.build_int_to_ptr(address, ptr_ty, "conv")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::PointerValue(ptr_val), BasicTypeEnum::IntType(_)) if *to == Type::Bool => {
+                self.builder.build_is_not_null(ptr_val, "tobool")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            (BasicValueEnum::PointerValue(ptr_val), BasicTypeEnum::IntType(int_ty)) => {
+                self.builder.build_ptr_to_int(ptr_val, int_ty, "conv")
+                    .map(|v| v.as_basic_value_enum())
+            }
+            _ => return Ok(value),
+        };
+        result.map_err(|e| llvm_error("Failed to build conversion", e))
+    }
+    
+    fn llvm_fn_type(&self, ret: &Type, params: &[Type], variadic: bool) -> Result<FunctionType<'ctx>, String> {
+        let param_types = params.iter()
+            .map(|ty| {
+                self.llvm_type(ty)?
+                    .ok_or_else(|| "Function parameters cannot be void".to_string())
+                    .map(|t| t.into())
+            })
+            .collect::<Result<Vec<BasicMetadataTypeEnum>, _>>()?;
+        Ok(match self.llvm_type(ret)? {
+            Some(ty) => ty.fn_type(&param_types, variadic),
+            None => self.context.void_type().fn_type(&param_types, variadic),
+        })
+    }
+    
     fn llvm_type(&self, ty: &Type) -> Result<Option<BasicTypeEnum<'ctx>>, String> {
         match ty {
             Type::Void => Ok(None),
@@ -521,14 +1597,18 @@ impl<'ctx> CodeGen<'ctx> {
             Type::Char | Type::I8 => Ok(Some(self.context.i8_type().as_basic_type_enum())),
             Type::I16 => Ok(Some(self.context.i16_type().as_basic_type_enum())),
             Type::I32 => Ok(Some(self.context.i32_type().as_basic_type_enum())),
-            Type::I64 | Type::ISize => Ok(Some(self.context.i64_type().as_basic_type_enum())),
+            Type::I64 => Ok(Some(self.context.i64_type().as_basic_type_enum())),
             Type::U8 => Ok(Some(self.context.i8_type().as_basic_type_enum())),
             Type::U16 => Ok(Some(self.context.i16_type().as_basic_type_enum())),
             Type::U32 => Ok(Some(self.context.i32_type().as_basic_type_enum())),
-            Type::U64 | Type::USize => Ok(Some(self.context.i64_type().as_basic_type_enum())),
+            Type::U64 => Ok(Some(self.context.i64_type().as_basic_type_enum())),
+            Type::ISize | Type::USize => Ok(Some(self.address_type().as_basic_type_enum())),
             Type::F32 => Ok(Some(self.context.f32_type().as_basic_type_enum())),
             Type::F64 => Ok(Some(self.context.f64_type().as_basic_type_enum())),
             Type::Pointer(inner, _) => {
+                if let Type::Function(ret, params, variadic) = inner.as_ref() {
+                    return Ok(Some(self.llvm_fn_type(ret, params, *variadic)?.ptr_type(AddressSpace::default()).as_basic_type_enum()));
+                }
                 let inner_ty = if let Some(t) = self.llvm_type(inner)? {
                     t
                 } else {
@@ -546,6 +1626,9 @@ impl<'ctx> CodeGen<'ctx> {
                     Err("Unsized arrays not supported in codegen".to_string())
                 }
             }
+            Type::Struct(name) | Type::Union(name) => self.records.get(name)
+                .map(|record| Some(record.llvm_type.as_basic_type_enum()))
+                .ok_or_else(|| format!("Unknown type '{}'", ty)),
             _ => Err(format!("Type conversion not implemented: {:?}", ty)),
         }
     }
@@ -564,3 +1647,60 @@ impl<'ctx> CodeGen<'ctx> {
     }
 }
 
+/// Attributes that map directly to LLVM function attributes
+const FUNCTION_ATTRIBUTES: [(&str, &str); 5] = [
+    ("noreturn", "noreturn"),
+    ("cold", "cold"),
+    ("hot", "hot"),
+    ("always_inline", "alwaysinline"),
+    ("noinline", "noinline"),
+];
+
+/// Sizes and alignments of scalar types on the target `machine`
+/// compiles to. LLVM doesn't know the size of C's `long`, which is 32
+/// bits on Windows and as wide as a pointer elsewhere.
+pub fn data_model(machine: &TargetMachine) -> DataModel {
+    let context = Context::create();
+    let target = machine.get_target_data();
+    let align = |ty: &dyn AnyType| target.get_abi_alignment(ty) as u64;
+    let pointer_size = target.get_pointer_byte_size(None) as u64;
+    let windows = machine.get_triple().as_str().to_string_lossy().contains("windows");
+    DataModel {
+        pointer: Layout::new(pointer_size, align(&context.i8_type().ptr_type(AddressSpace::default()))),
+        i16_align: align(&context.i16_type()),
+        i32_align: align(&context.i32_type()),
+        i64_align: align(&context.i64_type()),
+        f32_align: align(&context.f32_type()),
+        f64_align: align(&context.f64_type()),
+        long_size: if windows { 4 } else { pointer_size },
+    }
+}
+
+/// A constant array of `values`, which must all have type `element`
+fn const_array<'ctx>(element: BasicTypeEnum<'ctx>, values: &[BasicValueEnum<'ctx>]) -> Option<ArrayValue<'ctx>> {
+    if values.iter().any(|value| value.get_type() != element) {
+        return None;
+    }
+    let array = match element {
+        BasicTypeEnum::IntType(ty) => ty.const_array(&values.iter().map(|v| v.into_int_value()).collect::<Vec<_>>()),
+        BasicTypeEnum::FloatType(ty) => ty.const_array(&values.iter().map(|v| v.into_float_value()).collect::<Vec<_>>()),
+        BasicTypeEnum::PointerType(ty) => ty.const_array(&values.iter().map(|v| v.into_pointer_value()).collect::<Vec<_>>()),
+        BasicTypeEnum::ArrayType(ty) => ty.const_array(&values.iter().map(|v| v.into_array_value()).collect::<Vec<_>>()),
+        BasicTypeEnum::StructType(ty) => ty.const_array(&values.iter().map(|v| v.into_struct_value()).collect::<Vec<_>>()),
+        BasicTypeEnum::VectorType(ty) => ty.const_array(&values.iter().map(|v| v.into_vector_value()).collect::<Vec<_>>()),
+    };
+    Some(array)
+}
+
+/// Whether `expr` designates storage that codegen can take the address of
+fn is_lvalue(expr: &HirExpr) -> bool {
+    matches!(
+        expr.kind,
+        HirExprKind::Variable(_) | HirExprKind::Unary(UnaryOp::Deref, _) | HirExprKind::Field(..) | HirExprKind::Index(..)
+    )
+}
+
+/// An error reported by LLVM while building an instruction
+fn llvm_error(what: &str, error: impl std::fmt::Debug) -> FeraError {
+    FeraError::codegen(format!("{}: {:?}", what, error), None)
+}
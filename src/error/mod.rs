@@ -2,8 +2,12 @@ use codespan_reporting::diagnostic::{Diagnostic, Label};
 use codespan_reporting::files::SimpleFiles;
 use codespan_reporting::term;
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+use std::io::IsTerminal;
 use std::ops::Range;
 
+use crate::lexer::source_map::{FileId, SourceMap};
+use crate::lint::{Lint, LintLevel};
+
 pub type Span = Range<usize>;
 
 #[derive(Debug, Clone)]
@@ -12,6 +16,7 @@ pub enum FeraError {
     ParseError(ParseError),
     TypeError(TypeError),
     CodegenError(CodegenError),
+    Warning(Warning),
 }
 
 #[derive(Debug, Clone)]
@@ -46,84 +51,208 @@ pub struct CodegenError {
     pub suggestion: Option<String>,
 }
 
+/// A lint that fired. It fails the build only when its level is `Deny`.
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub lint: Lint,
+    pub level: LintLevel,
+    pub message: String,
+    pub span: Span,
+    pub suggestion: Option<String>,
+}
+
 impl FeraError {
-    pub fn report(&self, filename: &str, source: &str) {
+    pub fn lex(message: impl Into<String>, span: Span) -> Self {
+        FeraError::LexError(LexError {
+            message: message.into(),
+            span,
+            suggestion: None,
+        })
+    }
+    
+    pub fn parse(message: impl Into<String>, span: Span) -> Self {
+        FeraError::ParseError(ParseError {
+            message: message.into(),
+            span,
+            expected: None,
+            found: None,
+            suggestion: None,
+        })
+    }
+    
+    pub fn type_error(message: impl Into<String>, span: Span) -> Self {
+        FeraError::TypeError(TypeError {
+            message: message.into(),
+            span,
+            expected_type: None,
+            found_type: None,
+            suggestion: None,
+        })
+    }
+    
+    pub fn warning(lint: Lint, level: LintLevel, message: impl Into<String>, span: Span) -> Self {
+        FeraError::Warning(Warning {
+            lint,
+            level,
+            message: message.into(),
+            span,
+            suggestion: None,
+        })
+    }
+    
+    pub fn codegen(message: impl Into<String>, span: Option<Span>) -> Self {
+        FeraError::CodegenError(CodegenError {
+            message: message.into(),
+            span,
+            suggestion: None,
+        })
+    }
+    
+    /// Record what was expected and what was found instead. Only parse and
+    /// type errors carry this; other kinds are returned unchanged.
+    pub fn with_expected(mut self, expected: impl ToString, found: impl ToString) -> Self {
+        match &mut self {
+            FeraError::ParseError(e) => {
+                e.expected = Some(expected.to_string());
+                e.found = Some(found.to_string());
+            }
+            FeraError::TypeError(e) => {
+                e.expected_type = Some(expected.to_string());
+                e.found_type = Some(found.to_string());
+            }
+            FeraError::LexError(_) | FeraError::CodegenError(_) | FeraError::Warning(_) => {}
+        }
+        self
+    }
+    
+    pub fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        let slot = match &mut self {
+            FeraError::LexError(e) => &mut e.suggestion,
+            FeraError::ParseError(e) => &mut e.suggestion,
+            FeraError::TypeError(e) => &mut e.suggestion,
+            FeraError::CodegenError(e) => &mut e.suggestion,
+            FeraError::Warning(e) => &mut e.suggestion,
+        };
+        *slot = Some(suggestion.into());
+        self
+    }
+    
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            FeraError::LexError(e) => Some(e.span.clone()),
+            FeraError::ParseError(e) => Some(e.span.clone()),
+            FeraError::TypeError(e) => Some(e.span.clone()),
+            FeraError::CodegenError(e) => e.span.clone(),
+            FeraError::Warning(e) => Some(e.span.clone()),
+        }
+    }
+    
+    /// Whether this diagnostic should stop compilation
+    pub fn is_error(&self) -> bool {
+        match self {
+            FeraError::Warning(e) => e.level == LintLevel::Deny,
+            _ => true,
+        }
+    }
+    
+    /// Print the error with labels pointing into the original sources.
+    /// Spans refer to the preprocessed text described by `sources`.
+    pub fn report(&self, sources: &SourceMap) {
         let mut files = SimpleFiles::new();
-        let file_id = files.add(filename, source);
+        for file in sources.files() {
+            files.add(file.name.clone(), file.source.clone());
+        }
         
         let diagnostic = match self {
             FeraError::LexError(e) => {
-                let mut diag = Diagnostic::error()
-                    .with_message(format!("Lexer error: {}", e.message))
-                    .with_labels(vec![
-                        Label::primary(file_id, e.span.clone())
-                            .with_message(&e.message)
-                    ]);
+                let (labels, mut notes) = locate(sources, &e.span, &e.message);
                 
                 if let Some(suggestion) = &e.suggestion {
-                    diag = diag.with_notes(vec![format!("help: {}", suggestion)]);
+                    notes.push(format!("help: {}", suggestion));
                 }
                 
-                diag
+                Diagnostic::error()
+                    .with_message(format!("Lexer error: {}", e.message))
+                    .with_labels(labels)
+                    .with_notes(notes)
             }
             FeraError::ParseError(e) => {
-                let mut message = e.message.clone();
-                if let (Some(expected), Some(found)) = (&e.expected, &e.found) {
-                    message = format!("expected {}, found {}", expected, found);
-                }
+                let label = match &e.expected {
+                    Some(expected) => format!("expected {}", expected),
+                    None => e.message.clone(),
+                };
                 
-                let mut diag = Diagnostic::error()
-                    .with_message(format!("Parse error: {}", message))
-                    .with_labels(vec![
-                        Label::primary(file_id, e.span.clone())
-                            .with_message(&message)
-                    ]);
+                let (labels, mut notes) = locate(sources, &e.span, &label);
                 
                 if let Some(suggestion) = &e.suggestion {
-                    diag = diag.with_notes(vec![format!("help: {}", suggestion)]);
+                    notes.push(format!("help: {}", suggestion));
                 }
                 
-                diag
+                Diagnostic::error()
+                    .with_message(format!("Parse error: {}", e.message))
+                    .with_labels(labels)
+                    .with_notes(notes)
             }
             FeraError::TypeError(e) => {
-                let mut message = e.message.clone();
-                if let (Some(expected), Some(found)) = (&e.expected_type, &e.found_type) {
-                    message = format!("type mismatch: expected {}, found {}", expected, found);
-                }
+                let label = match (&e.expected_type, &e.found_type) {
+                    (Some(expected), Some(found)) => format!("expected `{}`, found `{}`", expected, found),
+                    _ => e.message.clone(),
+                };
                 
-                let mut diag = Diagnostic::error()
-                    .with_message(format!("Type error: {}", message))
-                    .with_labels(vec![
-                        Label::primary(file_id, e.span.clone())
-                            .with_message(&message)
-                    ]);
+                let (labels, mut notes) = locate(sources, &e.span, &label);
                 
                 if let Some(suggestion) = &e.suggestion {
-                    diag = diag.with_notes(vec![format!("help: {}", suggestion)]);
+                    notes.push(format!("help: {}", suggestion));
                 }
                 
-                diag
+                Diagnostic::error()
+                    .with_message(format!("Type error: {}", e.message))
+                    .with_labels(labels)
+                    .with_notes(notes)
             }
             FeraError::CodegenError(e) => {
-                let mut diag = Diagnostic::error()
-                    .with_message(format!("Codegen error: {}", e.message));
+                let (labels, mut notes) = match &e.span {
+                    Some(span) => locate(sources, span, &e.message),
+                    None => (Vec::new(), Vec::new()),
+                };
                 
-                if let Some(span) = &e.span {
-                    diag = diag.with_labels(vec![
-                        Label::primary(file_id, span.clone())
-                            .with_message(&e.message)
-                    ]);
+                if let Some(suggestion) = &e.suggestion {
+                    notes.push(format!("help: {}", suggestion));
                 }
                 
+                Diagnostic::error()
+                    .with_message(format!("Codegen error: {}", e.message))
+                    .with_labels(labels)
+                    .with_notes(notes)
+            }
+            FeraError::Warning(e) => {
+                let (labels, mut notes) = locate(sources, &e.span, &e.message);
+                
                 if let Some(suggestion) = &e.suggestion {
-                    diag = diag.with_notes(vec![format!("help: {}", suggestion)]);
+                    notes.push(format!("help: {}", suggestion));
                 }
+                notes.push(format!("note: `{} {}` is in effect", e.level.flag(), e.lint));
                 
-                diag
+                let diagnostic = if e.level == LintLevel::Deny {
+                    Diagnostic::error()
+                } else {
+                    Diagnostic::warning()
+                };
+                diagnostic
+                    .with_message(e.message.clone())
+                    .with_labels(labels)
+                    .with_notes(notes)
             }
         };
         
-        let writer = StandardStream::stderr(ColorChoice::Auto);
+        // termcolor's Auto only looks at the environment, so check for a
+        // terminal too to keep escape codes out of pipes and log files
+        let color = if std::io::stderr().is_terminal() {
+            ColorChoice::Auto
+        } else {
+            ColorChoice::Never
+        };
+        let writer = StandardStream::stderr(color);
         let config = codespan_reporting::term::Config::default();
         
         term::emit(&mut writer.lock(), &config, &files, &diagnostic)
@@ -131,6 +260,38 @@ impl FeraError {
     }
 }
 
+/// Labels and notes for `span`. Code produced by macros is labelled at the
+/// outermost invocation, with a note for every expansion it went through.
+fn locate(sources: &SourceMap, span: &Span, message: &str) -> (Vec<Label<FileId>>, Vec<String>) {
+    let mapped = match sources.lookup(span) {
+        Some(mapped) => mapped,
+        None => return (Vec::new(), Vec::new()),
+    };
+    
+    let location = sources.user_location(&mapped);
+    let mut labels = vec![
+        Label::primary(location.file, location.span).with_message(message)
+    ];
+    
+    let chain = sources.expansion_chain(mapped.expansion);
+    if !chain.is_empty() {
+        labels.push(
+            Label::secondary(mapped.origin.file, mapped.origin.span)
+                .with_message("in this macro expansion")
+        );
+    }
+    
+    let notes = chain.iter()
+        .map(|e| format!(
+            "in expansion of macro '{}', defined at {}",
+            e.macro_name,
+            sources.describe(&e.definition)
+        ))
+        .collect();
+    
+    (labels, notes)
+}
+
 impl From<String> for FeraError {
     fn from(s: String) -> Self {
         FeraError::CodegenError(CodegenError {
@@ -148,6 +309,8 @@ impl std::fmt::Display for FeraError {
             FeraError::ParseError(e) => write!(f, "Parse error: {}", e.message),
             FeraError::TypeError(e) => write!(f, "Type error: {}", e.message),
             FeraError::CodegenError(e) => write!(f, "Codegen error: {}", e.message),
+            FeraError::Warning(e) if e.level == LintLevel::Deny => write!(f, "Error: {}", e.message),
+            FeraError::Warning(e) => write!(f, "Warning: {}", e.message),
         }
     }
 }
@@ -1,5 +1,16 @@
 // High-level IR - desugared AST with name resolution
 use crate::ast;
+use crate::ast::Span;
+use crate::error::FeraError;
+use crate::types::asm;
+use crate::types::constant::{self, Constant, Declarations};
+use crate::types::layout::DataModel;
+use crate::types::{
+    argument_promotion, arithmetic_conversion, binary_result_type, builtin_functions, function_pointer,
+    function_signature, is_arithmetic, is_comparison, is_integer, operand_type, pointee, promote,
+    resolve_type,
+};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone)]
 pub struct HirProgram {
@@ -10,6 +21,18 @@ pub struct HirProgram {
 pub enum HirItem {
     Function(HirFunction),
     Global(HirGlobal),
+    Struct(HirRecord),
+    Union(HirRecord),
+}
+
+/// A struct or union definition
+#[derive(Debug, Clone)]
+pub struct HirRecord {
+    pub name: String,
+    pub fields: Vec<(String, ast::Type)>,
+    /// The alignment fields are capped at by `[[packed]]`
+    pub packing: Option<u64>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -17,35 +40,116 @@ pub struct HirFunction {
     pub name: String,
     pub return_type: ast::Type,
     pub params: Vec<(String, ast::Type)>,
-    pub body: HirBlock,
+    pub is_variadic: bool,
+    /// `None` for a declaration of a function defined elsewhere
+    pub body: Option<HirBlock>,
     pub linkage: ast::Linkage,
+    pub attributes: Vec<ast::Attribute>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct HirGlobal {
     pub name: String,
     pub ty: ast::Type,
-    pub init: Option<HirExpr>,
+    /// The initializer's value; globals without one are zero
+    pub init: Option<Constant>,
     pub linkage: ast::Linkage,
+    pub attributes: Vec<ast::Attribute>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct HirBlock {
     pub stmts: Vec<HirStmt>,
+    pub span: Span,
 }
 
+/// A statement together with the source span it was lowered from
 #[derive(Debug, Clone)]
-pub enum HirStmt {
+pub struct HirStmt {
+    pub kind: HirStmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum HirStmtKind {
     Let(String, ast::Type, Option<HirExpr>),
+    /// An expression whose value is discarded
     Expr(HirExpr),
+    /// A call of a function that returns void, which has no value to
+    /// use in an expression
+    VoidCall(HirCall),
     Return(Option<HirExpr>),
     If(HirExpr, Box<HirStmt>, Option<Box<HirStmt>>),
     While(HirExpr, Box<HirStmt>),
+    DoWhile(Box<HirStmt>, HirExpr),
+    /// Initializer, condition (always true when missing), increment and body
+    For(Option<Box<HirStmt>>, Option<HirExpr>, Option<Box<HirStmt>>, Box<HirStmt>),
+    /// The promoted value switched on and its labels in source order
+    Switch(HirExpr, Vec<HirSwitchCase>),
+    Break,
+    Continue,
+    Label(String),
+    Goto(String),
     Block(HirBlock),
+    Asm(HirAsm),
+}
+
+/// An inline asm statement, with its template in LLVM's syntax
+#[derive(Debug, Clone)]
+pub struct HirAsm {
+    pub template: String,
+    pub outputs: Vec<HirAsmOperand>,
+    pub inputs: Vec<HirAsmOperand>,
+    pub clobbers: Vec<String>,
+    /// `volatile`, or without outputs: the statement must stay even if
+    /// nothing uses what it computes
+    pub side_effects: bool,
+}
+
+/// An asm operand. Outputs and memory operands are lvalues.
+#[derive(Debug, Clone)]
+pub struct HirAsmOperand {
+    pub constraint: asm::Constraint,
+    pub expr: HirExpr,
+}
+
+/// A function call, with its arguments converted to the parameter types
+#[derive(Debug, Clone)]
+pub struct HirCall {
+    pub callee: HirCallee,
+    pub args: Vec<HirExpr>,
+}
+
+#[derive(Debug, Clone)]
+pub enum HirCallee {
+    /// A function called by name
+    Function(String),
+    /// A function pointer
+    Pointer(Box<HirExpr>),
 }
 
+/// A `case` label, or `default` when there is no value, and the
+/// statements up to the next label
 #[derive(Debug, Clone)]
-pub enum HirExpr {
+pub struct HirSwitchCase {
+    pub value: Option<i64>,
+    pub stmts: Vec<HirStmt>,
+    pub span: Span,
+}
+
+/// An expression together with its type and the source span it was
+/// lowered from. Type names are already resolved.
+#[derive(Debug, Clone)]
+pub struct HirExpr {
+    pub kind: HirExprKind,
+    pub ty: ast::Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum HirExprKind {
     IntLiteral(i64),
     FloatLiteral(f64),
     StringLiteral(String),
@@ -53,49 +157,124 @@ pub enum HirExpr {
     Variable(String),
     Binary(ast::BinaryOp, Box<HirExpr>, Box<HirExpr>),
     Unary(ast::UnaryOp, Box<HirExpr>),
-    Call(String, Vec<HirExpr>),
+    /// The address of a function
+    Function(String),
+    /// A call of a function that returns a value
+    Call(HirCall),
     Index(Box<HirExpr>, Box<HirExpr>),
     Field(Box<HirExpr>, String),
     Cast(ast::Type, Box<HirExpr>),
+    /// An implicit conversion of the operand to this expression's type
+    Convert(Box<HirExpr>),
     Assign(Box<HirExpr>, Box<HirExpr>),
+    CompoundAssign(ast::BinaryOp, Box<HirExpr>, Box<HirExpr>),
+    Ternary(Box<HirExpr>, Box<HirExpr>, Box<HirExpr>),
+}
+
+impl HirStmt {
+    pub fn new(kind: HirStmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl HirExpr {
+    pub fn new(kind: HirExprKind, ty: ast::Type, span: Span) -> Self {
+        Self { kind, ty, span }
+    }
+    
+    /// Whether this lvalue is reached through a pointer to volatile, so
+    /// every load and store of it has to happen as written
+    pub fn is_volatile(&self) -> bool {
+        let points_to_volatile = |ty: &ast::Type| matches!(
+            ty,
+            ast::Type::Pointer(_, qualifiers) if qualifiers.contains(&ast::TypeQualifier::Volatile)
+        );
+        match &self.kind {
+            HirExprKind::Unary(ast::UnaryOp::Deref, pointer) => points_to_volatile(&pointer.ty),
+            HirExprKind::Index(base, _) if matches!(base.ty, ast::Type::Array(..)) => base.is_volatile(),
+            HirExprKind::Index(base, _) => points_to_volatile(&base.ty),
+            HirExprKind::Field(base, _) => base.is_volatile(),
+            _ => false,
+        }
+    }
 }
 
+/// Lowers a type checked program, so missing names and mismatched
+/// types are treated as internal errors
 pub struct HirBuilder {
+    /// Struct, union, enum and typedef names and the fields of records
+    decls: Declarations,
+    functions: HashMap<String, (ast::Type, Vec<ast::Type>)>,
+    /// Functions declared with `...`
+    variadic: HashSet<String>,
+    globals: HashMap<String, ast::Type>,
+    /// Locals of the function being lowered, innermost block last
+    scopes: Vec<HashMap<String, ast::Type>>,
+    return_type: ast::Type,
 }
 
 impl HirBuilder {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            decls: Declarations::default(),
+            functions: builtin_functions(),
+            variadic: HashSet::new(),
+            globals: HashMap::new(),
+            scopes: Vec::new(),
+            return_type: ast::Type::Void,
+        }
+    }
+    
+    /// Use the sizes of `model`'s scalar types for `sizeof` and the
+    /// width of `isize` and `usize`
+    pub fn set_data_model(&mut self, model: DataModel) {
+        self.decls.model = model;
     }
     
-    pub fn lower_program(&mut self, program: &ast::Program) -> Result<HirProgram, String> {
+    pub fn lower_program(&mut self, program: &ast::Program) -> Result<HirProgram, FeraError> {
+        self.collect_declarations(program);
         let mut items = Vec::new();
         
         for item in &program.items {
             match item {
                 ast::Item::Function(f) => {
-                    if let Some(body) = &f.body {
-                        items.push(HirItem::Function(HirFunction {
-                            name: f.name.clone(),
-                            return_type: f.return_type.clone(),
-                            params: f.params.iter()
-                                .map(|p| (p.name.clone(), p.ty.clone()))
-                                .collect(),
-                            body: self.lower_block(body)?,
-                            linkage: f.linkage.clone(),
-                        }));
-                    }
+                    let params: Vec<_> = f.params.iter()
+                        .map(|p| (p.name.clone(), self.resolve(&p.ty)))
+                        .collect();
+                    self.scopes.push(params.iter().cloned().collect());
+                    self.return_type = self.resolve(&f.return_type);
+                    let body = f.body.as_ref().map(|body| self.lower_block(body)).transpose()?;
+                    self.scopes.pop();
+                    
+                    items.push(HirItem::Function(HirFunction {
+                        name: f.name.clone(),
+                        return_type: self.return_type.clone(),
+                        params,
+                        is_variadic: f.is_variadic,
+                        body,
+                        linkage: f.linkage.clone(),
+                        attributes: f.attributes.clone(),
+                        span: f.span.clone(),
+                    }));
+                }
+                ast::Item::Struct(s) => {
+                    items.push(HirItem::Struct(self.lower_record(&s.name, s.span.clone())));
+                }
+                ast::Item::Union(u) => {
+                    items.push(HirItem::Union(self.lower_record(&u.name, u.span.clone())));
                 }
                 ast::Item::GlobalVar(g) => {
+                    let ty = self.globals[&g.name].clone();
                     items.push(HirItem::Global(HirGlobal {
                         name: g.name.clone(),
-                        ty: g.ty.clone(),
-                        init: if let Some(init) = &g.init {
-                            Some(self.lower_expr(init)?)
-                        } else {
-                            None
+                        init: match &g.init {
+                            Some(init) => Some(constant::eval_constant(init, &ty, &self.decls, &self.globals, &self.functions)?),
+                            None => None,
                         },
+                        ty,
                         linkage: g.linkage.clone(),
+                        attributes: g.attributes.clone(),
+                        span: g.span.clone(),
                     }));
                 }
                 _ => {}
@@ -105,39 +284,107 @@ impl HirBuilder {
         Ok(HirProgram { items })
     }
     
-    fn lower_block(&mut self, block: &ast::Block) -> Result<HirBlock, String> {
+    /// Record every type name, record, function signature and global so
+    /// expressions can be typed regardless of declaration order
+    fn collect_declarations(&mut self, program: &ast::Program) {
+        for item in &program.items {
+            match item {
+                ast::Item::Struct(s) => {
+                    self.decls.types.insert(s.name.clone(), ast::Type::Struct(s.name.clone()));
+                }
+                ast::Item::Union(u) => {
+                    self.decls.types.insert(u.name.clone(), ast::Type::Union(u.name.clone()));
+                }
+                ast::Item::Enum(e) => {
+                    // Enums are lowered to the integer type backing them
+                    self.decls.types.insert(e.name.clone(), self.decls.backing_type(e));
+                }
+                ast::Item::TypeDef(td) => {
+                    let ty = self.resolve(&td.ty);
+                    self.decls.types.insert(td.name.clone(), ty);
+                }
+                _ => {}
+            }
+        }
+        
+        for item in &program.items {
+            match item {
+                ast::Item::Struct(ast::Struct { name, fields, attributes, .. })
+                | ast::Item::Union(ast::Union { name, fields, attributes, .. }) => {
+                    let fields = fields.iter()
+                        .map(|f| (f.name.clone(), self.resolve(&f.ty)))
+                        .collect();
+                    self.decls.declare_record(name, fields, attributes);
+                }
+                ast::Item::Enum(e) => {
+                    // The type checker has already reported invalid constants
+                    self.decls.declare_enum(e).ok();
+                }
+                ast::Item::Function(f) => {
+                    let params = f.params.iter().map(|p| self.resolve(&p.ty)).collect();
+                    self.functions.insert(f.name.clone(), (self.resolve(&f.return_type), params));
+                    if f.is_variadic {
+                        self.variadic.insert(f.name.clone());
+                    }
+                }
+                ast::Item::GlobalVar(g) => {
+                    let ty = constant::complete_type(&self.resolve(&g.ty), g.init.as_ref());
+                    self.globals.insert(g.name.clone(), ty);
+                }
+                _ => {}
+            }
+        }
+    }
+    
+    fn lower_record(&self, name: &str, span: Span) -> HirRecord {
+        HirRecord {
+            name: name.to_string(),
+            fields: self.decls.records.get(name).cloned().unwrap_or_default(),
+            packing: self.decls.packing.get(name).copied(),
+            span,
+        }
+    }
+    
+    fn resolve(&self, ty: &ast::Type) -> ast::Type {
+        resolve_type(ty, &self.decls.types)
+    }
+    
+    fn lower_block(&mut self, block: &ast::Block) -> Result<HirBlock, FeraError> {
+        self.scopes.push(HashMap::new());
         let mut stmts = Vec::new();
         
         for stmt in &block.stmts {
             stmts.push(self.lower_stmt(stmt)?);
         }
         
-        Ok(HirBlock { stmts })
+        self.scopes.pop();
+        Ok(HirBlock { stmts, span: block.span.clone() })
     }
     
-    fn lower_stmt(&mut self, stmt: &ast::Stmt) -> Result<HirStmt, String> {
-        match stmt {
+    fn lower_stmt(&mut self, stmt: &ast::Stmt) -> Result<HirStmt, FeraError> {
+        let kind = match stmt {
             ast::Stmt::Return(expr, _) => {
-                Ok(HirStmt::Return(if let Some(e) = expr {
-                    Some(self.lower_expr(e)?)
+                HirStmtKind::Return(if let Some(e) = expr {
+                    Some(convert(self.lower_expr(e)?, &self.return_type))
                 } else {
                     None
-                }))
+                })
             }
-            ast::Stmt::Expr(expr) => Ok(HirStmt::Expr(self.lower_expr(expr)?)),
+            ast::Stmt::Expr(expr) => self.lower_expr_stmt(expr)?,
             ast::Stmt::Let(l) => {
-                Ok(HirStmt::Let(
-                    l.name.clone(),
-                    l.ty.clone(),
-                    if let Some(init) = &l.init {
-                        Some(self.lower_expr(init)?)
-                    } else {
-                        None
-                    },
-                ))
+                let ty = self.resolve(&l.ty);
+                let init = if let Some(init) = &l.init {
+                    Some(convert(self.lower_expr(init)?, &ty))
+                } else {
+                    None
+                };
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(l.name.clone(), ty.clone());
+                }
+                HirStmtKind::Let(l.name.clone(), ty, init)
             }
             ast::Stmt::If(i) => {
-                Ok(HirStmt::If(
+                HirStmtKind::If(
                     self.lower_expr(&i.condition)?,
                     Box::new(self.lower_stmt(&i.then_branch)?),
                     if let Some(else_branch) = &i.else_branch {
@@ -145,54 +392,374 @@ impl HirBuilder {
                     } else {
                         None
                     },
-                ))
+                )
             }
             ast::Stmt::While(w) => {
-                Ok(HirStmt::While(
+                HirStmtKind::While(
                     self.lower_expr(&w.condition)?,
                     Box::new(self.lower_stmt(&w.body)?),
-                ))
+                )
             }
-            ast::Stmt::Block(b) => Ok(HirStmt::Block(self.lower_block(b)?)),
-            _ => Err("Statement lowering not fully implemented".to_string()),
-        }
+            ast::Stmt::DoWhile(d) => {
+                HirStmtKind::DoWhile(
+                    Box::new(self.lower_stmt(&d.body)?),
+                    self.lower_expr(&d.condition)?,
+                )
+            }
+            ast::Stmt::For(f) => {
+                self.scopes.push(HashMap::new());
+                let init = match &f.init {
+                    Some(init) => Some(Box::new(self.lower_stmt(init)?)),
+                    None => None,
+                };
+                let condition = f.condition.as_ref().map(|c| self.lower_expr(c)).transpose()?;
+                let increment = match &f.increment {
+                    Some(increment) => Some(Box::new(HirStmt::new(self.lower_expr_stmt(increment)?, increment.span()))),
+                    None => None,
+                };
+                let body = Box::new(self.lower_stmt(&f.body)?);
+                self.scopes.pop();
+                HirStmtKind::For(init, condition, increment, body)
+            }
+            ast::Stmt::Switch(switch) => {
+                let value = self.lower_expr(&switch.value)?;
+                let ty = promote(&value.ty);
+                let value = convert(value, &ty);
+                self.scopes.push(HashMap::new());
+                let mut cases = Vec::new();
+                for case in &switch.cases {
+                    let value = match &case.pattern {
+                        Some(pattern) => Some(constant::eval_int(pattern, &self.decls)?),
+                        None => None,
+                    };
+                    let stmts = case.stmts.iter().map(|s| self.lower_stmt(s)).collect::<Result<_, _>>()?;
+                    cases.push(HirSwitchCase { value, stmts, span: case.span.clone() });
+                }
+                self.scopes.pop();
+                HirStmtKind::Switch(value, cases)
+            }
+            ast::Stmt::Break(_) => HirStmtKind::Break,
+            ast::Stmt::Continue(_) => HirStmtKind::Continue,
+            ast::Stmt::Label(name, _) => HirStmtKind::Label(name.clone()),
+            ast::Stmt::Goto(name, _) => HirStmtKind::Goto(name.clone()),
+            ast::Stmt::Block(b) => HirStmtKind::Block(self.lower_block(b)?),
+            ast::Stmt::Asm(inline_asm) => HirStmtKind::Asm(self.lower_asm(inline_asm)?),
+        };
+        
+        Ok(HirStmt::new(kind, stmt.span()))
     }
     
-    fn lower_expr(&mut self, expr: &ast::Expr) -> Result<HirExpr, String> {
+    fn lower_asm(&mut self, inline_asm: &ast::InlineAsm) -> Result<HirAsm, FeraError> {
+        let mut outputs = Vec::new();
+        for operand in &inline_asm.outputs {
+            let constraint = asm::parse_constraint(operand, true, inline_asm.outputs.len())?;
+            outputs.push(HirAsmOperand { constraint, expr: self.lower_expr(&operand.expr)? });
+        }
+        let mut inputs = Vec::new();
+        for operand in &inline_asm.inputs {
+            let constraint = asm::parse_constraint(operand, false, outputs.len())?;
+            let expr = match constraint.kind {
+                asm::Kind::Immediate => {
+                    let value = constant::eval_int(&operand.expr, &self.decls)?;
+                    let ty = self.lower_expr(&operand.expr)?.ty;
+                    HirExpr::new(HirExprKind::IntLiteral(value), ty, operand.expr.span())
+                }
+                // A tied input lives where its output does, so it takes
+                // the output's type
+                asm::Kind::Tied(index) => convert(self.lower_expr(&operand.expr)?, &outputs[index].expr.ty),
+                asm::Kind::Register | asm::Kind::Memory => self.lower_expr(&operand.expr)?,
+            };
+            inputs.push(HirAsmOperand { constraint, expr });
+        }
+        
+        let basic = outputs.is_empty() && inputs.is_empty() && inline_asm.clobbers.is_empty();
+        let template = asm::llvm_template(&inline_asm.template, basic, outputs.len() + inputs.len(), &inline_asm.span)?;
+        Ok(HirAsm {
+            template,
+            side_effects: inline_asm.is_volatile || outputs.is_empty(),
+            outputs,
+            inputs,
+            clobbers: inline_asm.clobbers.clone(),
+        })
+    }
+
+    /// An expression whose value is discarded, the only place a void
+    /// call or a cast to void can appear
+    fn lower_expr_stmt(&mut self, expr: &ast::Expr) -> Result<HirStmtKind, FeraError> {
         match expr {
-            ast::Expr::IntLiteral(val, _) => Ok(HirExpr::IntLiteral(*val)),
-            ast::Expr::FloatLiteral(val, _) => Ok(HirExpr::FloatLiteral(*val)),
-            ast::Expr::StringLiteral(val, _) => Ok(HirExpr::StringLiteral(val.clone())),
-            ast::Expr::BoolLiteral(val, _) => Ok(HirExpr::BoolLiteral(*val)),
-            ast::Expr::Identifier(name, _) => Ok(HirExpr::Variable(name.clone())),
+            ast::Expr::Call(func, args, span) => {
+                let (call, ty) = self.lower_call(func, args)?;
+                Ok(match ty {
+                    ast::Type::Void => HirStmtKind::VoidCall(call),
+                    ty => HirStmtKind::Expr(HirExpr::new(HirExprKind::Call(call), ty, span.clone())),
+                })
+            }
+            // `(void)expr` only evaluates its operand
+            ast::Expr::Cast(ty, operand, _) if self.resolve(ty) == ast::Type::Void => self.lower_expr_stmt(operand),
+            expr => Ok(HirStmtKind::Expr(self.lower_expr(expr)?)),
+        }
+    }
+
+    /// A call and the type it returns
+    fn lower_call(&mut self, func: &ast::Expr, args: &[ast::Expr]) -> Result<(HirCall, ast::Type), FeraError> {
+        let callee = self.lower_expr(func)?;
+        let (ty, params) = function_signature(&callee.ty)
+            .map(|(ret, params, _)| (ret.clone(), params.to_vec()))
+            .ok_or_else(|| FeraError::codegen(format!("Cannot call '{}'", callee.ty), Some(func.span())))?;
+        let mut hir_args = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let arg = self.lower_expr(arg)?;
+            // Arguments past the parameters of a variadic function get
+            // the default argument promotions
+            let ty = params.get(i).cloned().unwrap_or_else(|| argument_promotion(&arg.ty));
+            hir_args.push(convert(arg, &ty));
+        }
+        // Functions called by name are called directly
+        let callee = match callee.kind {
+            HirExprKind::Function(name) => HirCallee::Function(name),
+            _ => HirCallee::Pointer(Box::new(callee)),
+        };
+        Ok((HirCall { callee, args: hir_args }, ty))
+    }
+
+    fn lower_expr(&mut self, expr: &ast::Expr) -> Result<HirExpr, FeraError> {
+        let (kind, ty) = match expr {
+            ast::Expr::IntLiteral(val, ty, _) => (HirExprKind::IntLiteral(*val), ty.clone()),
+            ast::Expr::FloatLiteral(val, _) => (HirExprKind::FloatLiteral(*val), ast::Type::F64),
+            ast::Expr::StringLiteral(val, _) => (
+                HirExprKind::StringLiteral(val.clone()),
+                ast::Type::Pointer(Box::new(ast::Type::Char), vec![ast::TypeQualifier::Const]),
+            ),
+            ast::Expr::CharLiteral(val, _) => (HirExprKind::IntLiteral(*val as i64), ast::Type::Char),
+            ast::Expr::BoolLiteral(val, _) => (HirExprKind::BoolLiteral(*val), ast::Type::Bool),
+            ast::Expr::Identifier(name, span) => {
+                let variable = self.scopes.iter().rev()
+                    .find_map(|scope| scope.get(name))
+                    .or_else(|| self.globals.get(name));
+                match (variable, self.functions.get(name), self.decls.constants.get(name)) {
+                    (Some(ty), ..) => (HirExprKind::Variable(name.clone()), ty.clone()),
+                    // A function's name stands for its address
+                    (None, Some((ret, params)), _) => (
+                        HirExprKind::Function(name.clone()),
+                        function_pointer(ret, params, self.variadic.contains(name)),
+                    ),
+                    // Enum constants are replaced by their value
+                    (None, None, Some((value, ty))) => (HirExprKind::IntLiteral(*value), ty.clone()),
+                    (None, None, None) => {
+                        return Err(FeraError::codegen(format!("Unknown variable: {}", name), Some(span.clone())));
+                    }
+                }
+            }
             ast::Expr::Binary(op, left, right, _) => {
-                Ok(HirExpr::Binary(
-                    op.clone(),
-                    Box::new(self.lower_expr(left)?),
-                    Box::new(self.lower_expr(right)?),
-                ))
-            }
-            ast::Expr::Unary(op, expr, _) => {
-                Ok(HirExpr::Unary(op.clone(), Box::new(self.lower_expr(expr)?)))
-            }
-            ast::Expr::Call(func, args, _) => {
-                if let ast::Expr::Identifier(name, _) = func.as_ref() {
-                    let hir_args = args.iter()
-                        .map(|a| self.lower_expr(a))
-                        .collect::<Result<Vec<_>, _>>()?;
-                    Ok(HirExpr::Call(name.clone(), hir_args))
-                } else {
-                    Err("Function call on non-identifier not supported".to_string())
+                let mut left = self.lower_expr(left)?;
+                let mut right = self.lower_expr(right)?;
+                let ty = match op {
+                    // Operands are only tested against zero
+                    ast::BinaryOp::LogicalAnd | ast::BinaryOp::LogicalOr => ast::Type::Bool,
+                    _ if is_arithmetic(&left.ty) && is_arithmetic(&right.ty) => {
+                        // The same types the type checker gives
+                        let operand_ty = operand_type(op, &left.ty, &right.ty, &self.decls.model);
+                        let ty = binary_result_type(op, &left.ty, &right.ty, &self.decls.model);
+                        left = convert(left, &operand_ty);
+                        right = convert(right, &operand_ty);
+                        ty
+                    }
+                    // A pointer compared with the null constant
+                    _ if is_comparison(op) => {
+                        if is_integer(&left.ty) {
+                            left = convert(left, &right.ty);
+                        } else if is_integer(&right.ty) {
+                            right = convert(right, &left.ty);
+                        }
+                        ast::Type::Bool
+                    }
+                    _ => match (pointee(&left.ty), pointee(&right.ty)) {
+                        (Some(_), Some(_)) => ast::Type::ISize,
+                        (Some(element), None) | (None, Some(element)) => ast::Type::Pointer(Box::new(element), vec![]),
+                        (None, None) => left.ty.clone(),
+                    },
+                };
+                (HirExprKind::Binary(op.clone(), Box::new(left), Box::new(right)), ty)
+            }
+            ast::Expr::Unary(op @ (ast::UnaryOp::Neg | ast::UnaryOp::BitNot), operand, _) => {
+                let operand = self.lower_expr(operand)?;
+                let ty = promote(&operand.ty);
+                (HirExprKind::Unary(op.clone(), Box::new(convert(operand, &ty))), ty)
+            }
+            ast::Expr::Unary(op, operand, _) => {
+                let operand = self.lower_expr(operand)?;
+                // `&f` and `*f` of a function are its address again
+                let is_function = matches!(operand.kind, HirExprKind::Function(_)) && *op == ast::UnaryOp::AddrOf;
+                if is_function || (*op == ast::UnaryOp::Deref && function_signature(&operand.ty).is_some()) {
+                    return Ok(operand);
                 }
+                let ty = match op {
+                    ast::UnaryOp::Deref => match &operand.ty {
+                        ast::Type::Pointer(pointee, _) => pointee.as_ref().clone(),
+                        ty => return Err(FeraError::codegen(format!("Cannot dereference '{}'", ty), Some(operand.span))),
+                    },
+                    ast::UnaryOp::AddrOf => ast::Type::Pointer(Box::new(operand.ty.clone()), vec![]),
+                    ast::UnaryOp::Not => ast::Type::Bool,
+                    _ => operand.ty.clone(),
+                };
+                (HirExprKind::Unary(op.clone(), Box::new(operand)), ty)
+            }
+            ast::Expr::Call(func, args, span) => {
+                let (call, ty) = self.lower_call(func, args)?;
+                if ty == ast::Type::Void {
+                    return Err(FeraError::codegen("A void call has no value", Some(span.clone())));
+                }
+                (HirExprKind::Call(call), ty)
+            }
+            ast::Expr::Index(base, index, span) => {
+                let base = self.lower_expr(base)?;
+                let index = self.lower_expr(index)?;
+                let ty = pointee(&base.ty).ok_or_else(|| FeraError::codegen(
+                    format!("Cannot index into '{}'", base.ty),
+                    Some(span.clone()),
+                ))?;
+                (HirExprKind::Index(Box::new(base), Box::new(index)), ty)
+            }
+            ast::Expr::Field(base, field, span) => {
+                let base = self.lower_expr(base)?;
+                let ty = match &base.ty {
+                    ast::Type::Struct(name) | ast::Type::Union(name) => self.decls.records.get(name)
+                        .and_then(|fields| fields.iter().find(|(f, _)| f == field))
+                        .map(|(_, ty)| ty.clone()),
+                    _ => None,
+                }.ok_or_else(|| FeraError::codegen(
+                    format!("No field '{}' on type '{}'", field, base.ty),
+                    Some(span.clone()),
+                ))?;
+                (HirExprKind::Field(Box::new(base), field.clone()), ty)
+            }
+            ast::Expr::Cast(ty, operand, span) => {
+                let ty = self.resolve(ty);
+                if ty == ast::Type::Void {
+                    return Err(FeraError::codegen("A cast to void has no value", Some(span.clone())));
+                }
+                let operand = self.lower_expr(operand)?;
+                (HirExprKind::Cast(ty.clone(), Box::new(operand)), ty)
             }
             ast::Expr::Assign(lhs, rhs, _) => {
-                Ok(HirExpr::Assign(
-                    Box::new(self.lower_expr(lhs)?),
-                    Box::new(self.lower_expr(rhs)?),
-                ))
+                let lhs = self.lower_expr(lhs)?;
+                let rhs = convert(self.lower_expr(rhs)?, &lhs.ty);
+                let ty = lhs.ty.clone();
+                (HirExprKind::Assign(Box::new(lhs), Box::new(rhs)), ty)
+            }
+            ast::Expr::CompoundAssign(op, lhs, rhs, _) => {
+                let lhs = self.lower_expr(lhs)?;
+                let mut rhs = self.lower_expr(rhs)?;
+                // The right operand takes the type the operation is done
+                // in, which the result is converted back from
+                if is_arithmetic(&lhs.ty) && is_arithmetic(&rhs.ty) {
+                    let operand_ty = operand_type(op, &lhs.ty, &rhs.ty, &self.decls.model);
+                    rhs = convert(rhs, &operand_ty);
+                }
+                let ty = lhs.ty.clone();
+                (HirExprKind::CompoundAssign(op.clone(), Box::new(lhs), Box::new(rhs)), ty)
+            }
+            ast::Expr::InitList(_, span) => {
+                return Err(FeraError::codegen("A braced list can only initialize a global", Some(span.clone())));
+            }
+            ast::Expr::Ternary(cond, then_expr, else_expr, _) => {
+                let cond = self.lower_expr(cond)?;
+                let then_expr = self.lower_expr(then_expr)?;
+                let else_expr = self.lower_expr(else_expr)?;
+                let ty = if is_arithmetic(&then_expr.ty) && is_arithmetic(&else_expr.ty) {
+                    arithmetic_conversion(&then_expr.ty, &else_expr.ty, &self.decls.model)
+                } else {
+                    then_expr.ty.clone()
+                };
+                let (then_expr, else_expr) = (convert(then_expr, &ty), convert(else_expr, &ty));
+                (HirExprKind::Ternary(Box::new(cond), Box::new(then_expr), Box::new(else_expr)), ty)
+            }
+            ast::Expr::Sizeof(ty, span) => {
+                let size = self.decls.layout(ty, span.clone())?.size;
+                (HirExprKind::IntLiteral(size as i64), ast::Type::USize)
             }
-            _ => Err("Expression lowering not fully implemented".to_string()),
+            ast::Expr::SizeofValue(operand, span) => {
+                // Only the operand's type matters; its code is dropped
+                let operand = self.lower_expr(operand)?;
+                let size = self.decls.layout(&operand.ty, span.clone())?.size;
+                (HirExprKind::IntLiteral(size as i64), ast::Type::USize)
+            }
+            ast::Expr::Alignof(ty, span) => {
+                let align = self.decls.layout(ty, span.clone())?.align;
+                (HirExprKind::IntLiteral(align as i64), ast::Type::USize)
+            }
+        };
+        
+        Ok(HirExpr::new(kind, ty, expr.span()))
+    }
+}
+
+/// Convert `expr` to `ty` where C converts implicitly: between arithmetic
+/// types, and from the null constant to a pointer. Integer literals take
+/// the new type directly. Anything else is left alone, as pointer
+/// conversions don't change the value.
+fn convert(expr: HirExpr, ty: &ast::Type) -> HirExpr {
+    let converts = match ty {
+        _ if expr.ty == *ty => false,
+        ast::Type::Pointer(..) => is_integer(&expr.ty),
+        _ => is_arithmetic(ty) && is_arithmetic(&expr.ty),
+    };
+    if !converts {
+        return expr;
+    }
+    
+    match expr.kind {
+        HirExprKind::IntLiteral(_) if (is_integer(ty) && *ty != ast::Type::Bool) || matches!(ty, ast::Type::Pointer(..)) => {
+            HirExpr { ty: ty.clone(), ..expr }
+        }
+        _ => {
+            let span = expr.span.clone();
+            HirExpr::new(HirExprKind::Convert(Box::new(expr)), ty.clone(), span)
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::types::TypeChecker;
+    
+    #[test]
+    fn test_types_match_the_type_checker() {
+        // Every variable has the type of its initializer, so the checker
+        // accepts it as is and lowering has nothing to convert
+        let source = "
+            enum Level : u8 { Low, High };
+            
+            i32 mix(Level level, u16 small, i64 wide, f32 real) {
+                bool _above = level > small;
+                bool _both = level && real;
+                bool _negated = !level;
+                u32 _sum = level + 1u;
+                i64 _product = level * wide;
+                f32 _scaled = real * small;
+                u8 _same = level;
+                i32 shifted = small << wide;
+                return shifted;
+            }
+        ";
+        let tokens = Lexer::new(source).tokenize();
+        let (program, errors) = Parser::new(tokens).parse_program();
+        assert!(errors.is_empty(), "unexpected parse errors: {:?}", errors);
+        let diagnostics = TypeChecker::new().check_program(&program);
+        assert!(diagnostics.is_empty(), "unexpected diagnostics: {:?}", diagnostics);
+        
+        let hir = HirBuilder::new().lower_program(&program).unwrap();
+        let Some(HirItem::Function(HirFunction { body: Some(body), .. })) = hir.items.last() else {
+            panic!("expected a function");
+        };
+        for stmt in &body.stmts {
+            if let HirStmtKind::Let(name, ty, Some(init)) = &stmt.kind {
+                assert_eq!(init.ty, *ty, "type of '{}'", name);
+                assert!(!matches!(init.kind, HirExprKind::Convert(_)), "'{}' is converted", name);
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 pub mod token;
 pub mod preprocessor;
+pub mod source_map;
 
 use logos::Logos;
 use std::fmt;
@@ -1,9 +1,127 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::source_map::{Expansion, ExpansionId, FileId, Origin, SourceMap, Span};
+
+/// Nesting depth at which `#include` is assumed to be recursive
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+/// Multi-character punctuators, longest first, used for maximal munch
+const PUNCTUATORS: &[&str] = &[
+    "<<=", ">>=", "...",
+    "<<", ">>", "&&", "||", "==", "!=", "<=", ">=", "+=", "-=", "*=", "/=",
+    "%=", "&=", "|=", "^=", "++", "--", "->", "##",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum PpKind {
+    Identifier,
+    Number,
+    Str,
+    Char,
+    Punct,
+    Newline,
+}
+
+#[derive(Debug, Clone)]
+struct PpToken {
+    kind: PpKind,
+    text: String,
+    space_before: bool,
+    /// Where the token was spelled
+    origin: Origin,
+    /// The macro expansion that produced the token, if any
+    expansion: Option<ExpansionId>,
+}
+
+impl PpToken {
+    fn new(kind: PpKind, text: impl Into<String>, origin: Origin) -> Self {
+        Self { kind, text: text.into(), space_before: false, origin, expansion: None }
+    }
+
+    fn newline() -> Self {
+        Self::new(PpKind::Newline, "\n", Origin { file: 0, span: 0..0 })
+    }
+
+    fn is_punct(&self, text: &str) -> bool {
+        self.kind == PpKind::Punct && self.text == text
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Macro {
+    Object {
+        body: Vec<PpToken>,
+        definition: Origin,
+    },
+    Function {
+        params: Vec<String>,
+        variadic: bool,
+        body: Vec<PpToken>,
+        definition: Origin,
+    },
+}
+
+impl Macro {
+    fn definition(&self) -> &Origin {
+        match self {
+            Macro::Object { definition, .. } | Macro::Function { definition, .. } => definition,
+        }
+    }
+}
+
+/// One `#if`/`#ifdef`/`#ifndef` group on the conditional stack
+struct Conditional {
+    /// Whether the region enclosing this group is emitted
+    parent_active: bool,
+    /// Whether the current branch is emitted
+    active: bool,
+    /// Whether some branch of this group has already been taken
+    taken: bool,
+    seen_else: bool,
+    line: usize,
+}
+
+/// A source line after backslash-newline splicing
+struct LogicalLine {
+    /// 1-based number of the first physical line
+    number: usize,
+    /// Byte offset of the line in its file
+    start: usize,
+    text: String,
+    /// Positions in `text` where a continuation was removed, with the
+    /// number of bytes removed there
+    splices: Vec<(usize, usize)>,
+}
+
+impl LogicalLine {
+    /// Byte offset in the file of byte `pos` of the logical line
+    fn file_offset(&self, pos: usize) -> usize {
+        let removed: usize = self.splices.iter()
+            .filter(|(at, _)| *at <= pos)
+            .map(|(_, len)| len)
+            .sum();
+        self.start + pos + removed
+    }
+
+    /// Tokenize `self.text[from..]`, locating tokens in `file`
+    fn tokenize(&self, from: usize, file: FileId) -> Vec<PpToken> {
+        tokenize(&self.text[from..], |span| Origin {
+            file,
+            span: self.file_offset(from + span.start)..self.file_offset(from + span.end),
+        })
+    }
+}
 
 pub struct Preprocessor {
-    defines: HashMap<String, String>,
+    defines: HashMap<String, Macro>,
     include_paths: Vec<PathBuf>,
+    once_files: HashSet<PathBuf>,
+    depth: usize,
+    map: SourceMap,
+    /// Pseudo-file holding macros defined through `add_define`
+    command_line: Option<FileId>,
 }
 
 impl Preprocessor {
@@ -11,61 +129,1006 @@ impl Preprocessor {
         Self {
             defines: HashMap::new(),
             include_paths: vec![PathBuf::from(".")],
+            once_files: HashSet::new(),
+            depth: 0,
+            map: SourceMap::new(),
+            command_line: None,
         }
     }
-    
+
+    /// Define an object-like macro, as if by `#define name value`
     pub fn add_define(&mut self, name: String, value: String) {
-        self.defines.insert(name, value);
+        let file = *self.command_line
+            .get_or_insert_with(|| self.map.add_file("<command line>".to_string(), String::new()));
+
+        let source = &mut self.map.file_mut(file).source;
+        let name_start = source.len();
+        let value_start = name_start + name.len() + 1;
+        source.push_str(&format!("{} {}\n", name, value));
+
+        let body = tokenize(&value, |span| Origin {
+            file,
+            span: value_start + span.start..value_start + span.end,
+        });
+        let definition = Origin { file, span: name_start..name_start + name.len() };
+        self.defines.insert(name, Macro::Object { body, definition });
     }
-    
+
     pub fn add_include_path(&mut self, path: PathBuf) {
         self.include_paths.push(path);
     }
-    
-    pub fn process(&self, source: &str) -> Result<String, String> {
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.defines.contains_key(name)
+    }
+
+    /// Mapping from offsets in the output back to the original files
+    pub fn source_map(&self) -> &SourceMap {
+        &self.map
+    }
+
+    pub fn into_source_map(self) -> SourceMap {
+        self.map
+    }
+
+    /// Preprocess a file on disk; quoted includes resolve relative to it
+    pub fn process_file(&mut self, path: &Path) -> Result<String, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file = self.map.add_file(path.display().to_string(), source.clone());
         let mut output = String::new();
-        let mut lines = source.lines();
-        
-        while let Some(line) = lines.next() {
-            if line.trim_start().starts_with('#') {
-                self.process_directive(line, &mut output)?;
-            } else {
-                output.push_str(&self.expand_macros(line));
+        self.process_source(&source, Some(path), file, &mut output)?;
+        Ok(output)
+    }
+
+    /// Preprocess an in-memory translation unit
+    pub fn process(&mut self, source: &str) -> Result<String, String> {
+        let file = self.map.add_file("<input>".to_string(), source.to_string());
+        let mut output = String::new();
+        self.process_source(source, None, file, &mut output)?;
+        Ok(output)
+    }
+
+    fn process_source(&mut self, source: &str, path: Option<&Path>, file: FileId, output: &mut String) -> Result<(), String> {
+        let text = strip_comments(source);
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut pending: Vec<PpToken> = Vec::new();
+
+        for line in logical_lines(&text) {
+            let active = conditionals.last().map(|c| c.active).unwrap_or(true);
+            let indent = line.text.len() - line.text.trim_start().len();
+
+            if line.text[indent..].starts_with('#') {
+                self.flush(&mut pending, output)?;
+                let tokens = line.tokenize(indent + 1, file);
+                self.process_directive(&tokens, path, line.number, &mut conditionals, output)
+                    .map_err(|e| format!("{}:{}: {}", display_name(path), line.number, e))?;
                 output.push('\n');
+            } else {
+                if active {
+                    pending.extend(line.tokenize(0, file));
+                }
+                pending.push(PpToken::newline());
+            }
+
+            for _ in 0..line.splices.len() {
+                pending.push(PpToken::newline());
             }
         }
-        
-        Ok(output)
+
+        self.flush(&mut pending, output)?;
+
+        if let Some(cond) = conditionals.last() {
+            return Err(format!("{}:{}: unterminated conditional directive", display_name(path), cond.line));
+        }
+
+        Ok(())
     }
-    
-    fn process_directive(&self, line: &str, output: &mut String) -> Result<(), String> {
-        let directive = line.trim_start().trim_start_matches('#').trim();
-        
-        if directive.starts_with("define") {
-            // Handle #define
-            Ok(())
-        } else if directive.starts_with("include") {
-            // Handle #include
-            Ok(())
-        } else if directive.starts_with("if") {
-            // Handle #if
-            Ok(())
-        } else if directive.starts_with("pragma") {
-            // Handle #pragma
-            Ok(())
-        } else {
-            Ok(())
+
+    /// Expand and emit the text lines collected since the last directive
+    fn flush(&mut self, pending: &mut Vec<PpToken>, output: &mut String) -> Result<(), String> {
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let expanded = self.expander().expand(pending, &mut Vec::new())?;
+        render(&expanded, output, Some(&mut self.map));
+        pending.clear();
+        Ok(())
+    }
+
+    fn expander(&mut self) -> Expander<'_> {
+        Expander { defines: &self.defines, map: &mut self.map }
+    }
+
+    fn process_directive(
+        &mut self,
+        tokens: &[PpToken],
+        path: Option<&Path>,
+        line: usize,
+        conditionals: &mut Vec<Conditional>,
+        output: &mut String,
+    ) -> Result<(), String> {
+        let active = conditionals.last().map(|c| c.active).unwrap_or(true);
+        let (name, args) = match tokens.split_first() {
+            Some((first, rest)) if first.kind == PpKind::Identifier => (first.text.as_str(), rest),
+            // The null directive `#` on its own line
+            None => return Ok(()),
+            Some(_) if !active => return Ok(()),
+            Some((first, _)) => return Err(format!("invalid preprocessing directive '#{}'", first.text)),
+        };
+
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let taken = active && match name {
+                    "ifdef" => self.is_defined(&expect_macro_name(args, name)?),
+                    "ifndef" => !self.is_defined(&expect_macro_name(args, name)?),
+                    _ => self.eval_condition(args)?,
+                };
+                conditionals.push(Conditional {
+                    parent_active: active,
+                    active: taken,
+                    taken,
+                    seen_else: false,
+                    line,
+                });
+                Ok(())
+            }
+            "elif" => {
+                let cond = conditionals.last_mut().ok_or("#elif without #if")?;
+                if cond.seen_else {
+                    return Err("#elif after #else".to_string());
+                }
+                if cond.parent_active && !cond.taken {
+                    let taken = self.eval_condition(args)?;
+                    cond.active = taken;
+                    cond.taken = taken;
+                } else {
+                    cond.active = false;
+                }
+                Ok(())
+            }
+            "else" => {
+                let cond = conditionals.last_mut().ok_or("#else without #if")?;
+                if cond.seen_else {
+                    return Err("#else after #else".to_string());
+                }
+                cond.seen_else = true;
+                cond.active = cond.parent_active && !cond.taken;
+                cond.taken = true;
+                Ok(())
+            }
+            "endif" => {
+                conditionals.pop().ok_or("#endif without #if")?;
+                Ok(())
+            }
+            // Everything below is ignored inside a skipped region
+            _ if !active => Ok(()),
+            "define" => self.define(args),
+            "undef" => {
+                let name = expect_macro_name(args, "undef")?;
+                self.defines.remove(&name);
+                Ok(())
+            }
+            "include" => self.include(args, path, output),
+            "pragma" => {
+                if args.first().is_some_and(|t| t.text == "once") {
+                    if let Some(path) = path {
+                        self.once_files.insert(canonical(path));
+                    }
+                }
+                // Unknown pragmas are ignored, as in C
+                Ok(())
+            }
+            "error" => Err(format!("#error {}", render_inline(args))),
+            "warning" => {
+                eprintln!("{}:{}: warning: {}", display_name(path), line, render_inline(args));
+                Ok(())
+            }
+            "line" => Ok(()),
+            _ => Err(format!("unknown preprocessing directive '#{}'", name)),
+        }
+    }
+
+    fn define(&mut self, args: &[PpToken]) -> Result<(), String> {
+        let name = expect_macro_name(args, "define")?;
+        let definition = args[0].origin.clone();
+        let rest = &args[1..];
+
+        if rest.iter().any(|t| t.is_punct("##")) {
+            return Err(format!("token pasting (##) is not supported in macro '{}'", name));
+        }
+
+        // Function-like only when '(' immediately follows the name
+        let is_function = rest.first().is_some_and(|t| t.is_punct("(") && !t.space_before);
+        if !is_function {
+            let body = trim_leading_space(rest);
+            self.defines.insert(name, Macro::Object { body, definition });
+            return Ok(());
+        }
+
+        let mut params = Vec::new();
+        let mut variadic = false;
+        let mut i = 1;
+        loop {
+            let token = rest.get(i).ok_or_else(|| format!("missing ')' in parameter list of macro '{}'", name))?;
+            i += 1;
+            match token.kind {
+                PpKind::Punct if token.text == ")" => break,
+                PpKind::Punct if token.text == "," && !params.is_empty() => {}
+                PpKind::Punct if token.text == "..." => variadic = true,
+                PpKind::Identifier if !variadic => {
+                    if params.contains(&token.text) {
+                        return Err(format!("duplicate macro parameter '{}'", token.text));
+                    }
+                    params.push(token.text.clone());
+                }
+                _ => return Err(format!("unexpected '{}' in parameter list of macro '{}'", token.text, name)),
+            }
+        }
+
+        let body = trim_leading_space(&rest[i..]);
+        for (j, token) in body.iter().enumerate() {
+            if token.is_punct("#") {
+                let operand = body.get(j + 1);
+                let is_param = operand.is_some_and(|t| params.contains(&t.text) || (variadic && t.text == "__VA_ARGS__"));
+                if !is_param {
+                    return Err(format!("'#' is not followed by a macro parameter in macro '{}'", name));
+                }
+            }
         }
+
+        self.defines.insert(name, Macro::Function { params, variadic, body, definition });
+        Ok(())
     }
-    
-    fn expand_macros(&self, line: &str) -> String {
-        let mut result = line.to_string();
-        
-        for (name, value) in &self.defines {
-            result = result.replace(name, value);
+
+    fn include(&mut self, args: &[PpToken], path: Option<&Path>, output: &mut String) -> Result<(), String> {
+        let (target, quoted) = match args.first() {
+            Some(t) if t.kind == PpKind::Str => (t.text[1..t.text.len() - 1].to_string(), true),
+            Some(t) if t.is_punct("<") => {
+                let close = args.iter().position(|t| t.is_punct(">"))
+                    .ok_or("missing '>' in #include")?;
+                let name: String = args[1..close].iter().map(|t| t.text.as_str()).collect();
+                (name, false)
+            }
+            _ => return Err("#include expects \"FILENAME\" or <FILENAME>".to_string()),
+        };
+
+        let resolved = self.resolve_include(&target, quoted, path)
+            .ok_or_else(|| format!("include file not found: {}", target))?;
+
+        if self.once_files.contains(&canonical(&resolved)) {
+            return Ok(());
+        }
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(format!("#include nested too deeply (at {})", resolved.display()));
         }
-        
+
+        let source = fs::read_to_string(&resolved)
+            .map_err(|e| format!("{}: {}", resolved.display(), e))?;
+        let file = self.map.add_file(resolved.display().to_string(), source.clone());
+
+        self.depth += 1;
+        let result = self.process_source(&source, Some(&resolved), file, output);
+        self.depth -= 1;
         result
     }
+
+    fn resolve_include(&self, target: &str, quoted: bool, path: Option<&Path>) -> Option<PathBuf> {
+        let current_dir = path.and_then(|f| f.parent()).map(Path::to_path_buf);
+
+        // Quoted includes look next to the including file first
+        let local = if quoted { current_dir } else { None };
+
+        local.iter()
+            .chain(self.include_paths.iter())
+            .map(|dir| dir.join(target))
+            .find(|candidate| candidate.is_file())
+    }
+
+    /// Evaluate the controlling expression of `#if`/`#elif`
+    fn eval_condition(&mut self, tokens: &[PpToken]) -> Result<bool, String> {
+        // `defined` must be resolved before macro expansion
+        let mut resolved = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            if token.kind == PpKind::Identifier && token.text == "defined" {
+                let parenthesized = tokens.get(i + 1).is_some_and(|t| t.is_punct("("));
+                let name_at = if parenthesized { i + 2 } else { i + 1 };
+                let name = tokens.get(name_at)
+                    .filter(|t| t.kind == PpKind::Identifier)
+                    .ok_or("operator 'defined' requires an identifier")?;
+                if parenthesized && !tokens.get(name_at + 1).is_some_and(|t| t.is_punct(")")) {
+                    return Err("missing ')' after 'defined'".to_string());
+                }
+                let value = if self.is_defined(&name.text) { "1" } else { "0" };
+                resolved.push(PpToken::new(PpKind::Number, value, token.origin.clone()));
+                i = if parenthesized { name_at + 2 } else { name_at + 1 };
+            } else {
+                resolved.push(token.clone());
+                i += 1;
+            }
+        }
+
+        let expanded = self.expander().expand(&resolved, &mut Vec::new())?;
+        if expanded.is_empty() {
+            return Err("#if with no expression".to_string());
+        }
+
+        let mut parser = CondParser { tokens: &expanded, pos: 0 };
+        let value = parser.ternary()?;
+        if let Some(extra) = parser.tokens.get(parser.pos) {
+            return Err(format!("unexpected '{}' in preprocessor expression", extra.text));
+        }
+        Ok(value != 0)
+    }
+}
+
+/// Macro expansion over the current definitions, recording each expansion
+/// in the source map
+struct Expander<'a> {
+    defines: &'a HashMap<String, Macro>,
+    map: &'a mut SourceMap,
+}
+
+impl<'a> Expander<'a> {
+    /// Fully macro-expand `tokens`, never re-expanding a macro named in `disabled`
+    fn expand(&mut self, tokens: &[PpToken], disabled: &mut Vec<String>) -> Result<Vec<PpToken>, String> {
+        let mut result = Vec::new();
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let token = &tokens[i];
+            i += 1;
+
+            let definition = match self.defines.get(&token.text) {
+                Some(def) if token.kind == PpKind::Identifier && !disabled.contains(&token.text) => def,
+                _ => {
+                    result.push(token.clone());
+                    continue;
+                }
+            };
+
+            let mut call_end = token.origin.span.end;
+            let (replacement, newlines) = match definition {
+                Macro::Object { body, .. } => {
+                    let id = self.record(token, definition, call_end);
+                    (mark_expansion(body, id), 0)
+                }
+                Macro::Function { params, variadic, body, .. } => {
+                    // A function-like macro name not followed by '(' is left alone
+                    let mut next = i;
+                    while tokens.get(next).is_some_and(|t| t.kind == PpKind::Newline) {
+                        next += 1;
+                    }
+                    if !tokens.get(next).is_some_and(|t| t.is_punct("(")) {
+                        result.push(token.clone());
+                        continue;
+                    }
+
+                    let (args, end, newlines) = collect_args(tokens, next + 1)
+                        .ok_or_else(|| format!("unterminated argument list invoking macro '{}'", token.text))?;
+                    i = end;
+
+                    let close = &tokens[end - 1];
+                    if close.origin.file == token.origin.file && close.expansion == token.expansion {
+                        call_end = close.origin.span.end;
+                    }
+
+                    let args = bind_args(token, params, *variadic, args)?;
+                    let id = self.record(token, definition, call_end);
+                    let body = mark_expansion(body, id);
+                    (self.substitute(&body, params, *variadic, &args, disabled)?, newlines)
+                }
+            };
+
+            disabled.push(token.text.clone());
+            let mut expanded = self.expand(&replacement, disabled)?;
+            disabled.pop();
+
+            if let Some(first) = expanded.first_mut() {
+                first.space_before = token.space_before;
+            }
+            result.extend(expanded);
+            result.extend((0..newlines).map(|_| PpToken::newline()));
+        }
+
+        Ok(result)
+    }
+
+    /// Register the invocation of `definition` at `token` in the source map
+    fn record(&mut self, token: &PpToken, definition: &Macro, call_end: usize) -> ExpansionId {
+        self.map.add_expansion(Expansion {
+            macro_name: token.text.clone(),
+            definition: definition.definition().clone(),
+            call_site: Origin {
+                file: token.origin.file,
+                span: token.origin.span.start..call_end,
+            },
+            parent: token.expansion,
+        })
+    }
+
+    /// Replace parameters in a function-like macro body with their arguments
+    fn substitute(
+        &mut self,
+        body: &[PpToken],
+        params: &[String],
+        variadic: bool,
+        args: &[Vec<PpToken>],
+        disabled: &mut Vec<String>,
+    ) -> Result<Vec<PpToken>, String> {
+        let param_index = |name: &str| {
+            params.iter().position(|p| p == name)
+                .or_else(|| (variadic && name == "__VA_ARGS__").then_some(params.len()))
+        };
+
+        let mut result = Vec::new();
+        let mut i = 0;
+        while i < body.len() {
+            let token = &body[i];
+
+            if token.is_punct("#") {
+                // Validated at definition time to be followed by a parameter
+                let operand = &body[i + 1];
+                let index = param_index(&operand.text).unwrap();
+                let mut stringized = token.clone();
+                stringized.kind = PpKind::Str;
+                stringized.text = stringize(&args[index]);
+                result.push(stringized);
+                i += 2;
+                continue;
+            }
+
+            match param_index(&token.text) {
+                Some(index) if token.kind == PpKind::Identifier => {
+                    let mut expanded = self.expand(&args[index], disabled)?;
+                    if let Some(first) = expanded.first_mut() {
+                        first.space_before = token.space_before;
+                    }
+                    result.extend(expanded);
+                }
+                _ => result.push(token.clone()),
+            }
+            i += 1;
+        }
+
+        Ok(result)
+    }
+}
+
+/// Recursive-descent evaluator for `#if` expressions over `i64`
+struct CondParser<'a> {
+    tokens: &'a [PpToken],
+    pos: usize,
+}
+
+impl<'a> CondParser<'a> {
+    fn next(&mut self) -> Option<&'a PpToken> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if self.tokens.get(self.pos).is_some_and(|t| t.is_punct(punct)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ternary(&mut self) -> Result<i64, String> {
+        let cond = self.binary(1)?;
+        if self.eat("?") {
+            let then_value = self.ternary()?;
+            if !self.eat(":") {
+                return Err("expected ':' in preprocessor expression".to_string());
+            }
+            let else_value = self.ternary()?;
+            Ok(if cond != 0 { then_value } else { else_value })
+        } else {
+            Ok(cond)
+        }
+    }
+
+    fn binary(&mut self, min_prec: u8) -> Result<i64, String> {
+        let mut lhs = self.unary()?;
+
+        while let Some(token) = self.tokens.get(self.pos) {
+            if token.kind != PpKind::Punct {
+                break;
+            }
+            let prec = match token.text.as_str() {
+                "*" | "/" | "%" => 10,
+                "+" | "-" => 9,
+                "<<" | ">>" => 8,
+                "<" | ">" | "<=" | ">=" => 7,
+                "==" | "!=" => 6,
+                "&" => 5,
+                "^" => 4,
+                "|" => 3,
+                "&&" => 2,
+                "||" => 1,
+                _ => break,
+            };
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(prec + 1)?;
+
+            lhs = match token.text.as_str() {
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err("division by zero in preprocessor expression".to_string()),
+                "/" => lhs.wrapping_div(rhs),
+                "%" => lhs.wrapping_rem(rhs),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "<" => (lhs < rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "&" => lhs & rhs,
+                "^" => lhs ^ rhs,
+                "|" => lhs | rhs,
+                "&&" => (lhs != 0 && rhs != 0) as i64,
+                _ => (lhs != 0 || rhs != 0) as i64,
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        if self.eat("!") {
+            return Ok((self.unary()? == 0) as i64);
+        }
+        if self.eat("~") {
+            return Ok(!self.unary()?);
+        }
+        if self.eat("-") {
+            return Ok(self.unary()?.wrapping_neg());
+        }
+        if self.eat("+") {
+            return self.unary();
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        let token = self.next().ok_or("unexpected end of preprocessor expression")?;
+
+        match token.kind {
+            PpKind::Number => parse_pp_integer(&token.text),
+            PpKind::Char => {
+                let inner = &token.text[1..token.text.len() - 1];
+                let value = match inner.strip_prefix('\\') {
+                    Some("n") => '\n',
+                    Some("t") => '\t',
+                    Some("r") => '\r',
+                    Some("0") => '\0',
+                    Some(escaped) => escaped.chars().next().unwrap_or('\\'),
+                    None => inner.chars().next().unwrap_or('\0'),
+                };
+                Ok(value as i64)
+            }
+            PpKind::Identifier if token.text == "true" => Ok(1),
+            // Identifiers left after expansion evaluate to zero
+            PpKind::Identifier => Ok(0),
+            PpKind::Punct if token.text == "(" => {
+                let value = self.ternary()?;
+                if !self.eat(")") {
+                    return Err("expected ')' in preprocessor expression".to_string());
+                }
+                Ok(value)
+            }
+            _ => Err(format!("unexpected '{}' in preprocessor expression", token.text)),
+        }
+    }
+}
+
+fn parse_pp_integer(text: &str) -> Result<i64, String> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let (radix, body) = match digits.get(..2) {
+        Some("0x") | Some("0X") => (16, &digits[2..]),
+        Some("0o") | Some("0O") => (8, &digits[2..]),
+        Some("0b") | Some("0B") => (2, &digits[2..]),
+        _ => (10, digits),
+    };
+    u64::from_str_radix(body, radix)
+        .map(|v| v as i64)
+        .map_err(|_| format!("invalid integer '{}' in preprocessor expression", text))
+}
+
+fn expect_macro_name(args: &[PpToken], directive: &str) -> Result<String, String> {
+    match args.first() {
+        Some(t) if t.kind == PpKind::Identifier => Ok(t.text.clone()),
+        _ => Err(format!("macro name missing in #{}", directive)),
+    }
+}
+
+/// Split the arguments of a macro invocation starting just after its '('.
+/// Returns the arguments, the index after the closing ')' and the number of
+/// line breaks swallowed by the invocation.
+fn collect_args(tokens: &[PpToken], start: usize) -> Option<(Vec<Vec<PpToken>>, usize, usize)> {
+    let mut args = vec![Vec::new()];
+    let mut depth = 0;
+    let mut newlines = 0;
+    let mut i = start;
+
+    loop {
+        let token = tokens.get(i)?;
+        i += 1;
+        match token.kind {
+            PpKind::Newline => {
+                newlines += 1;
+                continue;
+            }
+            PpKind::Punct if token.text == "(" => depth += 1,
+            PpKind::Punct if token.text == ")" => {
+                if depth == 0 {
+                    return Some((args, i, newlines));
+                }
+                depth -= 1;
+            }
+            PpKind::Punct if token.text == "," && depth == 0 => {
+                args.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        args.last_mut().unwrap().push(token.clone());
+    }
+}
+
+/// Match invocation arguments to parameters, folding extras into `__VA_ARGS__`
+fn bind_args(invocation: &PpToken, params: &[String], variadic: bool, mut args: Vec<Vec<PpToken>>) -> Result<Vec<Vec<PpToken>>, String> {
+    let name = &invocation.text;
+
+    // `F()` passes a single empty argument, which is zero arguments for `F()`
+    if params.is_empty() && args.len() == 1 && args[0].is_empty() {
+        args.clear();
+    }
+
+    if variadic {
+        if args.len() < params.len() {
+            return Err(format!("macro '{}' requires at least {} arguments, but {} given", name, params.len(), args.len()));
+        }
+        let rest = args.split_off(params.len());
+        let mut va_args = Vec::new();
+        for (i, arg) in rest.into_iter().enumerate() {
+            if i > 0 {
+                let origin = arg.first().unwrap_or(invocation).origin.clone();
+                va_args.push(PpToken::new(PpKind::Punct, ",", origin));
+            }
+            va_args.extend(arg);
+        }
+        args.push(va_args);
+    } else if args.len() != params.len() {
+        return Err(format!("macro '{}' requires {} arguments, but {} given", name, params.len(), args.len()));
+    }
+
+    Ok(args)
+}
+
+/// Copy a macro body, attributing every token to expansion `id`
+fn mark_expansion(body: &[PpToken], id: ExpansionId) -> Vec<PpToken> {
+    body.iter()
+        .map(|t| PpToken { expansion: Some(id), ..t.clone() })
+        .collect()
+}
+
+fn stringize(tokens: &[PpToken]) -> String {
+    let mut result = String::from("\"");
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && token.space_before {
+            result.push(' ');
+        }
+        if matches!(token.kind, PpKind::Str | PpKind::Char) {
+            for ch in token.text.chars() {
+                if ch == '"' || ch == '\\' {
+                    result.push('\\');
+                }
+                result.push(ch);
+            }
+        } else {
+            result.push_str(&token.text);
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn trim_leading_space(tokens: &[PpToken]) -> Vec<PpToken> {
+    let mut tokens = tokens.to_vec();
+    if let Some(first) = tokens.first_mut() {
+        first.space_before = false;
+    }
+    tokens
+}
+
+/// Whether two adjacent tokens would lex as a different token if printed
+/// without whitespace between them
+fn would_paste(prev: &PpToken, next: &PpToken) -> bool {
+    let word = |t: &PpToken| matches!(t.kind, PpKind::Identifier | PpKind::Number);
+    if word(prev) && word(next) {
+        return true;
+    }
+    if prev.kind != PpKind::Punct || next.kind != PpKind::Punct {
+        return false;
+    }
+    let joined = format!("{}{}", prev.text, next.text);
+    joined.starts_with("//") || joined.starts_with("/*") || PUNCTUATORS.iter()
+        .any(|p| p.len() > prev.text.len() && joined.starts_with(p))
+}
+
+/// Print tokens as source text, recording where each one came from
+fn render(tokens: &[PpToken], output: &mut String, mut map: Option<&mut SourceMap>) {
+    let mut prev: Option<&PpToken> = None;
+    for token in tokens {
+        if token.kind == PpKind::Newline {
+            output.push('\n');
+            prev = None;
+            continue;
+        }
+        if let Some(prev) = prev {
+            if token.space_before || would_paste(prev, token) {
+                output.push(' ');
+            }
+        }
+        let start = output.len();
+        output.push_str(&token.text);
+        if let Some(map) = map.as_deref_mut() {
+            map.add_segment(start..output.len(), token.origin.clone(), token.expansion);
+        }
+        prev = Some(token);
+    }
+}
+
+fn render_inline(tokens: &[PpToken]) -> String {
+    let mut output = String::new();
+    render(tokens, &mut output, None);
+    output
+}
+
+/// Split one line into preprocessing tokens; `locate` maps a byte range of
+/// `line` to its origin
+fn tokenize(line: &str, locate: impl Fn(Span) -> Origin) -> Vec<PpToken> {
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map_or(line.len(), |(offset, _)| *offset);
+    let mut tokens = Vec::new();
+    let mut space_before = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i].1;
+        let start = i;
+
+        let kind = if c.is_whitespace() {
+            space_before = true;
+            i += 1;
+            continue;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].1.is_ascii_alphanumeric() || chars[i].1 == '_') {
+                i += 1;
+            }
+            PpKind::Identifier
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|(_, d)| d.is_ascii_digit())) {
+            // pp-number: digits, letters, '.', and signed exponents
+            i += 1;
+            while i < chars.len() {
+                let d = chars[i].1;
+                let exponent_sign = matches!(d, '+' | '-') && matches!(chars[i - 1].1, 'e' | 'E' | 'p' | 'P');
+                if !(exponent_sign || d.is_ascii_alphanumeric() || d == '_' || d == '.') {
+                    break;
+                }
+                i += 1;
+            }
+            PpKind::Number
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            while i < chars.len() && chars[i].1 != c {
+                if chars[i].1 == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            if c == '"' { PpKind::Str } else { PpKind::Char }
+        } else {
+            let rest = &line[byte_at(i)..byte_at((i + 3).min(chars.len()))];
+            let len = PUNCTUATORS.iter()
+                .find(|p| rest.starts_with(*p))
+                .map_or(1, |p| p.len());
+            i += len;
+            PpKind::Punct
+        };
+
+        let span = byte_at(start)..byte_at(i);
+        tokens.push(PpToken {
+            kind,
+            text: line[span.clone()].to_string(),
+            space_before,
+            origin: locate(span),
+            expansion: None,
+        });
+        space_before = false;
+    }
+
+    tokens
 }
 
+/// Blank out comments, keeping every byte offset and line break intact and
+/// leaving string and character literals untouched
+fn strip_comments(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let blank = |output: &mut String, c: char| {
+        if c == '\n' {
+            output.push('\n');
+        } else {
+            output.push_str(&" ".repeat(c.len_utf8()));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                output.push(c);
+                while let Some(d) = chars.next() {
+                    output.push(d);
+                    if d == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            output.push(escaped);
+                        }
+                    } else if d == c || d == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                output.push(' ');
+                while let Some(d) = chars.next_if(|&d| d != '\n') {
+                    blank(&mut output, d);
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                output.push(' ');
+                let mut prev = '\0';
+                for d in chars.by_ref() {
+                    blank(&mut output, d);
+                    if prev == '*' && d == '/' {
+                        break;
+                    }
+                    prev = d;
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Join backslash-continued lines
+fn logical_lines(text: &str) -> Vec<LogicalLine> {
+    let mut lines = Vec::new();
+    let mut physical = text.split_inclusive('\n').enumerate().scan(0, |offset, (index, line)| {
+        let start = *offset;
+        *offset += line.len();
+        Some((index, start, line))
+    });
+
+    while let Some((index, start, line)) = physical.next() {
+        let mut logical = LogicalLine {
+            number: index + 1,
+            start,
+            text: String::new(),
+            splices: Vec::new(),
+        };
+        let mut current = line;
+        loop {
+            let content = current.trim_end_matches('\n').trim_end_matches('\r');
+            match content.strip_suffix('\\') {
+                Some(joined) => {
+                    logical.text.push_str(joined);
+                    let removed = current.len() - joined.len();
+                    logical.splices.push((logical.text.len(), removed));
+                    match physical.next() {
+                        Some((_, _, next)) => current = next,
+                        None => break,
+                    }
+                }
+                None => {
+                    logical.text.push_str(content);
+                    break;
+                }
+            }
+        }
+        lines.push(logical);
+    }
+
+    lines
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn display_name(path: Option<&Path>) -> String {
+    path.map_or_else(|| "<input>".to_string(), |f| f.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(source: &str) -> String {
+        Preprocessor::new().process(source).unwrap()
+    }
+
+    #[test]
+    fn test_object_and_function_macros() {
+        let output = preprocess("#define WIDTH 80\n#define MAX(a, b) ((a) > (b) ? (a) : (b))\ni32 x = MAX(WIDTH, 2);\n");
+        assert!(output.contains("i32 x = ((80) > (2) ? (80) : (2));"), "{}", output);
+    }
+
+    #[test]
+    fn test_macro_expansion_respects_identifier_boundaries() {
+        let output = preprocess("#define N 4\ni32 NN = N;\n");
+        assert!(output.contains("i32 NN = 4;"), "{}", output);
+    }
+
+    #[test]
+    fn test_self_referential_macro_is_not_reexpanded() {
+        let output = preprocess("#define foo foo + 1\ni32 x = foo;\n");
+        assert!(output.contains("i32 x = foo + 1;"), "{}", output);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let source = "#define A 2\n#if A > 1 && defined(A)\nyes\n#elif 1\nno\n#else\nno\n#endif\n#ifndef B\nundef_b\n#endif\n";
+        let output = preprocess(source);
+        assert!(output.contains("yes"));
+        assert!(output.contains("undef_b"));
+        assert!(!output.contains("no"));
+    }
+
+    #[test]
+    fn test_line_numbers_are_preserved() {
+        let output = preprocess("#define X 1\n/* a\n   b */\nX\n");
+        assert_eq!(output.lines().nth(3), Some("1"));
+    }
+
+    #[test]
+    fn test_undef_and_stringize() {
+        let output = preprocess("#define NAME(x) #x\n#define Y 1\n#undef Y\nNAME(hello world) Y\n");
+        assert!(output.contains("\"hello world\" Y"), "{}", output);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Preprocessor::new().process("#if 1\n").is_err());
+        assert!(Preprocessor::new().process("#endif\n").is_err());
+        assert!(Preprocessor::new().process("#error stop\n").is_err());
+        assert!(Preprocessor::new().process("#include \"does_not_exist.h\"\n").is_err());
+    }
+
+    #[test]
+    fn test_include_and_pragma_once() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("defs.h"), "#pragma once\n#define LIMIT 10\ni32 limit_value;\n").unwrap();
+        let main = dir.path().join("main.fera");
+        fs::write(&main, "#include \"defs.h\"\n#include \"defs.h\"\ni32 x = LIMIT;\n").unwrap();
+
+        let output = Preprocessor::new().process_file(&main).unwrap();
+        assert_eq!(output.matches("limit_value").count(), 1);
+        assert!(output.contains("i32 x = 10;"));
+    }
+}
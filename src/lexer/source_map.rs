@@ -0,0 +1,210 @@
+// Maps offsets in preprocessed output back to the files they were written in
+use std::ops::Range;
+
+pub type Span = Range<usize>;
+pub type FileId = usize;
+pub type ExpansionId = usize;
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+}
+
+/// A span of text in one of the original source files
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub file: FileId,
+    pub span: Span,
+}
+
+/// One macro invocation that contributed tokens to the output
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub macro_name: String,
+    /// Where the macro's name appears in its `#define`
+    pub definition: Origin,
+    /// The invocation, from the macro name to the closing ')'
+    pub call_site: Origin,
+    /// The expansion the invocation itself was produced by, if nested
+    pub parent: Option<ExpansionId>,
+}
+
+/// A run of output text that came from a single token
+#[derive(Debug, Clone)]
+struct Segment {
+    output: Span,
+    origin: Origin,
+    expansion: Option<ExpansionId>,
+}
+
+/// An output span translated back to where it was written
+#[derive(Debug, Clone, PartialEq)]
+pub struct MappedSpan {
+    pub origin: Origin,
+    pub expansion: Option<ExpansionId>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    segments: Vec<Segment>,
+    expansions: Vec<Expansion>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A map for source that was not preprocessed: offsets map to themselves
+    pub fn single(name: &str, source: &str) -> Self {
+        let mut map = Self::new();
+        let file = map.add_file(name.to_string(), source.to_string());
+        map.add_segment(0..source.len(), Origin { file, span: 0..source.len() }, None);
+        map
+    }
+
+    pub fn add_file(&mut self, name: String, source: String) -> FileId {
+        self.files.push(SourceFile { name, source });
+        self.files.len() - 1
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id]
+    }
+
+    pub(crate) fn file_mut(&mut self, id: FileId) -> &mut SourceFile {
+        &mut self.files[id]
+    }
+
+    pub fn add_expansion(&mut self, expansion: Expansion) -> ExpansionId {
+        self.expansions.push(expansion);
+        self.expansions.len() - 1
+    }
+
+    pub fn expansion(&self, id: ExpansionId) -> &Expansion {
+        &self.expansions[id]
+    }
+
+    /// Record that `output` was produced from `origin`. Segments must be
+    /// added in output order.
+    pub fn add_segment(&mut self, output: Span, origin: Origin, expansion: Option<ExpansionId>) {
+        self.segments.push(Segment { output, origin, expansion });
+    }
+
+    /// Translate a span of preprocessed output to the original source
+    pub fn lookup(&self, span: &Span) -> Option<MappedSpan> {
+        let first = self.segment_at(span.start)?;
+        let start = first.origin.span.start + (span.start.saturating_sub(first.output.start))
+            .min(first.origin.span.len());
+
+        let last = self.segment_at(span.end.saturating_sub(1).max(span.start))?;
+        let end = if last.origin.file == first.origin.file && last.expansion == first.expansion {
+            let offset = span.end.saturating_sub(last.output.start).min(last.origin.span.len());
+            (last.origin.span.start + offset).max(start)
+        } else {
+            // Spans crossing files or expansions collapse to their first token
+            first.origin.span.end.max(start)
+        };
+
+        Some(MappedSpan {
+            origin: Origin { file: first.origin.file, span: start..end },
+            expansion: first.expansion,
+        })
+    }
+
+    /// The expansions a span was produced through, innermost first
+    pub fn expansion_chain(&self, mut id: Option<ExpansionId>) -> Vec<&Expansion> {
+        let mut chain = Vec::new();
+        while let Some(current) = id {
+            let expansion = self.expansion(current);
+            chain.push(expansion);
+            id = expansion.parent;
+        }
+        chain
+    }
+
+    /// Where the user wrote the code behind `mapped`: the outermost macro
+    /// invocation for expanded tokens, otherwise the token itself
+    pub fn user_location(&self, mapped: &MappedSpan) -> Origin {
+        self.expansion_chain(mapped.expansion)
+            .last()
+            .map(|e| e.call_site.clone())
+            .unwrap_or_else(|| mapped.origin.clone())
+    }
+
+    /// 1-based line number of `offset` in `file`
+    pub fn line_number(&self, file: FileId, offset: usize) -> usize {
+        let source = &self.files[file].source;
+        source[..offset.min(source.len())].matches('\n').count() + 1
+    }
+
+    /// Render an origin as `file:line`
+    pub fn describe(&self, origin: &Origin) -> String {
+        format!("{}:{}", self.files[origin.file].name, self.line_number(origin.file, origin.span.start))
+    }
+
+    /// The segment containing `offset`, or the closest one before it
+    fn segment_at(&self, offset: usize) -> Option<&Segment> {
+        let index = self.segments.partition_point(|s| s.output.start <= offset);
+        self.segments.get(index.checked_sub(1)?)
+            .or_else(|| self.segments.first())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::preprocessor::Preprocessor;
+    use std::fs;
+
+    fn offset_of(text: &str, needle: &str) -> Span {
+        let start = text.find(needle).unwrap();
+        start..start + needle.len()
+    }
+
+    #[test]
+    fn test_single_file_is_identity() {
+        let map = SourceMap::single("main.fera", "i32 x = 1;");
+        let mapped = map.lookup(&(4..5)).unwrap();
+        assert_eq!(mapped.origin, Origin { file: 0, span: 4..5 });
+        assert_eq!(mapped.expansion, None);
+    }
+
+    #[test]
+    fn test_tokens_map_back_through_includes_and_macros() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("header.h"), "// header\n#define ZERO 0\n#define BAD(x) (x + ZERO)\ni32 from_header;\n").unwrap();
+        let main = dir.path().join("main.fera");
+        fs::write(&main, "#include \"header.h\"\n\ni32 value = BAD(y);\n").unwrap();
+
+        let mut preprocessor = Preprocessor::new();
+        let output = preprocessor.process_file(&main).unwrap();
+        let map = preprocessor.source_map();
+
+        // A token from the included header maps into the header
+        let mapped = map.lookup(&offset_of(&output, "from_header")).unwrap();
+        assert!(map.file(mapped.origin.file).name.ends_with("header.h"));
+        assert_eq!(map.describe(&mapped.origin).rsplit(':').next(), Some("4"));
+
+        // A token produced by a nested expansion reports the whole chain
+        let mapped = map.lookup(&offset_of(&output, "0")).unwrap();
+        let chain = map.expansion_chain(mapped.expansion);
+        let names: Vec<_> = chain.iter().map(|e| e.macro_name.as_str()).collect();
+        assert_eq!(names, ["ZERO", "BAD"]);
+        assert!(map.describe(&chain[1].definition).ends_with("header.h:3"));
+
+        // ...and its user-facing location is the invocation in main.fera
+        let call = map.user_location(&mapped);
+        assert_eq!(&map.file(call.file).source[call.span], "BAD(y)");
+
+        // Macro arguments keep the location they were written at
+        let mapped = map.lookup(&offset_of(&output, "y")).unwrap();
+        assert_eq!(map.describe(&mapped.origin).rsplit(':').next(), Some("3"));
+    }
+}
@@ -59,6 +59,8 @@ pub enum TokenKind {
     Asm,
     #[token("sizeof")]
     Sizeof,
+    #[token("alignof")]
+    Alignof,
     #[token("alignas")]
     Alignas,
     #[token("true")]
@@ -209,6 +211,8 @@ pub enum TokenKind {
     Comma,
     #[token(".")]
     Dot,
+    #[token("...")]
+    Ellipsis,
     #[token(":")]
     Colon,
     #[token("?")]
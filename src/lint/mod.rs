@@ -0,0 +1,149 @@
+// Lints - optional diagnostics whose level the user controls
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    UnreachableCode,
+    ImplicitNarrowing,
+    Shadowing,
+    MissingReturn,
+    SkippedInitialization,
+    NonExhaustiveSwitch,
+    Deprecated,
+    Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 10] = [
+        Lint::UnusedVariable,
+        Lint::UnusedParameter,
+        Lint::UnreachableCode,
+        Lint::ImplicitNarrowing,
+        Lint::Shadowing,
+        Lint::MissingReturn,
+        Lint::SkippedInitialization,
+        Lint::NonExhaustiveSwitch,
+        Lint::Deprecated,
+        Lint::Format,
+    ];
+
+    /// The name used on the command line and in `fera.toml`
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedParameter => "unused-parameter",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::ImplicitNarrowing => "implicit-narrowing",
+            Lint::Shadowing => "shadowing",
+            Lint::MissingReturn => "missing-return",
+            Lint::SkippedInitialization => "skipped-initialization",
+            Lint::NonExhaustiveSwitch => "non-exhaustive-switch",
+            Lint::Deprecated => "deprecated",
+            Lint::Format => "format",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+
+    pub fn default_level(self) -> LintLevel {
+        match self {
+            // Shadowing is common and usually deliberate, so it's opt-in
+            Lint::Shadowing => LintLevel::Allow,
+            _ => LintLevel::Warn,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl LintLevel {
+    pub fn from_name(name: &str) -> Option<LintLevel> {
+        match name {
+            "allow" => Some(LintLevel::Allow),
+            "warn" => Some(LintLevel::Warn),
+            "deny" => Some(LintLevel::Deny),
+            _ => None,
+        }
+    }
+
+    /// The command line flag that selects this level
+    pub fn flag(self) -> &'static str {
+        match self {
+            LintLevel::Allow => "-A",
+            LintLevel::Warn => "-W",
+            LintLevel::Deny => "-D",
+        }
+    }
+}
+
+/// The level every lint is reported at
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    levels: HashMap<Lint, LintLevel>,
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn level(&self, lint: Lint) -> LintLevel {
+        self.levels.get(&lint).copied().unwrap_or_else(|| lint.default_level())
+    }
+
+    pub fn set(&mut self, lint: Lint, level: LintLevel) {
+        self.levels.insert(lint, level);
+    }
+
+    /// Set the level of the lint called `name`, or of every lint for `all`
+    pub fn set_by_name(&mut self, name: &str, level: LintLevel) -> Result<(), String> {
+        if name == "all" {
+            for lint in Lint::ALL {
+                self.set(lint, level);
+            }
+            return Ok(());
+        }
+
+        let lint = Lint::from_name(name).ok_or_else(|| {
+            let known: Vec<_> = Lint::ALL.iter().map(|l| l.name()).collect();
+            format!("Unknown lint '{}' (expected one of: all, {})", name, known.join(", "))
+        })?;
+        self.set(lint, level);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let mut config = LintConfig::new();
+        assert_eq!(config.level(Lint::UnusedVariable), LintLevel::Warn);
+        assert_eq!(config.level(Lint::Shadowing), LintLevel::Allow);
+
+        config.set_by_name("all", LintLevel::Deny).unwrap();
+        config.set_by_name("unused-parameter", LintLevel::Allow).unwrap();
+        assert_eq!(config.level(Lint::Shadowing), LintLevel::Deny);
+        assert_eq!(config.level(Lint::UnusedParameter), LintLevel::Allow);
+
+        assert!(config.set_by_name("unused-everything", LintLevel::Warn).is_err());
+    }
+}
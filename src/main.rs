@@ -6,11 +6,14 @@ mod hir;
 mod codegen;
 mod cli;
 mod error;
+mod lint;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+use cli::FrontendArgs;
+
 #[derive(Parser)]
 #[command(name = "fera")]
 #[command(about = "Fera programming language compiler and toolchain", long_about = None)]
@@ -46,6 +49,9 @@ enum Commands {
         /// Optimization level (0-3)
         #[arg(short = 'O', default_value = "0")]
         opt_level: u8,
+        
+        #[command(flatten)]
+        frontend: FrontendArgs,
     },
     
     /// Build and run a Fera project or file
@@ -60,6 +66,9 @@ enum Commands {
         /// Arguments to pass to the program
         #[arg(last = true)]
         args: Vec<String>,
+        
+        #[command(flatten)]
+        frontend: FrontendArgs,
     },
     
     /// Run tests
@@ -82,6 +91,9 @@ enum Commands {
     Check {
         /// Input file or project directory
         input: PathBuf,
+        
+        #[command(flatten)]
+        frontend: FrontendArgs,
     },
     
     /// Remove build artifacts
@@ -103,11 +115,11 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
     
     match cli.command {
-        Commands::Build { input, release, output, target, link, opt_level } => {
-            cli::build::execute(input, release, output, target, link, opt_level)
+        Commands::Build { input, release, output, target, link, opt_level, frontend } => {
+            cli::build::execute(input, release, output, target, link, opt_level, frontend)
         }
-        Commands::Run { input, release, args } => {
-            cli::run::execute(input, release, args)
+        Commands::Run { input, release, args, frontend } => {
+            cli::run::execute(input, release, args, frontend)
         }
         Commands::Test { filter } => {
             cli::test::execute(filter)
@@ -115,8 +127,8 @@ fn main() -> Result<()> {
         Commands::Fmt { files, check } => {
             cli::fmt::execute(files, check)
         }
-        Commands::Check { input } => {
-            cli::check::execute(input)
+        Commands::Check { input, frontend } => {
+            cli::check::execute(input, frontend)
         }
         Commands::Clean { path } => {
             cli::clean::execute(path)
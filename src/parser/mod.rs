@@ -1,11 +1,29 @@
 use crate::ast::*;
+use crate::error::FeraError;
 use crate::lexer::token::{Token, TokenKind};
+use crate::types::attribute;
+use crate::types::constant::{self, Declarations};
+use crate::types::resolve_type;
+use std::collections::HashMap;
 use std::iter::Peekable;
 use std::vec::IntoIter;
 
 pub struct Parser {
     tokens: Peekable<IntoIter<Token>>,
     current: Option<Token>,
+    /// End offset of the most recently consumed token
+    prev_end: usize,
+    /// Number of unclosed `{` consumed so far
+    depth: usize,
+    errors: Vec<FeraError>,
+    /// Stop reporting after this many errors; 0 means no limit
+    error_limit: usize,
+    /// Set once the error limit cut parsing short
+    stopped: bool,
+    /// Types and records declared so far. Their names start a
+    /// declaration when they begin a statement, and constant expressions
+    /// such as array sizes can take their size.
+    decls: Declarations,
 }
 
 impl Parser {
@@ -13,21 +31,45 @@ impl Parser {
         let mut parser = Self {
             tokens: tokens.into_iter().peekable(),
             current: None,
+            prev_end: 0,
+            depth: 0,
+            errors: Vec::new(),
+            error_limit: 0,
+            stopped: false,
+            decls: Declarations::default(),
         };
         parser.advance();
         parser
     }
     
     fn advance(&mut self) -> Option<Token> {
+        if let Some(token) = &self.current {
+            self.prev_end = token.span.end;
+            match token.kind {
+                TokenKind::LeftBrace => self.depth += 1,
+                TokenKind::RightBrace => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
         self.current = self.tokens.next();
         self.current.clone()
     }
     
+    /// Start offset of the current token
+    fn start(&self) -> usize {
+        self.current.as_ref().map_or(self.prev_end, |t| t.span.start)
+    }
+    
+    /// Span from `start` to the end of the last consumed token
+    fn span_from(&self, start: usize) -> Span {
+        start..self.prev_end.max(start)
+    }
+    
     fn peek(&mut self) -> Option<&Token> {
         self.tokens.peek()
     }
     
-    fn expect(&mut self, kind: TokenKind) -> Result<Token, String> {
+    fn expect(&mut self, kind: TokenKind) -> Result<Token, FeraError> {
         if let Some(token) = &self.current {
             if token.kind == kind {
                 let token = token.clone();
@@ -35,38 +77,183 @@ impl Parser {
                 return Ok(token);
             }
         }
-        Err(format!("Expected {:?}, found {:?}", kind, self.current))
+        Err(self.unexpected(format!("{:?}", kind)))
+    }
+    
+    /// Error for a current token that doesn't fit the grammar
+    fn unexpected(&self, expected: impl Into<String>) -> FeraError {
+        let expected = expected.into();
+        match &self.current {
+            Some(token) if token.kind == TokenKind::Error => {
+                FeraError::lex(format!("Unrecognized token '{}'", token.text), token.span.clone())
+            }
+            Some(token) if token.kind != TokenKind::Eof => {
+                let found = format!("'{}'", token.text);
+                FeraError::parse(format!("Expected {}, found {}", expected, found), token.span.clone())
+                    .with_expected(expected, found)
+            }
+            _ => {
+                FeraError::parse(format!("Expected {}, found end of file", expected), self.prev_end..self.prev_end)
+                    .with_expected(expected, "end of file")
+            }
+        }
+    }
+    
+    pub fn set_error_limit(&mut self, limit: usize) {
+        self.error_limit = limit;
+    }
+    
+    /// Whether parsing stopped early because the error limit was hit
+    pub fn error_limit_reached(&self) -> bool {
+        self.stopped
     }
     
-    pub fn parse_program(&mut self) -> Result<Program, String> {
+    /// Parse the whole token stream. Syntax errors don't stop the parser:
+    /// it skips to the next statement or item and carries on, so the
+    /// program returned holds everything that could be parsed.
+    pub fn parse_program(&mut self) -> (Program, Vec<FeraError>) {
         let mut items = Vec::new();
         
-        while let Some(token) = &self.current {
-            if token.kind == TokenKind::Eof {
-                break;
+        while !self.at_eof() {
+            match self.parse_item() {
+                Ok(item) => items.push(item),
+                Err(error) => {
+                    self.record(error);
+                    self.synchronize_item();
+                }
             }
-            items.push(self.parse_item()?);
         }
         
-        Ok(Program { items })
+        (Program { items }, std::mem::take(&mut self.errors))
+    }
+    
+    fn at_eof(&self) -> bool {
+        self.current.as_ref().map(|t| t.kind == TokenKind::Eof).unwrap_or(true)
     }
     
-    fn parse_item(&mut self) -> Result<Item, String> {
-        let token = self.current.as_ref().ok_or("Unexpected EOF")?;
+    /// Keep a syntax error for the caller. Once the limit is reached the
+    /// rest of the input is dropped so parsing winds down quietly.
+    fn record(&mut self, error: FeraError) {
+        if self.stopped {
+            return;
+        }
+        self.errors.push(error);
+        if self.error_limit != 0 && self.errors.len() >= self.error_limit {
+            self.stopped = true;
+            while !self.at_eof() {
+                self.advance();
+            }
+        }
+    }
+    
+    /// Skip past the end of a broken statement: the next `;` or block at
+    /// the statement's own nesting level, stopping before the `}` that
+    /// closes the enclosing block. Always consumes a token unless at that
+    /// `}` or the end of input.
+    fn synchronize_stmt(&mut self, depth: usize) {
+        while let Some(token) = &self.current {
+            let kind = token.kind.clone();
+            if kind == TokenKind::Eof || (kind == TokenKind::RightBrace && self.depth <= depth) {
+                return;
+            }
+            self.advance();
+            if self.depth == depth && matches!(kind, TokenKind::Semicolon | TokenKind::RightBrace) {
+                return;
+            }
+        }
+    }
+    
+    /// Skip to the start of the next top-level item. Stops without
+    /// consuming only at keywords that begin an item, which every item
+    /// parser consumes, so recovery can't loop.
+    fn synchronize_item(&mut self) {
+        while let Some(token) = &self.current {
+            let kind = token.kind.clone();
+            if self.depth == 0 && matches!(kind, TokenKind::Eof | TokenKind::Export | TokenKind::Internal | TokenKind::Typedef) {
+                return;
+            }
+            self.advance();
+            if self.depth == 0 && matches!(kind, TokenKind::Semicolon | TokenKind::RightBrace) {
+                // `};` ends a struct or union
+                if kind == TokenKind::RightBrace && self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Semicolon) {
+                    self.advance();
+                }
+                return;
+            }
+        }
+    }
+    
+    fn parse_item(&mut self) -> Result<Item, FeraError> {
+        let attributes = self.parse_attributes()?;
+        let token = self.current.as_ref().ok_or_else(|| self.unexpected("item"))?;
         
+        let no_attributes = |item| match attribute::reject(&attributes, item) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        };
         match &token.kind {
-            TokenKind::Export | TokenKind::Internal => {
-                self.parse_function_or_global()
+            TokenKind::Struct => Ok(Item::Struct(self.parse_struct(attributes)?)),
+            TokenKind::Union => Ok(Item::Union(self.parse_union(attributes)?)),
+            TokenKind::Enum => {
+                no_attributes("an enum")?;
+                Ok(Item::Enum(self.parse_enum()?))
+            }
+            TokenKind::Typedef => {
+                no_attributes("a typedef")?;
+                Ok(Item::TypeDef(self.parse_typedef()?))
+            }
+            _ => self.parse_function_or_global(attributes),
+        }
+    }
+    
+    /// Any number of `[[name, name(args), ...]]` lists before an item
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>, FeraError> {
+        let mut attributes = Vec::new();
+        while self.current.as_ref().is_some_and(|t| t.kind == TokenKind::LeftBracket)
+            && self.peek().is_some_and(|t| t.kind == TokenKind::LeftBracket)
+        {
+            self.advance();
+            self.advance();
+            loop {
+                let start = self.start();
+                let name = self.expect(TokenKind::Identifier)?;
+                let mut args = Vec::new();
+                if self.current.as_ref().is_some_and(|t| t.kind == TokenKind::LeftParen) {
+                    self.advance();
+                    while !self.current.as_ref().is_some_and(|t| t.kind == TokenKind::RightParen) {
+                        args.push(self.parse_attribute_argument()?);
+                        if self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Comma) {
+                            self.advance();
+                        } else {
+                            break;
+                        }
+                    }
+                    self.expect(TokenKind::RightParen)?;
+                }
+                attributes.push(Attribute { name: name.text, args, span: self.span_from(start) });
+                
+                if self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Comma) {
+                    self.advance();
+                } else {
+                    break;
+                }
             }
-            TokenKind::Struct => Ok(Item::Struct(self.parse_struct()?)),
-            TokenKind::Union => Ok(Item::Union(self.parse_union()?)),
-            TokenKind::Enum => Ok(Item::Enum(self.parse_enum()?)),
-            TokenKind::Typedef => Ok(Item::TypeDef(self.parse_typedef()?)),
-            _ => self.parse_function_or_global(),
+            self.expect(TokenKind::RightBracket)?;
+            self.expect(TokenKind::RightBracket)?;
+        }
+        Ok(attributes)
+    }
+    
+    fn parse_attribute_argument(&mut self) -> Result<String, FeraError> {
+        match self.parse_primary()? {
+            Expr::StringLiteral(value, _) | Expr::Identifier(value, _) => Ok(value),
+            Expr::IntLiteral(value, _, _) => Ok(value.to_string()),
+            expr => Err(FeraError::parse("Attribute arguments must be literals or names", expr.span())),
         }
     }
     
-    fn parse_function_or_global(&mut self) -> Result<Item, String> {
+    fn parse_function_or_global(&mut self, attributes: Vec<Attribute>) -> Result<Item, FeraError> {
+        let start = self.start();
         let linkage = if let Some(token) = &self.current {
             match token.kind {
                 TokenKind::Export => {
@@ -84,14 +271,14 @@ impl Parser {
         };
         
         let return_type = self.parse_type()?;
-        let name_token = self.expect(TokenKind::Identifier)?;
+        let (name_token, return_type) = self.parse_declarator(return_type)?;
         let name = name_token.text.clone();
         
         if let Some(token) = &self.current {
             if token.kind == TokenKind::LeftParen {
                 // Function
                 self.advance(); // (
-                let params = self.parse_params()?;
+                let (params, is_variadic) = self.parse_params()?;
                 self.expect(TokenKind::RightParen)?;
                 
                 let body = if let Some(token) = &self.current {
@@ -110,16 +297,17 @@ impl Parser {
                     return_type,
                     name,
                     params,
+                    is_variadic,
                     body,
-                    attributes: Vec::new(),
-                    span: 0..0, // TODO: proper span tracking
+                    attributes,
+                    span: self.span_from(start),
                 }))
             } else {
                 // Global variable
                 let init = if let Some(token) = &self.current {
                     if token.kind == TokenKind::Equals {
                         self.advance();
-                        Some(self.parse_expr()?)
+                        Some(self.parse_initializer()?)
                     } else {
                         None
                     }
@@ -134,16 +322,41 @@ impl Parser {
                     ty: return_type,
                     name,
                     init,
-                    span: 0..0,
+                    attributes,
+                    span: self.span_from(start),
                 }))
             }
         } else {
-            Err("Unexpected EOF".to_string())
+            Err(self.unexpected("'(', '=' or ';'"))
         }
     }
     
-    fn parse_type(&mut self) -> Result<Type, String> {
-        let token = self.current.as_ref().ok_or("Expected type")?;
+    /// An expression or a braced list of initializers, which may end
+    /// with a comma
+    fn parse_initializer(&mut self) -> Result<Expr, FeraError> {
+        if !self.current.as_ref().is_some_and(|t| t.kind == TokenKind::LeftBrace) {
+            return self.parse_expr();
+        }
+        let start = self.start();
+        self.advance();
+        
+        let mut elements = Vec::new();
+        while !self.current.as_ref().is_some_and(|t| t.kind == TokenKind::RightBrace) {
+            elements.push(self.parse_initializer()?);
+            if self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        self.expect(TokenKind::RightBrace)?;
+        
+        Ok(Expr::InitList(elements, self.span_from(start)))
+    }
+    
+    fn parse_type(&mut self) -> Result<Type, FeraError> {
+        let qualifiers = self.parse_type_qualifiers();
+        let token = self.current.as_ref().ok_or_else(|| self.unexpected("type"))?;
         
         let base_type = match &token.kind {
             TokenKind::Void => { self.advance(); Type::Void }
@@ -167,6 +380,11 @@ impl Parser {
                 let name = self.expect(TokenKind::Identifier)?;
                 Type::Struct(name.text)
             }
+            TokenKind::Union => {
+                self.advance();
+                let name = self.expect(TokenKind::Identifier)?;
+                Type::Union(name.text)
+            }
             TokenKind::Enum => {
                 self.advance();
                 let name = self.expect(TokenKind::Identifier)?;
@@ -177,44 +395,160 @@ impl Parser {
                 self.advance();
                 Type::Named(name)
             }
-            _ => return Err(format!("Expected type, found {:?}", token.kind)),
+            _ => return Err(self.unexpected("type")),
         };
         
         // Handle pointer, array, etc.
-        self.parse_type_suffix(base_type)
+        self.parse_type_suffix(base_type, qualifiers)
     }
     
-    fn parse_type_suffix(&mut self, base: Type) -> Result<Type, String> {
-        if let Some(token) = &self.current {
+    /// The `*`s and `[N]`s after a base type. A pointer keeps the
+    /// qualifiers of what it points to, so in `volatile u32* const* p`
+    /// the `volatile` belongs to the inner pointer and the `const` to the
+    /// outer one. Qualifiers of the declared object itself aren't tracked.
+    fn parse_type_suffix(&mut self, base: Type, mut qualifiers: Vec<TypeQualifier>) -> Result<Type, FeraError> {
+        let mut ty = base;
+        qualifiers.extend(self.parse_type_qualifiers());
+        while let Some(token) = &self.current {
             match token.kind {
                 TokenKind::Star => {
                     self.advance();
-                    let qualifiers = self.parse_type_qualifiers();
-                    Ok(Type::Pointer(Box::new(base), qualifiers))
-                }
-                TokenKind::LeftBracket => {
-                    self.advance();
-                    let size = if let Some(token) = &self.current {
-                        if token.kind == TokenKind::RightBracket {
-                            None
-                        } else {
-                            // Parse size expression (simplified for now)
-                            self.advance();
-                            Some(0) // TODO: parse actual size
-                        }
-                    } else {
-                        None
-                    };
-                    self.expect(TokenKind::RightBracket)?;
-                    Ok(Type::Array(Box::new(base), size))
+                    ty = Type::Pointer(Box::new(ty), qualifiers);
+                    qualifiers = self.parse_type_qualifiers();
                 }
-                _ => Ok(base),
+                TokenKind::LeftBracket => return self.parse_array_dims(ty),
+                _ => break,
             }
-        } else {
-            Ok(base)
+        }
+        Ok(ty)
+    }
+    
+    /// Parse the `[N]` dimensions that may follow a type or a declared
+    /// name. `T x[2][3]` is an array of two arrays of three `T`.
+    fn parse_array_dims(&mut self, element: Type) -> Result<Type, FeraError> {
+        Ok(array_of(element, self.parse_dims()?))
+    }
+    
+    /// The lengths of `[N]` dimensions, `None` for `[]`
+    fn parse_dims(&mut self) -> Result<Vec<Option<usize>>, FeraError> {
+        let mut dims = Vec::new();
+        while self.current.as_ref().is_some_and(|t| t.kind == TokenKind::LeftBracket) {
+            self.advance();
+            if self.current.as_ref().is_some_and(|t| t.kind == TokenKind::RightBracket) {
+                dims.push(None);
+            } else {
+                let size = self.parse_expr()?;
+                let len = constant::eval_int(&size, &self.decls)?;
+                let len = usize::try_from(len)
+                    .map_err(|_| FeraError::parse("Array size cannot be negative", size.span()))?;
+                dims.push(Some(len));
+            }
+            self.expect(TokenKind::RightBracket)?;
+        }
+        Ok(dims)
+    }
+    
+    /// The name a declaration declares and its type: `T name[N]` or a
+    /// function pointer `T (*name)(params)`
+    fn parse_declarator(&mut self, base: Type) -> Result<(Token, Type), FeraError> {
+        match self.parse_optional_declarator(base)? {
+            (Some(name), ty) => Ok((name, ty)),
+            (None, _) => Err(self.unexpected(format!("{:?}", TokenKind::Identifier))),
+        }
+    }
+    
+    /// A type without a name, as in casts and `sizeof`: `T*`, `T[N]` or
+    /// `T (*)(params)`
+    fn parse_abstract_declarator(&mut self, base: Type) -> Result<Type, FeraError> {
+        match self.parse_optional_declarator(base)? {
+            (None, ty) => Ok(ty),
+            (Some(name), _) => Err(FeraError::parse(format!("Unexpected name '{}' in a type", name.text), name.span)),
         }
     }
     
+    /// A declarator whose name may be left out. In a function pointer
+    /// declarator the name and any array dimensions are inside the
+    /// parentheses: `T (*table[N])(params)` is an array of N pointers.
+    fn parse_optional_declarator(&mut self, base: Type) -> Result<(Option<Token>, Type), FeraError> {
+        let is_function_pointer = self.current.as_ref().is_some_and(|t| t.kind == TokenKind::LeftParen)
+            && self.peek().is_some_and(|t| t.kind == TokenKind::Star);
+        if !is_function_pointer {
+            let name = self.parse_optional_name();
+            return Ok((name, self.parse_array_dims(base)?));
+        }
+        
+        self.advance();
+        // As in `parse_type_suffix`, each pointer takes the qualifiers
+        // written before its `*`
+        let mut pointers = Vec::new();
+        let mut qualifiers = Vec::new();
+        while self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Star) {
+            self.advance();
+            pointers.push(std::mem::replace(&mut qualifiers, self.parse_type_qualifiers()));
+        }
+        let name = self.parse_optional_name();
+        let dims = self.parse_dims()?;
+        self.expect(TokenKind::RightParen)?;
+        self.expect(TokenKind::LeftParen)?;
+        let (params, variadic) = self.parse_param_types()?;
+        self.expect(TokenKind::RightParen)?;
+        
+        let function = Type::Function(Box::new(base), params, variadic);
+        let ty = pointers.into_iter().fold(function, |ty, qualifiers| Type::Pointer(Box::new(ty), qualifiers));
+        Ok((name, array_of(ty, dims)))
+    }
+    
+    fn parse_optional_name(&mut self) -> Option<Token> {
+        let name = self.current.clone().filter(|t| t.kind == TokenKind::Identifier)?;
+        self.advance();
+        Some(name)
+    }
+    
+    /// The parameter types of a function pointer, whose names are
+    /// optional, and whether they end in `...`
+    fn parse_param_types(&mut self) -> Result<(Vec<Type>, bool), FeraError> {
+        let mut params = Vec::new();
+        if self.at_void_params() {
+            self.advance();
+            return Ok((params, false));
+        }
+        while !self.current.as_ref().is_some_and(|t| t.kind == TokenKind::RightParen) {
+            if self.parse_ellipsis(params.is_empty())? {
+                return Ok((params, true));
+            }
+            let ty = self.parse_type()?;
+            params.push(match self.parse_optional_declarator(ty)? {
+                (_, Type::Array(element, _)) => Type::Pointer(element, vec![]),
+                (_, ty) => ty,
+            });
+            if self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        Ok((params, false))
+    }
+    
+    /// `(void)`, a parameter list without parameters
+    fn at_void_params(&mut self) -> bool {
+        self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Void)
+            && self.peek().is_some_and(|t| t.kind == TokenKind::RightParen)
+    }
+    
+    /// The `...` that ends the parameters of a variadic function. As in
+    /// C it has to follow at least one parameter.
+    fn parse_ellipsis(&mut self, first: bool) -> Result<bool, FeraError> {
+        let Some(token) = self.current.clone().filter(|t| t.kind == TokenKind::Ellipsis) else {
+            return Ok(false);
+        };
+        if first {
+            return Err(FeraError::parse("Expected a parameter before '...'", token.span));
+        }
+        self.advance();
+        Ok(true)
+    }
+    
     fn parse_type_qualifiers(&mut self) -> Vec<TypeQualifier> {
         let mut qualifiers = Vec::new();
         
@@ -239,21 +573,35 @@ impl Parser {
         qualifiers
     }
     
-    fn parse_params(&mut self) -> Result<Vec<Param>, String> {
+    /// The parameters of a function, and whether they end in `...`
+    fn parse_params(&mut self) -> Result<(Vec<Param>, bool), FeraError> {
         let mut params = Vec::new();
+        if self.at_void_params() {
+            self.advance();
+            return Ok((params, false));
+        }
         
         while let Some(token) = &self.current {
             if token.kind == TokenKind::RightParen {
                 break;
             }
+            if self.parse_ellipsis(params.is_empty())? {
+                return Ok((params, true));
+            }
             
+            let start = self.start();
             let ty = self.parse_type()?;
-            let name = self.expect(TokenKind::Identifier)?;
+            let (name, ty) = self.parse_declarator(ty)?;
+            // As in C, an array parameter is a pointer to its first element
+            let ty = match ty {
+                Type::Array(element, _) => Type::Pointer(element, vec![]),
+                ty => ty,
+            };
             
             params.push(Param {
                 ty,
                 name: name.text,
-                span: 0..0,
+                span: self.span_from(start),
             });
             
             if let Some(token) = &self.current {
@@ -265,12 +613,14 @@ impl Parser {
             }
         }
         
-        Ok(params)
+        Ok((params, false))
     }
     
-    fn parse_struct(&mut self) -> Result<Struct, String> {
+    fn parse_struct(&mut self, attributes: Vec<Attribute>) -> Result<Struct, FeraError> {
+        let start = self.start();
         self.expect(TokenKind::Struct)?;
         let name = self.expect(TokenKind::Identifier)?;
+        self.decls.types.insert(name.text.clone(), Type::Struct(name.text.clone()));
         self.expect(TokenKind::LeftBrace)?;
         
         let mut fields = Vec::new();
@@ -279,31 +629,35 @@ impl Parser {
                 break;
             }
             
+            let field_start = self.start();
             let ty = self.parse_type()?;
-            let name = self.expect(TokenKind::Identifier)?;
+            let (name, ty) = self.parse_declarator(ty)?;
             self.expect(TokenKind::Semicolon)?;
             
             fields.push(Field {
                 ty,
                 name: name.text,
-                span: 0..0,
+                span: self.span_from(field_start),
             });
         }
         
         self.expect(TokenKind::RightBrace)?;
         self.expect(TokenKind::Semicolon)?;
+        self.declare_record(&name.text, &fields, &attributes);
         
         Ok(Struct {
             name: name.text,
             fields,
-            attributes: Vec::new(),
-            span: 0..0,
+            attributes,
+            span: self.span_from(start),
         })
     }
     
-    fn parse_union(&mut self) -> Result<Union, String> {
+    fn parse_union(&mut self, attributes: Vec<Attribute>) -> Result<Union, FeraError> {
+        let start = self.start();
         self.expect(TokenKind::Union)?;
         let name = self.expect(TokenKind::Identifier)?;
+        self.decls.types.insert(name.text.clone(), Type::Union(name.text.clone()));
         self.expect(TokenKind::LeftBrace)?;
         
         let mut fields = Vec::new();
@@ -312,30 +666,44 @@ impl Parser {
                 break;
             }
             
+            let field_start = self.start();
             let ty = self.parse_type()?;
-            let name = self.expect(TokenKind::Identifier)?;
+            let (name, ty) = self.parse_declarator(ty)?;
             self.expect(TokenKind::Semicolon)?;
             
             fields.push(Field {
                 ty,
                 name: name.text,
-                span: 0..0,
+                span: self.span_from(field_start),
             });
         }
         
         self.expect(TokenKind::RightBrace)?;
         self.expect(TokenKind::Semicolon)?;
+        self.declare_record(&name.text, &fields, &attributes);
         
         Ok(Union {
             name: name.text,
             fields,
-            span: 0..0,
+            attributes,
+            span: self.span_from(start),
         })
     }
     
-    fn parse_enum(&mut self) -> Result<Enum, String> {
+    /// Remember the fields of a struct or union so later constant
+    /// expressions can lay it out
+    fn declare_record(&mut self, name: &str, fields: &[Field], attributes: &[Attribute]) {
+        let fields = fields.iter()
+            .map(|f| (f.name.clone(), resolve_type(&f.ty, &self.decls.types)))
+            .collect();
+        self.decls.declare_record(name, fields, attributes);
+    }
+    
+    fn parse_enum(&mut self) -> Result<Enum, FeraError> {
+        let start = self.start();
         self.expect(TokenKind::Enum)?;
         let name = self.expect(TokenKind::Identifier)?;
+        self.decls.types.insert(name.text.clone(), Type::Enum(name.text.clone()));
         
         let backing_type = if let Some(token) = &self.current {
             if token.kind == TokenKind::Colon {
@@ -356,6 +724,7 @@ impl Parser {
                 break;
             }
             
+            let variant_start = self.start();
             let variant_name = self.expect(TokenKind::Identifier)?;
             let value = if let Some(token) = &self.current {
                 if token.kind == TokenKind::Equals {
@@ -371,7 +740,7 @@ impl Parser {
             variants.push(EnumVariant {
                 name: variant_name.text,
                 value,
-                span: 0..0,
+                span: self.span_from(variant_start),
             });
             
             if let Some(token) = &self.current {
@@ -384,45 +753,61 @@ impl Parser {
         self.expect(TokenKind::RightBrace)?;
         self.expect(TokenKind::Semicolon)?;
         
-        Ok(Enum {
+        let e = Enum {
             name: name.text,
             backing_type,
             variants,
-            span: 0..0,
-        })
+            span: self.span_from(start),
+        };
+        // Constants are needed for array sizes; the type checker reports
+        // the ones that are invalid
+        self.decls.declare_enum(&e).ok();
+        Ok(e)
     }
     
-    fn parse_typedef(&mut self) -> Result<TypeDef, String> {
+    fn parse_typedef(&mut self) -> Result<TypeDef, FeraError> {
+        let start = self.start();
         self.expect(TokenKind::Typedef)?;
         let ty = self.parse_type()?;
-        let name = self.expect(TokenKind::Identifier)?;
+        let (name, ty) = self.parse_declarator(ty)?;
         self.expect(TokenKind::Semicolon)?;
+        self.decls.types.insert(name.text.clone(), resolve_type(&ty, &self.decls.types));
         
         Ok(TypeDef {
             name: name.text,
             ty,
-            span: 0..0,
+            span: self.span_from(start),
         })
     }
     
-    fn parse_block(&mut self) -> Result<Block, String> {
+    fn parse_block(&mut self) -> Result<Block, FeraError> {
+        let start = self.start();
         self.expect(TokenKind::LeftBrace)?;
         
         let mut stmts = Vec::new();
         while let Some(token) = &self.current {
-            if token.kind == TokenKind::RightBrace {
+            if matches!(token.kind, TokenKind::RightBrace | TokenKind::Eof) {
                 break;
             }
-            stmts.push(self.parse_stmt()?);
+            
+            let depth = self.depth;
+            match self.parse_stmt() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(error) => {
+                    self.record(error);
+                    self.synchronize_stmt(depth);
+                }
+            }
         }
         
         self.expect(TokenKind::RightBrace)?;
         
-        Ok(Block { stmts, span: 0..0 })
+        Ok(Block { stmts, span: self.span_from(start) })
     }
     
-    fn parse_stmt(&mut self) -> Result<Stmt, String> {
-        let token = self.current.as_ref().ok_or("Expected statement")?;
+    fn parse_stmt(&mut self) -> Result<Stmt, FeraError> {
+        let start = self.start();
+        let token = self.current.as_ref().ok_or_else(|| self.unexpected("statement"))?;
         
         match &token.kind {
             TokenKind::Return => {
@@ -437,20 +822,34 @@ impl Parser {
                     None
                 };
                 self.expect(TokenKind::Semicolon)?;
-                Ok(Stmt::Return(value, 0..0))
+                Ok(Stmt::Return(value, self.span_from(start)))
             }
             TokenKind::If => Ok(Stmt::If(self.parse_if()?)),
             TokenKind::While => Ok(Stmt::While(self.parse_while()?)),
             TokenKind::For => Ok(Stmt::For(self.parse_for()?)),
+            TokenKind::Do => Ok(Stmt::DoWhile(self.parse_do_while()?)),
+            TokenKind::Switch => Ok(Stmt::Switch(self.parse_switch()?)),
             TokenKind::Break => {
                 self.advance();
                 self.expect(TokenKind::Semicolon)?;
-                Ok(Stmt::Break(0..0))
+                Ok(Stmt::Break(self.span_from(start)))
             }
             TokenKind::Continue => {
                 self.advance();
                 self.expect(TokenKind::Semicolon)?;
-                Ok(Stmt::Continue(0..0))
+                Ok(Stmt::Continue(self.span_from(start)))
+            }
+            TokenKind::Goto => {
+                self.advance();
+                let label = self.expect(TokenKind::Identifier)?;
+                self.expect(TokenKind::Semicolon)?;
+                Ok(Stmt::Goto(label.text, self.span_from(start)))
+            }
+            TokenKind::Asm => Ok(Stmt::Asm(self.parse_asm()?)),
+            TokenKind::Identifier if self.tokens.peek().is_some_and(|t| t.kind == TokenKind::Colon) => {
+                let label = self.expect(TokenKind::Identifier)?;
+                self.expect(TokenKind::Colon)?;
+                Ok(Stmt::Label(label.text, self.span_from(start)))
             }
             TokenKind::LeftBrace => Ok(Stmt::Block(self.parse_block()?)),
             _ => {
@@ -467,21 +866,13 @@ impl Parser {
     }
     
     fn is_type_start(&self) -> bool {
-        if let Some(token) = &self.current {
-            matches!(token.kind, 
-                TokenKind::Void | TokenKind::Bool | TokenKind::Char | TokenKind::Int |
-                TokenKind::I8 | TokenKind::I16 | TokenKind::I32 | TokenKind::I64 | TokenKind::ISize |
-                TokenKind::U8 | TokenKind::U16 | TokenKind::U32 | TokenKind::U64 | TokenKind::USize |
-                TokenKind::F32 | TokenKind::F64 | TokenKind::Struct | TokenKind::Enum
-            )
-        } else {
-            false
-        }
+        self.current.as_ref().is_some_and(|token| starts_type(token, &self.decls.types))
     }
     
-    fn parse_let_stmt(&mut self) -> Result<Stmt, String> {
+    fn parse_let_stmt(&mut self) -> Result<Stmt, FeraError> {
+        let start = self.start();
         let ty = self.parse_type()?;
-        let name = self.expect(TokenKind::Identifier)?;
+        let (name, ty) = self.parse_declarator(ty)?;
         
         let init = if let Some(token) = &self.current {
             if token.kind == TokenKind::Equals {
@@ -500,11 +891,12 @@ impl Parser {
             ty,
             name: name.text,
             init,
-            span: 0..0,
+            span: self.span_from(start),
         }))
     }
     
-    fn parse_if(&mut self) -> Result<If, String> {
+    fn parse_if(&mut self) -> Result<If, FeraError> {
+        let start = self.start();
         self.expect(TokenKind::If)?;
         self.expect(TokenKind::LeftParen)?;
         let condition = self.parse_expr()?;
@@ -527,26 +919,95 @@ impl Parser {
             condition,
             then_branch,
             else_branch,
-            span: 0..0,
+            span: self.span_from(start),
         })
     }
     
-    fn parse_while(&mut self) -> Result<While, String> {
+    fn parse_while(&mut self) -> Result<While, FeraError> {
+        let start = self.start();
         self.expect(TokenKind::While)?;
         self.expect(TokenKind::LeftParen)?;
         let condition = self.parse_expr()?;
         self.expect(TokenKind::RightParen)?;
         let body = Box::new(self.parse_stmt()?);
         
-        Ok(While { condition, body, span: 0..0 })
+        Ok(While { condition, body, span: self.span_from(start) })
+    }
+    
+    fn parse_do_while(&mut self) -> Result<DoWhile, FeraError> {
+        let start = self.start();
+        self.expect(TokenKind::Do)?;
+        let body = Box::new(self.parse_stmt()?);
+        self.expect(TokenKind::While)?;
+        self.expect(TokenKind::LeftParen)?;
+        let condition = self.parse_expr()?;
+        self.expect(TokenKind::RightParen)?;
+        self.expect(TokenKind::Semicolon)?;
+        
+        Ok(DoWhile { body, condition, span: self.span_from(start) })
+    }
+    
+    /// Parse a `switch`. Each `case` or `default` label owns the
+    /// statements up to the next label; control falls through from one
+    /// label's statements into the next.
+    fn parse_switch(&mut self) -> Result<Switch, FeraError> {
+        let start = self.start();
+        self.expect(TokenKind::Switch)?;
+        self.expect(TokenKind::LeftParen)?;
+        let value = self.parse_expr()?;
+        self.expect(TokenKind::RightParen)?;
+        self.expect(TokenKind::LeftBrace)?;
+        
+        let mut cases = Vec::new();
+        while let Some(token) = &self.current {
+            let case_start = self.start();
+            let pattern = match token.kind {
+                TokenKind::Case => {
+                    self.advance();
+                    Some(self.parse_expr()?)
+                }
+                TokenKind::Default => {
+                    self.advance();
+                    None
+                }
+                TokenKind::RightBrace | TokenKind::Eof => break,
+                _ => return Err(self.unexpected("'case' or 'default'")),
+            };
+            self.expect(TokenKind::Colon)?;
+            
+            let mut stmts = Vec::new();
+            while let Some(token) = &self.current {
+                if matches!(token.kind, TokenKind::Case | TokenKind::Default | TokenKind::RightBrace | TokenKind::Eof) {
+                    break;
+                }
+                
+                let depth = self.depth;
+                match self.parse_stmt() {
+                    Ok(stmt) => stmts.push(stmt),
+                    Err(error) => {
+                        self.record(error);
+                        self.synchronize_stmt(depth);
+                    }
+                }
+            }
+            
+            cases.push(SwitchCase { pattern, stmts, span: self.span_from(case_start) });
+        }
+        
+        self.expect(TokenKind::RightBrace)?;
+        
+        Ok(Switch { value, cases, span: self.span_from(start) })
     }
     
-    fn parse_for(&mut self) -> Result<For, String> {
+    fn parse_for(&mut self) -> Result<For, FeraError> {
+        let start = self.start();
         self.expect(TokenKind::For)?;
         self.expect(TokenKind::LeftParen)?;
         
+        // A declaration or expression statement consumes its own `;`
         let init = if let Some(token) = &self.current {
             if token.kind == TokenKind::Semicolon {
+                self.advance();
                 None
             } else {
                 Some(Box::new(self.parse_stmt()?))
@@ -584,29 +1045,121 @@ impl Parser {
             condition,
             increment,
             body,
-            span: 0..0,
+            span: self.span_from(start),
         })
     }
     
-    fn parse_expr(&mut self) -> Result<Expr, String> {
+    /// `asm [volatile] ("template" [: outputs [: inputs [: clobbers]]]);`
+    fn parse_asm(&mut self) -> Result<InlineAsm, FeraError> {
+        let start = self.start();
+        self.expect(TokenKind::Asm)?;
+        let is_volatile = self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Volatile);
+        if is_volatile {
+            self.advance();
+        }
+        self.expect(TokenKind::LeftParen)?;
+        let template = self.parse_asm_string()?;
+        
+        let mut sections = [Vec::new(), Vec::new()];
+        let mut clobbers = Vec::new();
+        let mut section = 0;
+        while self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Colon) && section < 3 {
+            self.advance();
+            if section < 2 {
+                sections[section] = self.parse_asm_operands()?;
+            } else {
+                while self.current.as_ref().is_some_and(|t| t.kind == TokenKind::StringLiteral) {
+                    clobbers.push(self.parse_asm_string()?);
+                    if self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Comma) {
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+            }
+            section += 1;
+        }
+        self.expect(TokenKind::RightParen)?;
+        self.expect(TokenKind::Semicolon)?;
+        
+        let [outputs, inputs] = sections;
+        Ok(InlineAsm { template, outputs, inputs, clobbers, is_volatile, span: self.span_from(start) })
+    }
+    
+    /// A comma separated list of `"constraint"(expr)`, possibly empty
+    fn parse_asm_operands(&mut self) -> Result<Vec<AsmOperand>, FeraError> {
+        let mut operands = Vec::new();
+        while self.current.as_ref().is_some_and(|t| t.kind == TokenKind::StringLiteral) {
+            let start = self.start();
+            let constraint = self.parse_asm_string()?;
+            self.expect(TokenKind::LeftParen)?;
+            let expr = self.parse_expr()?;
+            self.expect(TokenKind::RightParen)?;
+            operands.push(AsmOperand { constraint, expr, span: self.span_from(start) });
+            
+            if self.current.as_ref().is_some_and(|t| t.kind == TokenKind::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        Ok(operands)
+    }
+    
+    /// One or more adjacent string literals, joined as in C
+    fn parse_asm_string(&mut self) -> Result<String, FeraError> {
+        if !self.current.as_ref().is_some_and(|t| t.kind == TokenKind::StringLiteral) {
+            return Err(self.unexpected("string literal"));
+        }
+        let mut text = String::new();
+        while self.current.as_ref().is_some_and(|t| t.kind == TokenKind::StringLiteral) {
+            let Expr::StringLiteral(value, _) = self.parse_primary()? else { unreachable!() };
+            text.push_str(&value);
+        }
+        Ok(text)
+    }
+    
+    fn parse_expr(&mut self) -> Result<Expr, FeraError> {
         self.parse_assignment()
     }
     
-    fn parse_assignment(&mut self) -> Result<Expr, String> {
+    fn parse_assignment(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
         let expr = self.parse_ternary()?;
         
         if let Some(token) = &self.current {
             if token.kind == TokenKind::Equals {
                 self.advance();
                 let rhs = self.parse_assignment()?;
-                return Ok(Expr::Assign(Box::new(expr), Box::new(rhs), 0..0));
+                return Ok(Expr::Assign(Box::new(expr), Box::new(rhs), self.span_from(start)));
+            }
+            
+            let op = match token.kind {
+                TokenKind::PlusEquals => Some(BinaryOp::Add),
+                TokenKind::MinusEquals => Some(BinaryOp::Sub),
+                TokenKind::StarEquals => Some(BinaryOp::Mul),
+                TokenKind::SlashEquals => Some(BinaryOp::Div),
+                TokenKind::PercentEquals => Some(BinaryOp::Mod),
+                TokenKind::AmpersandEquals => Some(BinaryOp::BitAnd),
+                TokenKind::PipeEquals => Some(BinaryOp::BitOr),
+                TokenKind::CaretEquals => Some(BinaryOp::BitXor),
+                TokenKind::LeftShiftEquals => Some(BinaryOp::LeftShift),
+                TokenKind::RightShiftEquals => Some(BinaryOp::RightShift),
+                _ => None,
+            };
+            
+            if let Some(op) = op {
+                self.advance();
+                let rhs = self.parse_assignment()?;
+                return Ok(Expr::CompoundAssign(op, Box::new(expr), Box::new(rhs), self.span_from(start)));
             }
         }
         
         Ok(expr)
     }
     
-    fn parse_ternary(&mut self) -> Result<Expr, String> {
+    fn parse_ternary(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
         let expr = self.parse_logical_or()?;
         
         if let Some(token) = &self.current {
@@ -619,7 +1172,7 @@ impl Parser {
                     Box::new(expr),
                     Box::new(then_expr),
                     Box::new(else_expr),
-                    0..0,
+                    self.span_from(start),
                 ));
             }
         }
@@ -627,14 +1180,15 @@ impl Parser {
         Ok(expr)
     }
     
-    fn parse_logical_or(&mut self) -> Result<Expr, String> {
+    fn parse_logical_or(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
         let mut left = self.parse_logical_and()?;
         
         while let Some(token) = &self.current {
             if token.kind == TokenKind::LogicalOr {
                 self.advance();
                 let right = self.parse_logical_and()?;
-                left = Expr::Binary(BinaryOp::LogicalOr, Box::new(left), Box::new(right), 0..0);
+                left = Expr::Binary(BinaryOp::LogicalOr, Box::new(left), Box::new(right), self.span_from(start));
             } else {
                 break;
             }
@@ -643,14 +1197,66 @@ impl Parser {
         Ok(left)
     }
     
-    fn parse_logical_and(&mut self) -> Result<Expr, String> {
-        let mut left = self.parse_equality()?;
+    fn parse_logical_and(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
+        let mut left = self.parse_bit_or()?;
         
         while let Some(token) = &self.current {
             if token.kind == TokenKind::LogicalAnd {
+                self.advance();
+                let right = self.parse_bit_or()?;
+                left = Expr::Binary(BinaryOp::LogicalAnd, Box::new(left), Box::new(right), self.span_from(start));
+            } else {
+                break;
+            }
+        }
+        
+        Ok(left)
+    }
+    
+    fn parse_bit_or(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
+        let mut left = self.parse_bit_xor()?;
+        
+        while let Some(token) = &self.current {
+            if token.kind == TokenKind::Pipe {
+                self.advance();
+                let right = self.parse_bit_xor()?;
+                left = Expr::Binary(BinaryOp::BitOr, Box::new(left), Box::new(right), self.span_from(start));
+            } else {
+                break;
+            }
+        }
+        
+        Ok(left)
+    }
+    
+    fn parse_bit_xor(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
+        let mut left = self.parse_bit_and()?;
+        
+        while let Some(token) = &self.current {
+            if token.kind == TokenKind::Caret {
+                self.advance();
+                let right = self.parse_bit_and()?;
+                left = Expr::Binary(BinaryOp::BitXor, Box::new(left), Box::new(right), self.span_from(start));
+            } else {
+                break;
+            }
+        }
+        
+        Ok(left)
+    }
+    
+    fn parse_bit_and(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
+        let mut left = self.parse_equality()?;
+        
+        while let Some(token) = &self.current {
+            if token.kind == TokenKind::Ampersand {
                 self.advance();
                 let right = self.parse_equality()?;
-                left = Expr::Binary(BinaryOp::LogicalAnd, Box::new(left), Box::new(right), 0..0);
+                left = Expr::Binary(BinaryOp::BitAnd, Box::new(left), Box::new(right), self.span_from(start));
             } else {
                 break;
             }
@@ -659,7 +1265,8 @@ impl Parser {
         Ok(left)
     }
     
-    fn parse_equality(&mut self) -> Result<Expr, String> {
+    fn parse_equality(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
         let mut left = self.parse_comparison()?;
         
         while let Some(token) = &self.current {
@@ -670,14 +1277,15 @@ impl Parser {
             };
             self.advance();
             let right = self.parse_comparison()?;
-            left = Expr::Binary(op, Box::new(left), Box::new(right), 0..0);
+            left = Expr::Binary(op, Box::new(left), Box::new(right), self.span_from(start));
         }
         
         Ok(left)
     }
     
-    fn parse_comparison(&mut self) -> Result<Expr, String> {
-        let mut left = self.parse_additive()?;
+    fn parse_comparison(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
+        let mut left = self.parse_shift()?;
         
         while let Some(token) = &self.current {
             let op = match token.kind {
@@ -688,14 +1296,33 @@ impl Parser {
                 _ => break,
             };
             self.advance();
+            let right = self.parse_shift()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right), self.span_from(start));
+        }
+        
+        Ok(left)
+    }
+    
+    fn parse_shift(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
+        let mut left = self.parse_additive()?;
+        
+        while let Some(token) = &self.current {
+            let op = match token.kind {
+                TokenKind::LeftShift => BinaryOp::LeftShift,
+                TokenKind::RightShift => BinaryOp::RightShift,
+                _ => break,
+            };
+            self.advance();
             let right = self.parse_additive()?;
-            left = Expr::Binary(op, Box::new(left), Box::new(right), 0..0);
+            left = Expr::Binary(op, Box::new(left), Box::new(right), self.span_from(start));
         }
         
         Ok(left)
     }
     
-    fn parse_additive(&mut self) -> Result<Expr, String> {
+    fn parse_additive(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
         let mut left = self.parse_multiplicative()?;
         
         while let Some(token) = &self.current {
@@ -706,13 +1333,14 @@ impl Parser {
             };
             self.advance();
             let right = self.parse_multiplicative()?;
-            left = Expr::Binary(op, Box::new(left), Box::new(right), 0..0);
+            left = Expr::Binary(op, Box::new(left), Box::new(right), self.span_from(start));
         }
         
         Ok(left)
     }
     
-    fn parse_multiplicative(&mut self) -> Result<Expr, String> {
+    fn parse_multiplicative(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
         let mut left = self.parse_unary()?;
         
         while let Some(token) = &self.current {
@@ -724,34 +1352,79 @@ impl Parser {
             };
             self.advance();
             let right = self.parse_unary()?;
-            left = Expr::Binary(op, Box::new(left), Box::new(right), 0..0);
+            left = Expr::Binary(op, Box::new(left), Box::new(right), self.span_from(start));
         }
         
         Ok(left)
     }
     
-    fn parse_unary(&mut self) -> Result<Expr, String> {
+    fn parse_unary(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
+        
+        // `(` followed by a type name starts a cast rather than a
+        // parenthesised expression
+        if self.at_parenthesized_type() {
+            let ty = self.parse_parenthesized_type()?;
+            let expr = self.parse_unary()?;
+            return Ok(Expr::Cast(ty, Box::new(expr), self.span_from(start)));
+        }
+        
         if let Some(token) = &self.current {
+            match token.kind {
+                TokenKind::Sizeof => {
+                    self.advance();
+                    if self.at_parenthesized_type() {
+                        let ty = self.parse_parenthesized_type()?;
+                        return Ok(Expr::Sizeof(ty, self.span_from(start)));
+                    }
+                    let expr = self.parse_unary()?;
+                    return Ok(Expr::SizeofValue(Box::new(expr), self.span_from(start)));
+                }
+                TokenKind::Alignof => {
+                    self.advance();
+                    let ty = self.parse_parenthesized_type()?;
+                    return Ok(Expr::Alignof(ty, self.span_from(start)));
+                }
+                _ => {}
+            }
+            
             let op = match token.kind {
                 TokenKind::Minus => Some(UnaryOp::Neg),
                 TokenKind::Bang => Some(UnaryOp::Not),
                 TokenKind::Tilde => Some(UnaryOp::BitNot),
                 TokenKind::Star => Some(UnaryOp::Deref),
                 TokenKind::Ampersand => Some(UnaryOp::AddrOf),
+                TokenKind::PlusPlus => Some(UnaryOp::PreInc),
+                TokenKind::MinusMinus => Some(UnaryOp::PreDec),
                 _ => None,
             };
             
             if let Some(op) = op {
                 self.advance();
                 let expr = self.parse_unary()?;
-                return Ok(Expr::Unary(op, Box::new(expr), 0..0));
+                return Ok(Expr::Unary(op, Box::new(expr), self.span_from(start)));
             }
         }
         
         self.parse_postfix()
     }
     
-    fn parse_postfix(&mut self) -> Result<Expr, String> {
+    /// Whether the current `(` opens a type name, as in a cast or `sizeof(T)`
+    fn at_parenthesized_type(&mut self) -> bool {
+        self.current.as_ref().is_some_and(|t| t.kind == TokenKind::LeftParen)
+            && self.tokens.peek().is_some_and(|t| starts_type(t, &self.decls.types))
+    }
+    
+    fn parse_parenthesized_type(&mut self) -> Result<Type, FeraError> {
+        self.expect(TokenKind::LeftParen)?;
+        let ty = self.parse_type()?;
+        let ty = self.parse_abstract_declarator(ty)?;
+        self.expect(TokenKind::RightParen)?;
+        Ok(ty)
+    }
+    
+    fn parse_postfix(&mut self) -> Result<Expr, FeraError> {
+        let start = self.start();
         let mut expr = self.parse_primary()?;
         
         loop {
@@ -761,25 +1434,35 @@ impl Parser {
                         self.advance();
                         let args = self.parse_args()?;
                         self.expect(TokenKind::RightParen)?;
-                        expr = Expr::Call(Box::new(expr), args, 0..0);
+                        expr = Expr::Call(Box::new(expr), args, self.span_from(start));
                     }
                     TokenKind::LeftBracket => {
                         self.advance();
                         let index = self.parse_expr()?;
                         self.expect(TokenKind::RightBracket)?;
-                        expr = Expr::Index(Box::new(expr), Box::new(index), 0..0);
+                        expr = Expr::Index(Box::new(expr), Box::new(index), self.span_from(start));
                     }
                     TokenKind::Dot => {
                         self.advance();
                         let field = self.expect(TokenKind::Identifier)?;
-                        expr = Expr::Field(Box::new(expr), field.text, 0..0);
+                        expr = Expr::Field(Box::new(expr), field.text, self.span_from(start));
                     }
                     TokenKind::Arrow => {
                         self.advance();
                         let field = self.expect(TokenKind::Identifier)?;
                         // ptr->field is syntactic sugar for (*ptr).field
-                        let deref = Expr::Unary(UnaryOp::Deref, Box::new(expr), 0..0);
-                        expr = Expr::Field(Box::new(deref), field.text, 0..0);
+                        let span = self.span_from(start);
+                        let deref = Expr::Unary(UnaryOp::Deref, Box::new(expr), span.clone());
+                        expr = Expr::Field(Box::new(deref), field.text, span);
+                    }
+                    TokenKind::PlusPlus | TokenKind::MinusMinus => {
+                        let op = if token.kind == TokenKind::PlusPlus {
+                            UnaryOp::PostInc
+                        } else {
+                            UnaryOp::PostDec
+                        };
+                        self.advance();
+                        expr = Expr::Unary(op, Box::new(expr), self.span_from(start));
                     }
                     _ => break,
                 }
@@ -791,7 +1474,7 @@ impl Parser {
         Ok(expr)
     }
     
-    fn parse_args(&mut self) -> Result<Vec<Expr>, String> {
+    fn parse_args(&mut self) -> Result<Vec<Expr>, FeraError> {
         let mut args = Vec::new();
         
         while let Some(token) = &self.current {
@@ -813,46 +1496,49 @@ impl Parser {
         Ok(args)
     }
     
-    fn parse_primary(&mut self) -> Result<Expr, String> {
-        let token = self.current.as_ref().ok_or("Unexpected EOF")?;
+    fn parse_primary(&mut self) -> Result<Expr, FeraError> {
+        let token = self.current.as_ref().ok_or_else(|| self.unexpected("expression"))?;
+        let span = token.span.clone();
         
         match &token.kind {
             TokenKind::DecimalInteger | TokenKind::HexInteger | 
             TokenKind::OctalInteger | TokenKind::BinaryInteger => {
-                let value = self.parse_integer(&token.text)?;
+                let (value, ty) = self.parse_integer(&token.text)
+                    .map_err(|e| FeraError::lex(e, span.clone()))?;
                 self.advance();
-                Ok(Expr::IntLiteral(value, 0..0))
+                Ok(Expr::IntLiteral(value, ty, span))
             }
             TokenKind::FloatLiteral => {
                 let value = token.text.trim_end_matches('f')
                     .parse::<f64>()
-                    .map_err(|e| format!("Invalid float: {}", e))?;
+                    .map_err(|e| FeraError::lex(format!("Invalid float: {}", e), span.clone()))?;
                 self.advance();
-                Ok(Expr::FloatLiteral(value, 0..0))
+                Ok(Expr::FloatLiteral(value, span))
             }
             TokenKind::StringLiteral => {
                 let raw = &token.text[1..token.text.len()-1];
-                let value = self.unescape_string(raw)?;
+                let value = self.unescape_string(raw)
+                    .map_err(|e| FeraError::lex(e, span.clone()))?;
                 self.advance();
-                Ok(Expr::StringLiteral(value, 0..0))
+                Ok(Expr::StringLiteral(value, span))
             }
             TokenKind::CharLiteral => {
-                let value = token.text.chars().nth(1).ok_or("Invalid char")?;
+                let value = token.text.chars().nth(1).ok_or_else(|| FeraError::lex("Invalid char", span.clone()))?;
                 self.advance();
-                Ok(Expr::CharLiteral(value, 0..0))
+                Ok(Expr::CharLiteral(value, span))
             }
             TokenKind::True => {
                 self.advance();
-                Ok(Expr::BoolLiteral(true, 0..0))
+                Ok(Expr::BoolLiteral(true, span))
             }
             TokenKind::False => {
                 self.advance();
-                Ok(Expr::BoolLiteral(false, 0..0))
+                Ok(Expr::BoolLiteral(false, span))
             }
             TokenKind::Identifier => {
                 let name = token.text.clone();
                 self.advance();
-                Ok(Expr::Identifier(name, 0..0))
+                Ok(Expr::Identifier(name, span))
             }
             TokenKind::LeftParen => {
                 self.advance();
@@ -860,26 +1546,48 @@ impl Parser {
                 self.expect(TokenKind::RightParen)?;
                 Ok(expr)
             }
-            _ => Err(format!("Unexpected token: {:?}", token.kind)),
+            _ => Err(self.unexpected("expression")),
         }
     }
     
-    fn parse_integer(&self, text: &str) -> Result<i64, String> {
-        let text = text.trim_end_matches(|c| c == 'u' || c == 'U' || c == 'l' || c == 'L');
+    /// Parse an integer literal into its value and type. Like C, the type
+    /// is the first of `i32`, `i64` and `u64` that holds the value; hex,
+    /// octal and binary literals also try the unsigned type of each width.
+    /// A `u` suffix allows only unsigned types and an `l` suffix skips `i32`.
+    fn parse_integer(&self, text: &str) -> Result<(i64, Type), String> {
+        let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+        let suffix = text[digits.len()..].to_ascii_lowercase();
+        let is_unsigned = suffix.contains('u');
+        let is_long = suffix.contains('l');
+        
+        let (radix, digits, name) = match digits.get(..2) {
+            Some("0x" | "0X") => (16, &digits[2..], "hex"),
+            Some("0o" | "0O") => (8, &digits[2..], "octal"),
+            Some("0b" | "0B") => (2, &digits[2..], "binary"),
+            _ => (10, digits, "decimal"),
+        };
+        let value = u64::from_str_radix(digits, radix)
+            .map_err(|e| format!("Invalid {} integer: {}", name, e))?;
         
-        if text.starts_with("0x") || text.starts_with("0X") {
-            i64::from_str_radix(&text[2..], 16)
-                .map_err(|e| format!("Invalid hex integer: {}", e))
-        } else if text.starts_with("0o") || text.starts_with("0O") {
-            i64::from_str_radix(&text[2..], 8)
-                .map_err(|e| format!("Invalid octal integer: {}", e))
-        } else if text.starts_with("0b") || text.starts_with("0B") {
-            i64::from_str_radix(&text[2..], 2)
-                .map_err(|e| format!("Invalid binary integer: {}", e))
-        } else {
-            text.parse::<i64>()
-                .map_err(|e| format!("Invalid decimal integer: {}", e))
+        let mut candidates = Vec::new();
+        if !is_long {
+            if !is_unsigned {
+                candidates.push((Type::I32, i32::MAX as u64));
+            }
+            if is_unsigned || radix != 10 {
+                candidates.push((Type::U32, u32::MAX as u64));
+            }
+        }
+        if !is_unsigned {
+            candidates.push((Type::I64, i64::MAX as u64));
         }
+        candidates.push((Type::U64, u64::MAX));
+        
+        let ty = candidates.into_iter()
+            .find(|(_, max)| value <= *max)
+            .map(|(ty, _)| ty)
+            .unwrap_or(Type::U64);
+        Ok((value as i64, ty))
     }
     
     fn unescape_string(&self, s: &str) -> Result<String, String> {
@@ -908,3 +1616,486 @@ impl Parser {
     }
 }
 
+
+/// Whether `token` can begin a type: a type keyword, a qualifier or a
+/// declared struct, union, enum or typedef name
+fn starts_type(token: &Token, types: &HashMap<String, Type>) -> bool {
+    matches!(token.kind,
+        TokenKind::Void | TokenKind::Bool | TokenKind::Char | TokenKind::Int |
+        TokenKind::I8 | TokenKind::I16 | TokenKind::I32 | TokenKind::I64 | TokenKind::ISize |
+        TokenKind::U8 | TokenKind::U16 | TokenKind::U32 | TokenKind::U64 | TokenKind::USize |
+        TokenKind::F32 | TokenKind::F64 | TokenKind::Struct | TokenKind::Union | TokenKind::Enum |
+        TokenKind::Const | TokenKind::Volatile
+    ) || (token.kind == TokenKind::Identifier && types.contains_key(&token.text))
+}
+
+/// `element` wrapped in array dimensions, outermost first
+fn array_of(element: Type, dims: Vec<Option<usize>>) -> Type {
+    dims.into_iter().rev().fold(element, |ty, len| Type::Array(Box::new(ty), len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    
+    fn parse_with_errors(source: &str, limit: usize) -> (Program, Vec<FeraError>) {
+        let tokens = Lexer::new(source).tokenize();
+        let mut parser = Parser::new(tokens);
+        parser.set_error_limit(limit);
+        parser.parse_program()
+    }
+    
+    fn parse(source: &str) -> Program {
+        let (program, errors) = parse_with_errors(source, 0);
+        assert!(errors.is_empty(), "unexpected errors: {:?}", errors);
+        program
+    }
+    
+    #[test]
+    fn test_spans_cover_source_text() {
+        let source = "i32 limit = 10;\nexport i32 add(i32 a, i32 b) {\n    return a + b * 2;\n}\n";
+        let program = parse(source);
+        
+        let Item::GlobalVar(global) = &program.items[0] else { panic!("expected global") };
+        assert_eq!(&source[global.span.clone()], "i32 limit = 10;");
+        
+        let Item::Function(func) = &program.items[1] else { panic!("expected function") };
+        assert!(source[func.span.clone()].starts_with("export i32 add"));
+        assert!(source[func.span.clone()].ends_with('}'));
+        assert_eq!(&source[func.params[1].span.clone()], "i32 b");
+        
+        let body = func.body.as_ref().unwrap();
+        let Stmt::Return(Some(value), span) = &body.stmts[0] else { panic!("expected return") };
+        assert_eq!(&source[span.clone()], "return a + b * 2;");
+        assert_eq!(&source[value.span()], "a + b * 2");
+        
+        let Expr::Binary(_, _, rhs, _) = value else { panic!("expected binary") };
+        assert_eq!(&source[rhs.span()], "b * 2");
+    }
+    
+    #[test]
+    fn test_recovers_from_errors_in_statements_and_items() {
+        let source = "\
+i32 broken(i32 a,, i32 b) { return a; }
+export i32 main() {
+    i32 x = 1 +;
+    i32 y = (2;
+    if (y) { y = ; }
+    return x + y;
+}
+i32 after = 3;
+";
+        let (program, errors) = parse_with_errors(source, 0);
+        
+        let lines: Vec<_> = errors.iter()
+            .map(|e| source[..e.span().unwrap().start].matches('\n').count() + 1)
+            .collect();
+        assert_eq!(lines, [1, 3, 4, 5]);
+        
+        // Everything after the errors is still parsed
+        let names: Vec<_> = program.items.iter()
+            .filter_map(|item| match item {
+                Item::Function(f) => Some(f.name.as_str()),
+                Item::GlobalVar(g) => Some(g.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["main", "after"]);
+        
+        let Item::Function(main) = &program.items[0] else { panic!("expected function") };
+        let body = main.body.as_ref().unwrap();
+        assert!(matches!(body.stmts.as_slice(), [Stmt::If(_), Stmt::Return(..)]));
+    }
+    
+    #[test]
+    fn test_error_limit_stops_reporting() {
+        let source = "i32 a = ;\ni32 b = ;\ni32 c = ;\ni32 d = ;\n";
+        let tokens = Lexer::new(source).tokenize();
+        let mut parser = Parser::new(tokens);
+        parser.set_error_limit(2);
+        let (_, errors) = parser.parse_program();
+        assert_eq!(errors.len(), 2);
+        assert!(parser.error_limit_reached());
+        
+        let (_, errors) = parse_with_errors(source, 0);
+        assert_eq!(errors.len(), 4);
+    }
+    
+    /// Render an expression with explicit parentheses
+    fn grouping(expr: &Expr) -> String {
+        match expr {
+            Expr::Binary(op, left, right, _) => {
+                format!("({} {} {})", grouping(left), op.symbol(), grouping(right))
+            }
+            Expr::Unary(UnaryOp::BitNot, inner, _) => format!("~{}", grouping(inner)),
+            Expr::Unary(UnaryOp::PreInc, inner, _) => format!("(++{})", grouping(inner)),
+            Expr::Unary(UnaryOp::PostInc, inner, _) => format!("({}++)", grouping(inner)),
+            Expr::Unary(UnaryOp::Neg, inner, _) => format!("(-{})", grouping(inner)),
+            Expr::Assign(lhs, rhs, _) => format!("({} = {})", grouping(lhs), grouping(rhs)),
+            Expr::CompoundAssign(op, lhs, rhs, _) => format!("({} {}= {})", grouping(lhs), op.symbol(), grouping(rhs)),
+            Expr::Identifier(name, _) => name.clone(),
+            Expr::IntLiteral(value, _, _) => value.to_string(),
+            Expr::Cast(ty, inner, _) => format!("(({}){})", ty, grouping(inner)),
+            Expr::Sizeof(ty, _) => format!("sizeof({})", ty),
+            Expr::SizeofValue(inner, _) => format!("(sizeof {})", grouping(inner)),
+            Expr::Alignof(ty, _) => format!("alignof({})", ty),
+            _ => panic!("unexpected expression {:?}", expr),
+        }
+    }
+    
+    #[test]
+    fn test_c_operator_precedence() {
+        let cases = [
+            ("a | b ^ c & d", "(a | (b ^ (c & d)))"),
+            ("a & b == c", "(a & (b == c))"),
+            ("a << 1 + b", "(a << (1 + b))"),
+            ("a < b << c", "(a < (b << c))"),
+            ("a || b && c | d", "(a || (b && (c | d)))"),
+            ("flags & ~mask | 1 << 5", "((flags & ~mask) | (1 << 5))"),
+            ("a >> 2 >> 1", "((a >> 2) >> 1)"),
+            ("a |= 1 << 5", "(a |= (1 << 5))"),
+            ("a = b += 2", "(a = (b += 2))"),
+            ("-a++ + ++b", "((-(a++)) + (++b))"),
+        ];
+        
+        for (source, expected) in cases {
+            let program = parse(&format!("i32 x = {};", source));
+            let Item::GlobalVar(global) = &program.items[0] else { panic!("expected global") };
+            assert_eq!(grouping(global.init.as_ref().unwrap()), expected, "{}", source);
+        }
+    }
+    
+    #[test]
+    fn test_declared_type_names_start_declarations() {
+        let program = parse("
+            struct Vec3 { f32 x; f32 y; };
+            union Bits { f32 f; i32 i; };
+            typedef Vec3* VecPtr;
+            void f(VecPtr p) {
+                Vec3 v;
+                union Bits b;
+                VecPtr q = p;
+                p->x = v.y;
+            }
+        ");
+        
+        let Item::Function(func) = &program.items[3] else { panic!("expected function") };
+        let stmts = &func.body.as_ref().unwrap().stmts;
+        let types: Vec<_> = stmts[..3].iter()
+            .map(|stmt| match stmt {
+                Stmt::Let(l) => l.ty.clone(),
+                _ => panic!("expected declaration, found {:?}", stmt),
+            })
+            .collect();
+        assert_eq!(types, [
+            Type::Named("Vec3".to_string()),
+            Type::Union("Bits".to_string()),
+            Type::Named("VecPtr".to_string()),
+        ]);
+        
+        let Stmt::Expr(Expr::Assign(lhs, _, _)) = &stmts[3] else { panic!("expected assignment") };
+        let Expr::Field(base, field, _) = lhs.as_ref() else { panic!("expected field") };
+        assert!(matches!(base.as_ref(), Expr::Unary(UnaryOp::Deref, _, _)));
+        assert_eq!(field, "x");
+    }
+    
+    #[test]
+    fn test_array_and_pointer_declarators() {
+        let program = parse("
+            i32 table[4];
+            i32 sum(const i32* values, i32 rows[], char** names) {
+                i32 grid[2][3];
+                return 0;
+            }
+        ");
+        
+        let int = || Box::new(Type::I32);
+        let Item::GlobalVar(global) = &program.items[0] else { panic!("expected global") };
+        assert_eq!(global.ty, Type::Array(int(), Some(4)));
+        
+        let Item::Function(func) = &program.items[1] else { panic!("expected function") };
+        let params: Vec<_> = func.params.iter().map(|p| p.ty.clone()).collect();
+        assert_eq!(params, [
+            Type::Pointer(int(), vec![TypeQualifier::Const]),
+            Type::Pointer(int(), vec![]),
+            Type::Pointer(Box::new(Type::Pointer(Box::new(Type::Char), vec![])), vec![]),
+        ]);
+        
+        let Stmt::Let(grid) = &func.body.as_ref().unwrap().stmts[0] else { panic!("expected declaration") };
+        assert_eq!(grid.ty, Type::Array(Box::new(Type::Array(int(), Some(3))), Some(2)));
+    }
+    
+    #[test]
+    fn test_function_pointer_declarators() {
+        let program = parse("
+            typedef i32 (*BinOp)(i32, i32);
+            struct Device { void (*reset)(void); };
+            void (*vectors[16])(void);
+            void each(i32 (*visit)(i32* item, i32), i32 items[]) {
+                BinOp op;
+                usize size = sizeof(void (*)(u8));
+                op = (BinOp)(i32 (*)(i32, i32))0;
+            }
+        ");
+        
+        let function = |ret: Type, params: Vec<Type>| Type::Pointer(Box::new(Type::Function(Box::new(ret), params, false)), vec![]);
+        let int_ptr = Type::Pointer(Box::new(Type::I32), vec![]);
+        let Item::TypeDef(typedef) = &program.items[0] else { panic!("expected typedef") };
+        assert_eq!(typedef.ty, function(Type::I32, vec![Type::I32, Type::I32]));
+        
+        let Item::Struct(device) = &program.items[1] else { panic!("expected struct") };
+        assert_eq!(device.fields[0].ty, function(Type::Void, vec![]));
+        
+        let Item::GlobalVar(vectors) = &program.items[2] else { panic!("expected global") };
+        assert_eq!(vectors.name, "vectors");
+        assert_eq!(vectors.ty, Type::Array(Box::new(function(Type::Void, vec![])), Some(16)));
+        
+        let Item::Function(each) = &program.items[3] else { panic!("expected function") };
+        assert_eq!(each.params[0].name, "visit");
+        assert_eq!(each.params[0].ty, function(Type::I32, vec![int_ptr.clone(), Type::I32]));
+        assert_eq!(each.params[1].ty, int_ptr);
+        let stmts = &each.body.as_ref().unwrap().stmts;
+        let Stmt::Let(size) = &stmts[1] else { panic!("expected declaration") };
+        assert!(matches!(&size.init, Some(Expr::Sizeof(ty, _)) if *ty == function(Type::Void, vec![Type::U8])));
+        let Stmt::Expr(Expr::Assign(_, cast, _)) = &stmts[2] else { panic!("expected assignment") };
+        let Expr::Cast(_, inner, _) = cast.as_ref() else { panic!("expected cast") };
+        assert!(matches!(inner.as_ref(), Expr::Cast(ty, ..) if *ty == function(Type::I32, vec![Type::I32, Type::I32])));
+    }
+    
+    #[test]
+    fn test_variadic_declarations() {
+        let program = parse("
+            i32 printf(char* format, ...);
+            i32 (*log)(u8 level, char* format, ...);
+        ");
+        
+        let Item::Function(printf) = &program.items[0] else { panic!("expected function") };
+        assert!(printf.is_variadic);
+        assert_eq!(printf.params.len(), 1);
+        let Item::GlobalVar(log) = &program.items[1] else { panic!("expected global") };
+        let function = Type::Function(Box::new(Type::I32), vec![Type::U8, Type::Pointer(Box::new(Type::Char), vec![])], true);
+        assert_eq!(log.ty, Type::Pointer(Box::new(function), vec![]));
+        assert_eq!(log.ty.to_string(), "i32 (*)(u8, char*, ...)");
+        
+        let (_, errors) = parse_with_errors("void f(...);", 0);
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, ["Parse error: Expected a parameter before '...'"]);
+    }
+    
+    #[test]
+    fn test_casts() {
+        let program = parse("
+            typedef u32* Register;
+            void poke(i64 value, Register reg) {
+                value = (u8)value + 1;
+                value = (i64)-(value);
+                reg = (Register)(value);
+                value = (value) * 2;
+                value = (volatile u32*)0x40020000 - reg;
+            }
+        ");
+        
+        let Item::Function(func) = &program.items[1] else { panic!("expected function") };
+        let casts: Vec<_> = func.body.as_ref().unwrap().stmts.iter()
+            .map(|stmt| match stmt {
+                Stmt::Expr(Expr::Assign(_, value, _)) => grouping(value),
+                _ => panic!("expected assignment"),
+            })
+            .collect();
+        assert_eq!(casts, [
+            "(((u8)value) + 1)",
+            "((i64)(-value))",
+            "((Register)value)",
+            "(value * 2)",
+            "(((volatile u32*)1073872896) - reg)",
+        ]);
+    }
+    
+    #[test]
+    fn test_pointer_qualifiers() {
+        let program = parse("
+            const char* name;
+            volatile u32* const* registers;
+            u8 volatile* restrict status;
+        ");
+        
+        let types: Vec<_> = program.items.iter()
+            .map(|item| match item {
+                Item::GlobalVar(global) => global.ty.to_string(),
+                _ => panic!("expected global"),
+            })
+            .collect();
+        assert_eq!(types, ["const char*", "volatile u32* const*", "volatile u8*"]);
+    }
+    
+    #[test]
+    fn test_sizeof_and_alignof() {
+        let program = parse("
+            typedef u8 Byte;
+            void sizes(i64 value, i64* out) {
+                *out = sizeof value + 1;
+                *out = sizeof(value) * 2;
+                *out = sizeof(Byte) * value;
+                *out = sizeof(struct Point*) - alignof(u16[3]);
+                *out = sizeof -value;
+            }
+        ");
+        
+        let Item::Function(func) = &program.items[1] else { panic!("expected function") };
+        let sizes: Vec<_> = func.body.as_ref().unwrap().stmts.iter()
+            .map(|stmt| match stmt {
+                Stmt::Expr(Expr::Assign(_, value, _)) => grouping(value),
+                _ => panic!("expected assignment"),
+            })
+            .collect();
+        assert_eq!(sizes, [
+            "((sizeof value) + 1)",
+            "((sizeof value) * 2)",
+            "(sizeof(Byte) * value)",
+            "(sizeof(struct Point*) - alignof(u16[3]))",
+            "(sizeof (-value))",
+        ]);
+    }
+    
+    #[test]
+    fn test_loops_and_switch() {
+        let program = parse("
+            void f(i32 n) {
+                for (;;) { break; }
+                do n--; while (n > 0);
+                switch (n) {
+                    case 1:
+                    case 2: n = 0;
+                    default: n++; break;
+                    case 3 + 1: return;
+                }
+            }
+        ");
+        
+        let Item::Function(func) = &program.items[0] else { panic!("expected function") };
+        let stmts = &func.body.as_ref().unwrap().stmts;
+        let Stmt::For(for_stmt) = &stmts[0] else { panic!("expected for") };
+        assert!(for_stmt.init.is_none() && for_stmt.condition.is_none() && for_stmt.increment.is_none());
+        assert!(matches!(&stmts[1], Stmt::DoWhile(d) if matches!(*d.body, Stmt::Expr(_))));
+        
+        let Stmt::Switch(switch) = &stmts[2] else { panic!("expected switch") };
+        let cases: Vec<_> = switch.cases.iter()
+            .map(|case| (case.pattern.as_ref().map(grouping), case.stmts.len()))
+            .collect();
+        assert_eq!(cases, [
+            (Some("1".to_string()), 0),
+            (Some("2".to_string()), 1),
+            (None, 2),
+            (Some("(3 + 1)".to_string()), 1),
+        ]);
+    }
+    
+    #[test]
+    fn test_labels_and_goto() {
+        let program = parse("
+            void f(i32 n) {
+            again:
+                n = n ? n - 1 : 0;
+                if (n) goto again;
+            done:
+            }
+        ");
+        
+        let Item::Function(func) = &program.items[0] else { panic!("expected function") };
+        let stmts = &func.body.as_ref().unwrap().stmts;
+        assert!(matches!(&stmts[0], Stmt::Label(name, _) if name == "again"));
+        assert!(matches!(&stmts[1], Stmt::Expr(Expr::Assign(..))));
+        let Stmt::If(if_stmt) = &stmts[2] else { panic!("expected if") };
+        assert!(matches!(&*if_stmt.then_branch, Stmt::Goto(name, _) if name == "again"));
+        assert!(matches!(&stmts[3], Stmt::Label(name, _) if name == "done"));
+    }
+    
+    #[test]
+    fn test_inline_asm() {
+        let program = parse("
+            u64 f(u64 x) {
+                asm volatile(\"cli\");
+                asm(\"mov %%cr0, %0\\n\\t\"
+                    \"add %2, %0\" : \"=r\"(x), \"+m\"(x) : \"ri\"(x + 1) : \"cc\", \"memory\");
+                asm volatile(\"wfi\" ::: \"memory\");
+                return x;
+            }
+        ");
+        
+        let Item::Function(func) = &program.items[0] else { panic!("expected function") };
+        let stmts = &func.body.as_ref().unwrap().stmts;
+        let Stmt::Asm(cli) = &stmts[0] else { panic!("expected asm") };
+        assert!(cli.is_volatile && cli.template == "cli" && cli.outputs.is_empty() && cli.clobbers.is_empty());
+        
+        let Stmt::Asm(mov) = &stmts[1] else { panic!("expected asm") };
+        assert!(!mov.is_volatile);
+        assert_eq!(mov.template, "mov %%cr0, %0\n\tadd %2, %0");
+        let constraints: Vec<_> = mov.outputs.iter().chain(&mov.inputs).map(|o| o.constraint.as_str()).collect();
+        assert_eq!(constraints, ["=r", "+m", "ri"]);
+        assert!(matches!(mov.inputs[0].expr, Expr::Binary(..)));
+        assert_eq!(mov.clobbers, ["cc", "memory"]);
+        
+        let Stmt::Asm(wfi) = &stmts[2] else { panic!("expected asm") };
+        assert!(wfi.outputs.is_empty() && wfi.inputs.is_empty());
+        assert_eq!(wfi.clobbers, ["memory"]);
+    }
+    
+    #[test]
+    fn test_attributes() {
+        let program = parse("
+            [[packed(2)]] struct Header { u8 tag; u32 len; };
+            [[deprecated(\"use run_v2\"), cold]] [[noinline]]
+            void run(i32* slots) { slots[slots[0]] = 1; }
+            [[section(\".boot\")]] i32 boot;
+            u8 table[sizeof(Header)];
+        ");
+        
+        let names = |attributes: &[Attribute]| -> Vec<(String, Vec<String>)> {
+            attributes.iter().map(|a| (a.name.clone(), a.args.clone())).collect()
+        };
+        let Item::Struct(header) = &program.items[0] else { panic!("expected struct") };
+        assert_eq!(names(&header.attributes), [("packed".to_string(), vec!["2".to_string()])]);
+        let Item::Function(run) = &program.items[1] else { panic!("expected function") };
+        assert_eq!(names(&run.attributes), [
+            ("deprecated".to_string(), vec!["use run_v2".to_string()]),
+            ("cold".to_string(), vec![]),
+            ("noinline".to_string(), vec![]),
+        ]);
+        let Item::GlobalVar(boot) = &program.items[2] else { panic!("expected global") };
+        assert_eq!(names(&boot.attributes), [("section".to_string(), vec![".boot".to_string()])]);
+        // Packing applies to sizes computed while parsing
+        let Item::GlobalVar(table) = &program.items[3] else { panic!("expected global") };
+        assert_eq!(table.ty, Type::Array(Box::new(Type::U8), Some(6)));
+        
+        let (_, errors) = parse_with_errors("[[packed]] enum E { A };", 0);
+        assert_eq!(errors[0].to_string(), "Parse error: Attributes cannot be applied to an enum");
+    }
+    
+    #[test]
+    fn test_integer_literal_types() {
+        let cases = [
+            ("42", 42, Type::I32),
+            ("2147483648", 2147483648, Type::I64),
+            ("0x7fffffff", 0x7fffffff, Type::I32),
+            ("0xffffffff", 0xffffffff, Type::U32),
+            ("0x100000000", 0x100000000, Type::I64),
+            ("0xffffffffffffffff", -1, Type::U64),
+            ("18446744073709551615", -1, Type::U64),
+            ("7u", 7, Type::U32),
+            ("7L", 7, Type::I64),
+            ("7UL", 7, Type::U64),
+            ("0b101u", 5, Type::U32),
+            ("5000000000u", 5000000000, Type::U64),
+        ];
+        
+        for (source, value, ty) in cases {
+            let program = parse(&format!("u64 x = {};", source));
+            let Item::GlobalVar(global) = &program.items[0] else { panic!("expected global") };
+            match global.init.as_ref().unwrap() {
+                Expr::IntLiteral(v, t, _) => assert_eq!((*v, t), (value, &ty), "{}", source),
+                expr => panic!("expected a literal for {}, found {:?}", source, expr),
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// GCC-style inline asm constraints and templates, and their LLVM spelling
+use crate::ast::{AsmOperand, Span};
+use crate::error::FeraError;
+
+/// How an asm operand is passed to the instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// In a register, or wherever the backend prefers (`r`, `g`, `{rax}`)
+    Register,
+    /// In memory, through the address of an lvalue (`m`)
+    Memory,
+    /// As an integer constant (`i`, `n`)
+    Immediate,
+    /// In the same place as output N (`0`, `1`, ...)
+    Tied(usize),
+}
+
+/// A parsed operand constraint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    pub kind: Kind,
+    /// `+`: an output that is also read
+    pub read_write: bool,
+    /// `&`: an output written before every input is read
+    pub early_clobber: bool,
+    /// The constraint codes as LLVM spells them, without `=`, `+` or `&`
+    pub codes: String,
+}
+
+/// Single letter constraints that put the operand in a register
+const REGISTER_CODES: &str = "rqabcdSD";
+const MEMORY_CODES: &str = "m";
+const IMMEDIATE_CODES: &str = "in";
+
+impl Constraint {
+    /// The LLVM constraint of this operand as an output, or as an input
+    /// when it isn't one
+    pub fn llvm(&self, output: bool) -> String {
+        let indirect = if self.kind == Kind::Memory { "*" } else { "" };
+        let prefix = match (output, self.early_clobber) {
+            (true, true) => "=&",
+            (true, false) => "=",
+            (false, _) => "",
+        };
+        format!("{}{}{}", prefix, indirect, self.codes)
+    }
+}
+
+/// Parse the constraint of an output, or of an input when there are
+/// `outputs` outputs it can refer to
+pub fn parse_constraint(operand: &AsmOperand, output: bool, outputs: usize) -> Result<Constraint, FeraError> {
+    let text = operand.constraint.as_str();
+    let error = |message: String| FeraError::type_error(message, operand.span.clone());
+
+    let (read_write, rest) = match text.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('=').unwrap_or(text)),
+    };
+    let has_prefix = read_write || rest.len() < text.len();
+    if output && !has_prefix {
+        return Err(error(format!("Asm output constraint '{}' must start with '=' or '+'", text)));
+    }
+    if !output && has_prefix {
+        return Err(error(format!("Asm input constraint '{}' cannot start with '{}'", text, &text[..1])));
+    }
+    let (early_clobber, rest) = match rest.strip_prefix('&') {
+        Some(rest) if output => (true, rest),
+        _ => (false, rest),
+    };
+
+    if let Ok(index) = rest.parse::<usize>() {
+        if output {
+            return Err(error(format!("Asm output cannot use constraint '{}'", text)));
+        }
+        if index >= outputs {
+            return Err(error(format!(
+                "Asm constraint '{}' refers to output {}, but there are only {} outputs",
+                text, index, outputs,
+            )));
+        }
+        return Ok(Constraint { kind: Kind::Tied(index), read_write, early_clobber, codes: rest.to_string() });
+    }
+    if rest.starts_with('{') && rest.ends_with('}') && rest.len() > 2 {
+        return Ok(Constraint { kind: Kind::Register, read_write, early_clobber, codes: rest.to_string() });
+    }
+
+    let unknown = || {
+        error(format!("Unknown asm constraint '{}'", text))
+            .with_suggestion("known constraints: r, q, a, b, c, d, S, D, m, i, n, g, {register} and output numbers")
+    };
+    if rest.is_empty() {
+        return Err(unknown());
+    }
+    let mut codes = String::new();
+    let (mut register, mut memory, mut immediate) = (false, false, false);
+    for code in rest.chars() {
+        if REGISTER_CODES.contains(code) {
+            register = true;
+            codes.push(code);
+        } else if MEMORY_CODES.contains(code) {
+            memory = true;
+            codes.push(code);
+        } else if IMMEDIATE_CODES.contains(code) {
+            immediate = true;
+            codes.push(code);
+        } else if code == 'g' {
+            // Any register, memory or immediate, which LLVM spells out
+            register = true;
+            codes.push_str("imr");
+        } else {
+            return Err(unknown());
+        }
+    }
+
+    let kind = if register || (memory && immediate) {
+        Kind::Register
+    } else if memory {
+        Kind::Memory
+    } else {
+        Kind::Immediate
+    };
+    if output && kind == Kind::Immediate {
+        return Err(error(format!("Asm output cannot use constraint '{}'", text)));
+    }
+    Ok(Constraint { kind, read_write, early_clobber, codes })
+}
+
+/// Rewrite a GCC asm template in LLVM's syntax: `%N` becomes `$N`,
+/// `%kN` becomes `${N:k}`, `%=` becomes `${:uid}` and `%%` becomes `%`.
+/// A basic asm statement, one without operands or clobbers, has no
+/// `%` escapes at all.
+pub fn llvm_template(template: &str, basic: bool, operands: usize, span: &Span) -> Result<String, FeraError> {
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => result.push_str("$$"),
+            '%' if !basic => {
+                let modifier = chars.next_if(|c| c.is_ascii_alphabetic());
+                let mut digits = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+                match (modifier, digits.parse::<usize>()) {
+                    (_, Ok(index)) if index >= operands => {
+                        return Err(FeraError::type_error(
+                            format!("Asm operand '%{}' is out of range, there are {} operands", digits, operands),
+                            span.clone(),
+                        ));
+                    }
+                    (None, Ok(index)) => result.push_str(&format!("${}", index)),
+                    (Some(modifier), Ok(index)) => result.push_str(&format!("${{{}:{}}}", index, modifier)),
+                    (None, Err(_)) if chars.next_if_eq(&'%').is_some() => result.push('%'),
+                    (None, Err(_)) if chars.next_if_eq(&'=').is_some() => result.push_str("${:uid}"),
+                    _ => {
+                        return Err(FeraError::type_error("Invalid '%' escape in asm template", span.clone())
+                            .with_suggestion("use '%N' for operand N and '%%' for a literal '%'"));
+                    }
+                }
+            }
+            c => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Expr;
+
+    fn operand(constraint: &str) -> AsmOperand {
+        AsmOperand { constraint: constraint.to_string(), expr: Expr::IntLiteral(0, crate::ast::Type::I32, 0..0), span: 0..0 }
+    }
+
+    fn llvm(constraint: &str, output: bool) -> Result<String, String> {
+        parse_constraint(&operand(constraint), output, 2)
+            .map(|c| c.llvm(output))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_parse_constraints() {
+        assert_eq!(llvm("=r", true), Ok("=r".to_string()));
+        assert_eq!(llvm("=&r", true), Ok("=&r".to_string()));
+        assert_eq!(llvm("+m", true), Ok("=*m".to_string()));
+        assert_eq!(llvm("m", false), Ok("*m".to_string()));
+        assert_eq!(llvm("g", false), Ok("imr".to_string()));
+        assert_eq!(llvm("{rax}", false), Ok("{rax}".to_string()));
+        assert_eq!(llvm("1", false), Ok("1".to_string()));
+        assert!(parse_constraint(&operand("+r"), true, 1).unwrap().read_write);
+        assert_eq!(parse_constraint(&operand("in"), false, 0).unwrap().kind, Kind::Immediate);
+
+        assert_eq!(llvm("r", true), Err("Type error: Asm output constraint 'r' must start with '=' or '+'".to_string()));
+        assert_eq!(llvm("=r", false), Err("Type error: Asm input constraint '=r' cannot start with '='".to_string()));
+        assert_eq!(llvm("=i", true), Err("Type error: Asm output cannot use constraint '=i'".to_string()));
+        assert_eq!(llvm("z", false), Err("Type error: Unknown asm constraint 'z'".to_string()));
+        assert_eq!(
+            llvm("2", false),
+            Err("Type error: Asm constraint '2' refers to output 2, but there are only 2 outputs".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_llvm_template() {
+        let template = |text: &str, basic: bool| llvm_template(text, basic, 2, &(0..0)).map_err(|e| e.to_string());
+        assert_eq!(template("mov %%cr0, %0", false), Ok("mov %cr0, $0".to_string()));
+        assert_eq!(template("add %k1, %0; jmp 1f%=", false), Ok("add ${1:k}, $0; jmp 1f${:uid}".to_string()));
+        assert_eq!(template("movl $1, %eax", true), Ok("movl $$1, %eax".to_string()));
+        assert_eq!(
+            template("mov %2, %0", false),
+            Err("Type error: Asm operand '%2' is out of range, there are 2 operands".to_string()),
+        );
+        assert_eq!(template("mov %eax", false), Err("Type error: Invalid '%' escape in asm template".to_string()));
+    }
+}
@@ -0,0 +1,190 @@
+// Validation of `[[attributes]]` against the items they are applied to
+use crate::ast::{Attribute, Span};
+use crate::error::FeraError;
+
+/// The kinds of item attributes can be applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Function,
+    Global,
+    Record,
+}
+
+impl Target {
+    fn describe(self) -> &'static str {
+        match self {
+            Target::Function => "a function",
+            Target::Global => "a global",
+            Target::Record => "a struct or union",
+        }
+    }
+}
+
+/// The arguments an attribute takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Arguments {
+    None,
+    /// `deprecated` and `deprecated("message")`
+    OptionalString,
+    String,
+    /// `packed` and `packed(N)`, where N is a power of two
+    OptionalAlignment,
+}
+
+/// Every known attribute, the items it applies to and its arguments
+const ATTRIBUTES: &[(&str, &[Target], Arguments)] = &[
+    ("noreturn", &[Target::Function], Arguments::None),
+    ("cold", &[Target::Function], Arguments::None),
+    ("hot", &[Target::Function], Arguments::None),
+    ("always_inline", &[Target::Function], Arguments::None),
+    ("noinline", &[Target::Function], Arguments::None),
+    ("deprecated", &[Target::Function], Arguments::OptionalString),
+    ("packed", &[Target::Record], Arguments::OptionalAlignment),
+    ("section", &[Target::Function, Target::Global], Arguments::String),
+    ("weak", &[Target::Function, Target::Global], Arguments::None),
+    ("used", &[Target::Function, Target::Global], Arguments::None),
+];
+
+/// Pairs of attributes that can't be applied to the same item
+const CONFLICTS: &[(&str, &str)] = &[("cold", "hot"), ("always_inline", "noinline")];
+
+/// Check that `attributes` are known, apply to `target`, take the right
+/// arguments and don't conflict with each other
+pub fn validate(attributes: &[Attribute], target: Target) -> Vec<FeraError> {
+    let mut errors = Vec::new();
+    for (i, attribute) in attributes.iter().enumerate() {
+        let Some((_, targets, arguments)) = ATTRIBUTES.iter().find(|(name, ..)| *name == attribute.name) else {
+            let known: Vec<_> = ATTRIBUTES.iter().map(|(name, ..)| *name).collect();
+            errors.push(FeraError::type_error(format!("Unknown attribute '{}'", attribute.name), attribute.span.clone())
+                .with_suggestion(format!("known attributes: {}", known.join(", "))));
+            continue;
+        };
+        if !targets.contains(&target) {
+            errors.push(FeraError::type_error(
+                format!("Attribute '{}' cannot be applied to {}", attribute.name, target.describe()),
+                attribute.span.clone(),
+            ));
+            continue;
+        }
+        if let Err(error) = check_arguments(attribute, *arguments) {
+            errors.push(error);
+        }
+        if attributes[..i].iter().any(|earlier| earlier.name == attribute.name) {
+            errors.push(FeraError::type_error(format!("Duplicate attribute '{}'", attribute.name), attribute.span.clone()));
+        }
+        for (a, b) in CONFLICTS {
+            let other = if attribute.name == *a { b } else if attribute.name == *b { a } else { continue };
+            if attributes[..i].iter().any(|earlier| earlier.name == *other) {
+                errors.push(FeraError::type_error(
+                    format!("Attributes '{}' and '{}' conflict", other, attribute.name),
+                    attribute.span.clone(),
+                ));
+            }
+        }
+    }
+    errors
+}
+
+fn check_arguments(attribute: &Attribute, arguments: Arguments) -> Result<(), FeraError> {
+    let expects = |what: &str| FeraError::type_error(
+        format!("Attribute '{}' expects {}", attribute.name, what),
+        attribute.span.clone(),
+    );
+    match (arguments, attribute.args.as_slice()) {
+        (Arguments::None, []) => Ok(()),
+        (Arguments::None, _) => Err(expects("no arguments")),
+        (Arguments::OptionalString, [] | [_]) | (Arguments::String, [_]) => Ok(()),
+        (Arguments::OptionalString, _) => Err(expects("at most one message")),
+        (Arguments::String, _) => Err(expects("one string")),
+        (Arguments::OptionalAlignment, []) => Ok(()),
+        (Arguments::OptionalAlignment, [_]) if packing(std::slice::from_ref(attribute)).is_some() => Ok(()),
+        (Arguments::OptionalAlignment, _) => Err(expects("a power of two alignment")),
+    }
+}
+
+/// The attribute called `name`, if present
+pub fn find<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+    attributes.iter().find(|attribute| attribute.name == name)
+}
+
+/// The alignment `[[packed(N)]]` caps fields at; plain `[[packed]]`
+/// removes all padding
+pub fn packing(attributes: &[Attribute]) -> Option<u64> {
+    let attribute = find(attributes, "packed")?;
+    match attribute.args.as_slice() {
+        [] => Some(1),
+        [align] => align.parse::<u64>().ok().filter(|align| align.is_power_of_two()),
+        _ => None,
+    }
+}
+
+/// The message of a `[[deprecated]]` attribute, empty when it has none
+pub fn deprecation(attributes: &[Attribute]) -> Option<&str> {
+    let attribute = find(attributes, "deprecated")?;
+    Some(attribute.args.first().map_or("", String::as_str))
+}
+
+/// Report attributes on an item that can't have any
+pub fn reject(attributes: &[Attribute], item: &str) -> Option<FeraError> {
+    let first = attributes.first()?;
+    let span: Span = first.span.start..attributes.last().map_or(first.span.end, |a| a.span.end);
+    Some(FeraError::parse(format!("Attributes cannot be applied to {}", item), span))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(name: &str, args: &[&str]) -> Attribute {
+        Attribute { name: name.to_string(), args: args.iter().map(|a| a.to_string()).collect(), span: 0..0 }
+    }
+
+    fn messages(attributes: &[Attribute], target: Target) -> Vec<String> {
+        validate(attributes, target).iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_attributes() {
+        let function = [attribute("noreturn", &[]), attribute("cold", &[]), attribute("section", &[".text.boot"])];
+        assert!(messages(&function, Target::Function).is_empty());
+        assert!(messages(&[attribute("packed", &["2"])], Target::Record).is_empty());
+
+        assert_eq!(messages(&[attribute("inline", &[])], Target::Function), ["Type error: Unknown attribute 'inline'"]);
+        assert_eq!(
+            messages(&[attribute("packed", &[])], Target::Function),
+            ["Type error: Attribute 'packed' cannot be applied to a function"],
+        );
+        assert_eq!(
+            messages(&[attribute("noreturn", &[])], Target::Global),
+            ["Type error: Attribute 'noreturn' cannot be applied to a global"],
+        );
+        assert_eq!(
+            messages(&[attribute("packed", &["3"])], Target::Record),
+            ["Type error: Attribute 'packed' expects a power of two alignment"],
+        );
+        assert_eq!(
+            messages(&[attribute("section", &[])], Target::Global),
+            ["Type error: Attribute 'section' expects one string"],
+        );
+        assert_eq!(
+            messages(&[attribute("hot", &[]), attribute("cold", &[]), attribute("hot", &[])], Target::Function),
+            [
+                "Type error: Attributes 'hot' and 'cold' conflict",
+                "Type error: Duplicate attribute 'hot'",
+                "Type error: Attributes 'cold' and 'hot' conflict",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_packing_and_deprecation() {
+        assert_eq!(packing(&[attribute("packed", &[])]), Some(1));
+        assert_eq!(packing(&[attribute("packed", &["4"])]), Some(4));
+        assert_eq!(packing(&[attribute("packed", &["0"])]), None);
+        assert_eq!(packing(&[attribute("used", &[])]), None);
+
+        assert_eq!(deprecation(&[attribute("deprecated", &["use v2"])]), Some("use v2"));
+        assert_eq!(deprecation(&[attribute("deprecated", &[])]), Some(""));
+        assert_eq!(deprecation(&[]), None);
+    }
+}
@@ -0,0 +1,520 @@
+// Evaluation of constant expressions: array sizes, enum values and the
+// initializers of globals
+use crate::ast::{Attribute, BinaryOp, Enum, Expr, Span, Type, UnaryOp};
+use crate::error::FeraError;
+use crate::types::attribute;
+use crate::types::layout::{layout_of, record_layout, DataModel, Layout};
+use crate::types::{integer_width, is_float, is_integer, is_signed, literal_fits, resolve_type};
+use std::collections::HashMap;
+
+/// The declarations a constant expression can refer to
+#[derive(Debug, Default)]
+pub struct Declarations {
+    /// Struct, union, enum and typedef names and the types they stand for
+    pub types: HashMap<String, Type>,
+    /// Fields of every struct and union, by name
+    pub records: HashMap<String, Vec<(String, Type)>>,
+    /// Value and type of every enum constant
+    pub constants: HashMap<String, (i64, Type)>,
+    /// Backing type and constants of every enum, in declaration order
+    pub enums: HashMap<String, (Type, Vec<String>)>,
+    /// The alignment the fields of `[[packed]]` records are capped at
+    pub packing: HashMap<String, u64>,
+    /// Sizes and alignments of the target's scalar types
+    pub model: DataModel,
+}
+
+impl Declarations {
+    /// Record the fields of a struct or union and whether it's packed
+    pub fn declare_record(&mut self, name: &str, fields: Vec<(String, Type)>, attributes: &[Attribute]) {
+        self.records.insert(name.to_string(), fields);
+        if let Some(packing) = attribute::packing(attributes) {
+            self.packing.insert(name.to_string(), packing);
+        }
+    }
+    
+    /// Size and alignment of `ty`, for `sizeof` and `alignof`
+    pub fn layout(&self, ty: &Type, span: Span) -> Result<Layout, FeraError> {
+        let ty = resolve_type(ty, &self.types);
+        self.layout_nested(&ty, &mut Vec::new()).ok_or_else(|| {
+            FeraError::type_error(format!("Cannot take the size of '{}'", ty), span)
+                .with_suggestion("only complete types have a size")
+        })
+    }
+    
+    /// Layout of `ty`, laying out the records it contains from their
+    /// fields. A record that contains itself has no layout.
+    fn layout_nested<'a>(&'a self, ty: &'a Type, enclosing: &mut Vec<&'a str>) -> Option<Layout> {
+        match ty {
+            Type::Struct(name) | Type::Union(name) => {
+                let fields = self.records.get(name)?;
+                if enclosing.contains(&name.as_str()) {
+                    return None;
+                }
+                enclosing.push(name);
+                let fields = fields.iter()
+                    .map(|(_, field)| self.layout_nested(field, enclosing))
+                    .collect::<Option<Vec<_>>>();
+                enclosing.pop();
+                let packing = self.packing.get(name).copied();
+                Some(record_layout(&fields?, matches!(ty, Type::Union(_)), packing).layout)
+            }
+            Type::Array(element, Some(len)) => {
+                let element = self.layout_nested(element, enclosing)?;
+                Some(Layout::new(element.size * *len as u64, element.align))
+            }
+            Type::Enum(name) => layout_of(&self.enums.get(name)?.0, &HashMap::new(), &self.model),
+            _ => layout_of(ty, &HashMap::new(), &self.model),
+        }
+    }
+    
+    /// The integer type values of an enum type are stored as. Other
+    /// types are returned unchanged.
+    pub fn underlying(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Enum(name) => self.enums.get(name).map_or(Type::I32, |(backing, _)| backing.clone()),
+            _ => ty.clone(),
+        }
+    }
+    
+    /// The integer type an enum is stored as, `i32` unless it names one
+    pub fn backing_type(&self, e: &Enum) -> Type {
+        e.backing_type.as_ref().map_or(Type::I32, |ty| resolve_type(ty, &self.types))
+    }
+    
+    /// Evaluate the constants of an enum. A constant without a value is
+    /// one more than the one before it, and the first defaults to zero.
+    /// Stops at the first constant that is invalid.
+    pub fn declare_enum(&mut self, e: &Enum) -> Result<(), FeraError> {
+        let backing = self.backing_type(e);
+        if integer_width(&backing, &self.model).is_none() {
+            return Err(FeraError::type_error(
+                format!("Backing type of enum '{}' must be an integer, found '{}'", e.name, backing),
+                e.span.clone(),
+            ));
+        }
+        self.enums.insert(e.name.clone(), (backing.clone(), Vec::new()));
+        
+        let mut next = 0i64;
+        for variant in &e.variants {
+            let value = match &variant.value {
+                Some(expr) => eval_int(expr, self)?,
+                None => next,
+            };
+            if !literal_fits(value, &backing, &self.model) {
+                return Err(FeraError::type_error(
+                    format!("Value {} of '{}' does not fit in '{}'", value, variant.name, backing),
+                    variant.span.clone(),
+                ));
+            }
+            if self.constants.contains_key(&variant.name) {
+                return Err(FeraError::type_error(
+                    format!("Duplicate enum constant '{}'", variant.name),
+                    variant.span.clone(),
+                ));
+            }
+            self.constants.insert(variant.name.clone(), (value, backing.clone()));
+            if let Some((_, names)) = self.enums.get_mut(&e.name) {
+                names.push(variant.name.clone());
+            }
+            next = value.wrapping_add(1);
+        }
+        Ok(())
+    }
+}
+
+/// Evaluate an integer constant expression: literals, enum constants,
+/// `sizeof`, `alignof`, casts to integer types and the arithmetic, bitwise,
+/// comparison and logical operators over them. Arithmetic wraps at
+/// 64 bits.
+pub fn eval_int(expr: &Expr, decls: &Declarations) -> Result<i64, FeraError> {
+    match expr {
+        Expr::IntLiteral(value, _, _) => Ok(*value),
+        Expr::CharLiteral(c, _) => Ok(*c as i64),
+        Expr::BoolLiteral(b, _) => Ok(*b as i64),
+        Expr::Identifier(name, span) => decls.constants.get(name)
+            .map(|(value, _)| *value)
+            .ok_or_else(|| not_constant(span.clone())),
+        Expr::Sizeof(ty, span) => Ok(decls.layout(ty, span.clone())?.size as i64),
+        Expr::Alignof(ty, span) => Ok(decls.layout(ty, span.clone())?.align as i64),
+        Expr::Unary(op, operand, span) => {
+            let value = eval_int(operand, decls)?;
+            match op {
+                UnaryOp::Neg => Ok(value.wrapping_neg()),
+                UnaryOp::BitNot => Ok(!value),
+                UnaryOp::Not => Ok((value == 0) as i64),
+                _ => Err(not_constant(span.clone())),
+            }
+        }
+        Expr::Binary(op, left, right, span) => {
+            let left = eval_int(left, decls)?;
+            // The right operand of `&&` and `||` only counts when reached
+            match op {
+                BinaryOp::LogicalAnd if left == 0 => return Ok(0),
+                BinaryOp::LogicalOr if left != 0 => return Ok(1),
+                _ => {}
+            }
+            let right_span = right.span();
+            let right = eval_int(right, decls)?;
+            let value = match op {
+                BinaryOp::Add => left.wrapping_add(right),
+                BinaryOp::Sub => left.wrapping_sub(right),
+                BinaryOp::Mul => left.wrapping_mul(right),
+                BinaryOp::Div | BinaryOp::Mod if right == 0 => {
+                    return Err(FeraError::type_error("Division by zero in constant expression", right_span));
+                }
+                BinaryOp::Div => left.wrapping_div(right),
+                BinaryOp::Mod => left.wrapping_rem(right),
+                BinaryOp::BitAnd => left & right,
+                BinaryOp::BitOr => left | right,
+                BinaryOp::BitXor => left ^ right,
+                BinaryOp::LeftShift | BinaryOp::RightShift if !(0..64).contains(&right) => {
+                    return Err(FeraError::type_error(
+                        format!("Shift by {} in constant expression is out of range", right),
+                        span.clone(),
+                    ));
+                }
+                BinaryOp::LeftShift => left << right,
+                BinaryOp::RightShift => left >> right,
+                BinaryOp::LogicalAnd | BinaryOp::LogicalOr => (right != 0) as i64,
+                BinaryOp::Equal => (left == right) as i64,
+                BinaryOp::NotEqual => (left != right) as i64,
+                BinaryOp::Less => (left < right) as i64,
+                BinaryOp::Greater => (left > right) as i64,
+                BinaryOp::LessEqual => (left <= right) as i64,
+                BinaryOp::GreaterEqual => (left >= right) as i64,
+            };
+            Ok(value)
+        }
+        Expr::Ternary(condition, then_expr, else_expr, _) => {
+            if eval_int(condition, decls)? != 0 {
+                eval_int(then_expr, decls)
+            } else {
+                eval_int(else_expr, decls)
+            }
+        }
+        Expr::Cast(ty, operand, span) => {
+            let value = eval_int(operand, decls)?;
+            let ty = decls.underlying(&resolve_type(ty, &decls.types));
+            if ty == Type::Bool {
+                return Ok((value != 0) as i64);
+            }
+            let bits = integer_width(&ty, &decls.model).ok_or_else(|| not_constant(span.clone()))?;
+            Ok(truncate(value, bits, is_signed(&ty)))
+        }
+        _ => Err(not_constant(expr.span())),
+    }
+}
+
+/// The value of a global's initializer
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    /// The contents of a `char` array, or a pointer to a private copy of
+    /// the string with a terminating null
+    String(String),
+    /// A pointer to a global
+    Address(String),
+    Null,
+    /// Elements of an array or fields of a struct, in order. A union is
+    /// initialized through its first field.
+    Aggregate(Vec<Constant>),
+    /// An element or field without an initializer
+    Zero,
+}
+
+/// Evaluate the initializer of a global of type `ty`. Pointers can be
+/// initialized with null, a string literal or the address of one of
+/// `globals` or `functions`; arrays, structs and unions with a braced
+/// list.
+pub fn eval_constant(
+    expr: &Expr,
+    ty: &Type,
+    decls: &Declarations,
+    globals: &HashMap<String, Type>,
+    functions: &HashMap<String, (Type, Vec<Type>)>,
+) -> Result<Constant, FeraError> {
+    let ty = decls.underlying(&resolve_type(ty, &decls.types));
+    match (&ty, expr) {
+        (Type::Array(element, len), Expr::InitList(elements, span)) => {
+            let len = len.unwrap_or(elements.len());
+            if elements.len() > len {
+                return Err(too_many_initializers(&ty, span.clone()));
+            }
+            let mut values = elements.iter()
+                .map(|element_expr| eval_constant(element_expr, element, decls, globals, functions))
+                .collect::<Result<Vec<_>, _>>()?;
+            values.resize(len, Constant::Zero);
+            Ok(Constant::Aggregate(values))
+        }
+        (Type::Array(element, len), Expr::StringLiteral(value, span)) if is_char(element) => {
+            // Like C, the terminating null is dropped when it doesn't fit
+            if len.is_some_and(|len| value.len() > len) {
+                return Err(FeraError::type_error(
+                    format!("String of {} characters does not fit in '{}'", value.len(), ty),
+                    span.clone(),
+                ));
+            }
+            Ok(Constant::String(value.clone()))
+        }
+        (Type::Struct(name) | Type::Union(name), Expr::InitList(elements, span)) => {
+            let fields = decls.records.get(name).ok_or_else(|| not_constant_initializer(span.clone()))?;
+            let len = if matches!(ty, Type::Union(_)) { fields.len().min(1) } else { fields.len() };
+            if elements.len() > len {
+                return Err(too_many_initializers(&ty, span.clone()));
+            }
+            let mut values = elements.iter().zip(fields)
+                .map(|(field_expr, (_, field))| eval_constant(field_expr, field, decls, globals, functions))
+                .collect::<Result<Vec<_>, _>>()?;
+            values.resize(len, Constant::Zero);
+            Ok(Constant::Aggregate(values))
+        }
+        (_, Expr::InitList(_, span)) => Err(FeraError::type_error(
+            format!("Cannot initialize '{}' with a braced list", ty),
+            span.clone(),
+        ).with_suggestion("braced lists initialize arrays, structs and unions")),
+        (Type::Pointer(..), _) => eval_address(expr, decls, globals, functions),
+        (Type::Bool, _) => Ok(Constant::Int((eval_int(expr, decls)? != 0) as i64)),
+        _ if is_integer(&ty) => Ok(Constant::Int(eval_int(expr, decls)?)),
+        _ if is_float(&ty) => Ok(Constant::Float(eval_float(expr, decls)?)),
+        _ => Err(not_constant_initializer(expr.span())),
+    }
+}
+
+/// `ty` with the length of an unsized array taken from its initializer
+pub fn complete_type(ty: &Type, init: Option<&Expr>) -> Type {
+    match (ty, init) {
+        (Type::Array(element, None), Some(Expr::InitList(elements, _))) => {
+            Type::Array(element.clone(), Some(elements.len()))
+        }
+        (Type::Array(element, None), Some(Expr::StringLiteral(value, _))) if is_char(element) => {
+            Type::Array(element.clone(), Some(value.len() + 1))
+        }
+        _ => ty.clone(),
+    }
+}
+
+fn is_char(ty: &Type) -> bool {
+    matches!(ty, Type::Char | Type::I8 | Type::U8)
+}
+
+/// A pointer constant: null, a string literal, or the address of a
+/// global or function. Arrays and functions decay to their address.
+fn eval_address(
+    expr: &Expr,
+    decls: &Declarations,
+    globals: &HashMap<String, Type>,
+    functions: &HashMap<String, (Type, Vec<Type>)>,
+) -> Result<Constant, FeraError> {
+    match expr {
+        Expr::StringLiteral(value, _) => Ok(Constant::String(value.clone())),
+        Expr::Unary(UnaryOp::AddrOf, operand, _) => match operand.as_ref() {
+            Expr::Identifier(name, _) if globals.contains_key(name) || functions.contains_key(name) => {
+                Ok(Constant::Address(name.clone()))
+            }
+            _ => Err(not_constant_initializer(expr.span())),
+        },
+        Expr::Identifier(name, _) if matches!(globals.get(name), Some(Type::Array(..))) => {
+            Ok(Constant::Address(name.clone()))
+        }
+        Expr::Identifier(name, _) if !globals.contains_key(name) && functions.contains_key(name) => {
+            Ok(Constant::Address(name.clone()))
+        }
+        Expr::Cast(ty, operand, _) if matches!(resolve_type(ty, &decls.types), Type::Pointer(..)) => {
+            eval_address(operand, decls, globals, functions)
+        }
+        _ => match eval_int(expr, decls) {
+            Ok(0) => Ok(Constant::Null),
+            _ => Err(not_constant_initializer(expr.span())),
+        },
+    }
+}
+
+/// Evaluate a floating constant expression. Integer constant expressions
+/// are evaluated as integers, then converted.
+fn eval_float(expr: &Expr, decls: &Declarations) -> Result<f64, FeraError> {
+    if let Ok(value) = eval_int(expr, decls) {
+        return Ok(value as f64);
+    }
+    match expr {
+        Expr::FloatLiteral(value, _) => Ok(*value),
+        Expr::Unary(UnaryOp::Neg, operand, _) => Ok(-eval_float(operand, decls)?),
+        Expr::Binary(op @ (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div), left, right, _) => {
+            let (left, right) = (eval_float(left, decls)?, eval_float(right, decls)?);
+            Ok(match op {
+                BinaryOp::Add => left + right,
+                BinaryOp::Sub => left - right,
+                BinaryOp::Mul => left * right,
+                _ => left / right,
+            })
+        }
+        Expr::Ternary(condition, then_expr, else_expr, _) => {
+            if eval_int(condition, decls)? != 0 {
+                eval_float(then_expr, decls)
+            } else {
+                eval_float(else_expr, decls)
+            }
+        }
+        Expr::Cast(ty, operand, _) if is_float(&resolve_type(ty, &decls.types)) => {
+            let value = eval_float(operand, decls)?;
+            // A cast to `f32` rounds
+            Ok(if resolve_type(ty, &decls.types) == Type::F32 { value as f32 as f64 } else { value })
+        }
+        _ => Err(not_constant_initializer(expr.span())),
+    }
+}
+
+fn not_constant_initializer(span: Span) -> FeraError {
+    FeraError::type_error("Initializer of a global must be a constant", span)
+        .with_suggestion("globals are initialized with literals, enum constants, 'sizeof' and addresses of globals")
+}
+
+fn too_many_initializers(ty: &Type, span: Span) -> FeraError {
+    FeraError::type_error(format!("Too many initializers for '{}'", ty), span)
+}
+
+fn not_constant(span: Span) -> FeraError {
+    FeraError::type_error("Expected an integer constant expression", span)
+        .with_suggestion("constant expressions are built from literals, enum constants, 'sizeof' and 'alignof'")
+}
+
+/// Keep the low `bits` bits of `value`, sign-extending them for signed types
+fn truncate(value: i64, bits: u32, signed: bool) -> i64 {
+    if bits >= 64 {
+        return value;
+    }
+    let shift = 64 - bits;
+    if signed {
+        (value << shift) >> shift
+    } else {
+        ((value as u64) << shift >> shift) as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// Length of the array declared last in `source`, or the first error
+    fn array_len(source: &str) -> Result<usize, String> {
+        let tokens = Lexer::new(source).tokenize();
+        let (program, errors) = Parser::new(tokens).parse_program();
+        if let Some(error) = errors.first() {
+            return Err(error.to_string());
+        }
+        match program.items.last() {
+            Some(crate::ast::Item::GlobalVar(global)) => match &global.ty {
+                Type::Array(_, Some(len)) => Ok(*len),
+                ty => panic!("expected sized array, found {}", ty),
+            },
+            item => panic!("expected global, found {:?}", item),
+        }
+    }
+
+    /// Value of the initializer of the global declared last in `source`
+    fn global_value(source: &str) -> Result<Constant, String> {
+        let tokens = Lexer::new(source).tokenize();
+        let (program, errors) = Parser::new(tokens).parse_program();
+        assert!(errors.is_empty(), "unexpected parse errors: {:?}", errors);
+        
+        let mut decls = Declarations::default();
+        let mut globals = HashMap::new();
+        let mut functions = HashMap::new();
+        for item in &program.items {
+            match item {
+                crate::ast::Item::Struct(s) => {
+                    decls.types.insert(s.name.clone(), Type::Struct(s.name.clone()));
+                    let fields = s.fields.iter().map(|f| (f.name.clone(), f.ty.clone())).collect();
+                    decls.records.insert(s.name.clone(), fields);
+                }
+                crate::ast::Item::GlobalVar(global) => {
+                    globals.insert(global.name.clone(), complete_type(&global.ty, global.init.as_ref()));
+                }
+                crate::ast::Item::Function(f) => {
+                    let params = f.params.iter().map(|p| p.ty.clone()).collect();
+                    functions.insert(f.name.clone(), (f.return_type.clone(), params));
+                }
+                _ => {}
+            }
+        }
+        let Some(crate::ast::Item::GlobalVar(global)) = program.items.last() else { panic!("expected global") };
+        let init = global.init.as_ref().expect("expected initializer");
+        eval_constant(init, &globals[&global.name], &decls, &globals, &functions).map_err(|e| e.to_string())
+    }
+    
+    #[test]
+    fn test_global_initializers() {
+        use Constant::*;
+        assert_eq!(global_value("f64 x = -1.5 * 2;"), Ok(Float(-3.0)));
+        assert_eq!(global_value("f32 x = 1 / 4;"), Ok(Float(0.0)));
+        assert_eq!(global_value("bool x = 7;"), Ok(Int(1)));
+        assert_eq!(global_value("char* x = \"hi\";"), Ok(String("hi".to_string())));
+        assert_eq!(global_value("u8* x = (u8*)0;"), Ok(Null));
+        assert_eq!(global_value("i32 n; i32* x = &n;"), Ok(Address("n".to_string())));
+        assert_eq!(global_value("i32 n[3]; i32* x = n;"), Ok(Address("n".to_string())));
+        assert_eq!(global_value("void tick(); void (*x)() = tick;"), Ok(Address("tick".to_string())));
+        assert_eq!(
+            global_value("void tick(); void (*x[2])(void) = {&tick, 0};"),
+            Ok(Aggregate(vec![Address("tick".to_string()), Null])),
+        );
+        assert_eq!(global_value("i32 x[3] = {1, 2,};"), Ok(Aggregate(vec![Int(1), Int(2), Zero])));
+        assert_eq!(
+            global_value("struct P { i32 x; f64 y; }; P x[] = {{1, 2}, {3}};"),
+            Ok(Aggregate(vec![Aggregate(vec![Int(1), Float(2.0)]), Aggregate(vec![Int(3), Zero])])),
+        );
+        
+        assert!(global_value("i32 n; i32* x = &n + 1;").unwrap_err().contains("must be a constant"));
+        assert!(global_value("i32 n; i32 x = n;").unwrap_err().contains("Expected an integer constant expression"));
+        assert!(global_value("i32 x[1] = {1, 2};").unwrap_err().contains("Too many initializers for 'i32[1]'"));
+        assert!(global_value("i32 x = {1};").unwrap_err().contains("Cannot initialize 'i32' with a braced list"));
+        assert!(global_value("char x[2] = \"abc\";").unwrap_err().contains("does not fit in 'char[2]'"));
+    }
+    
+    #[test]
+    fn test_constant_array_sizes() {
+        assert_eq!(array_len("u8 a[2 * 3 + 1];"), Ok(7));
+        assert_eq!(array_len("u8 a[(1 << 4) - 1];"), Ok(15));
+        assert_eq!(array_len("u8 a[1 > 0 ? 2 : 3];"), Ok(2));
+        assert_eq!(array_len("u8 a[(u8)258];"), Ok(2));
+        assert_eq!(array_len("u8 a[-(i8)255];"), Ok(1));
+        assert_eq!(array_len("u8 a[0 && 1 / 0];"), Ok(0));
+
+        assert_eq!(array_len("enum E { A = 3, B, C = B * 2 }; u8 a[C];"), Ok(8));
+        assert_eq!(array_len("enum E : i8 { A = -2, B, C }; u8 a[C + 1];"), Ok(1));
+        
+        assert!(array_len("u8 a[4 / 0];").unwrap_err().contains("Division by zero"));
+        assert!(array_len("u8 a[2 - 3];").unwrap_err().contains("cannot be negative"));
+        assert!(array_len("i32 n; u8 a[n];").unwrap_err().contains("Expected an integer constant expression"));
+    }
+
+    #[test]
+    fn test_sizeof_and_alignof() {
+        let declarations = "
+            struct Header { u8 tag; u32 len; u16 flags; };
+            union Cell { u8 bytes[5]; u32 word; };
+            struct Node { Node* next; Header header; char name[3]; };
+            typedef Header Table[4];
+            enum Color { RED, GREEN };
+            enum Flags : u16 { READ = 1, WRITE = 2 };
+            [[packed]] struct Packed { u8 tag; u32 len; u16 flags; };
+        ";
+        let len = |expr: &str| array_len(&format!("{} u8 a[{}];", declarations, expr));
+        assert_eq!(len("sizeof(char)"), Ok(1));
+        assert_eq!(len("sizeof(i64*)"), Ok(8));
+        assert_eq!(len("sizeof(f64[3])"), Ok(24));
+        assert_eq!(len("sizeof(Header)"), Ok(12));
+        assert_eq!(len("alignof(Header)"), Ok(4));
+        assert_eq!(len("sizeof(union Cell)"), Ok(8));
+        assert_eq!(len("sizeof(Node)"), Ok(24));
+        assert_eq!(len("alignof(Node)"), Ok(8));
+        assert_eq!(len("sizeof(Table) / sizeof(Header)"), Ok(4));
+        assert_eq!(len("sizeof(Color) + sizeof(Flags)"), Ok(6));
+        assert_eq!(len("sizeof(Packed)"), Ok(7));
+        assert_eq!(len("sizeof(Packed[2]) + alignof(Packed)"), Ok(15));
+
+        assert!(len("sizeof(void)").unwrap_err().contains("Cannot take the size of 'void'"));
+    }
+}
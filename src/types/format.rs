@@ -0,0 +1,185 @@
+// printf-style format strings and the arguments their conversions expect
+use crate::ast::Type;
+use crate::types::integer_width;
+use crate::types::layout::DataModel;
+use std::fmt;
+
+/// The printf-family functions and the position of their format argument
+pub fn format_position(function: &str) -> Option<usize> {
+    match function {
+        "printf" => Some(0),
+        "fprintf" | "sprintf" | "dprintf" => Some(1),
+        "snprintf" => Some(2),
+        _ => None,
+    }
+}
+
+/// The argument a conversion takes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    /// A value of this type, or an integer of the same width
+    Value(Type),
+    /// Any pointer (`%p`)
+    Pointer,
+}
+
+/// One argument a format string takes: a conversion like `%ld`, or the
+/// `*` width or precision of one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    pub spec: String,
+    pub expected: Expected,
+}
+
+const FLAGS: &str = "-+ #0'";
+
+/// The arguments `format` takes on a target with the sizes in `model`,
+/// in order, or why it's invalid
+pub fn parse_format(format: &str, model: &DataModel) -> Result<Vec<Conversion>, String> {
+    let mut conversions = Vec::new();
+    let mut chars = format.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        if chars.next_if(|&(_, c)| c == '%').is_some() {
+            continue;
+        }
+
+        // Flags, width, precision, then the length modifier
+        let mut stars = 0;
+        while chars.next_if(|&(_, c)| FLAGS.contains(c)).is_some() {}
+        for precision in [false, true] {
+            if precision && chars.next_if(|&(_, c)| c == '.').is_none() {
+                break;
+            }
+            if chars.next_if(|&(_, c)| c == '*').is_some() {
+                stars += 1;
+            }
+            while chars.next_if(|(_, c)| c.is_ascii_digit()).is_some() {}
+        }
+        let mut length = String::new();
+        while let Some((_, c)) = chars.next_if(|&(_, c)| "hljztL".contains(c)) {
+            length.push(c);
+        }
+
+        let Some((end, conversion)) = chars.next() else {
+            return Err(format!("Format string ends in the middle of '{}'", &format[start..]));
+        };
+        let spec = &format[start..end + conversion.len_utf8()];
+        let long = if model.long_size == 8 { Type::I64 } else { Type::I32 };
+        let integer = match length.as_str() {
+            "" | "hh" | "h" => Some(Type::I32),
+            "l" => Some(long),
+            "ll" | "j" => Some(Type::I64),
+            "z" => Some(Type::USize),
+            "t" => Some(Type::ISize),
+            _ => None,
+        };
+        let expected = match (conversion, integer.clone()) {
+            ('d' | 'i' | 'o' | 'u' | 'x' | 'X', Some(ty)) => Expected::Value(ty),
+            ('c', _) if length.is_empty() => Expected::Value(Type::I32),
+            ('s', _) if length.is_empty() => Expected::Value(Type::Pointer(Box::new(Type::Char), vec![])),
+            ('p', _) if length.is_empty() => Expected::Pointer,
+            ('f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A', _) if matches!(length.as_str(), "" | "l") => {
+                Expected::Value(Type::F64)
+            }
+            ('n', _) => {
+                let count = match length.as_str() {
+                    "hh" => Type::I8,
+                    "h" => Type::I16,
+                    _ => integer.unwrap_or(Type::I32),
+                };
+                Expected::Value(Type::Pointer(Box::new(count), vec![]))
+            }
+            _ => return Err(format!("Unknown conversion '{}' in format string", spec)),
+        };
+
+        for _ in 0..stars {
+            conversions.push(Conversion { spec: spec.to_string(), expected: Expected::Value(Type::I32) });
+        }
+        conversions.push(Conversion { spec: spec.to_string(), expected });
+    }
+    Ok(conversions)
+}
+
+impl Expected {
+    /// Whether an argument of type `found`, after the default argument
+    /// promotions, fits. As with C compilers, the signedness of
+    /// integers and the type pointers point to aren't checked, except
+    /// for strings and `%n`.
+    pub fn accepts(&self, found: &Type, model: &DataModel) -> bool {
+        let found = match found {
+            Type::Array(element, _) => Type::Pointer(element.clone(), vec![]),
+            found => found.clone(),
+        };
+        match (self, &found) {
+            (_, Type::Error) => true,
+            (Expected::Pointer, found) => matches!(found, Type::Pointer(..)),
+            (Expected::Value(Type::Pointer(expected, _)), Type::Pointer(found, _)) => {
+                match (integer_width(expected, model), integer_width(found, model)) {
+                    (Some(expected), Some(found)) => expected == found,
+                    _ => matches!(**found, Type::Void),
+                }
+            }
+            (Expected::Value(expected), found) => match (integer_width(expected, model), integer_width(found, model)) {
+                (Some(expected), Some(found)) => expected == found,
+                _ => expected == found,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Value(ty) => write!(f, "'{}'", ty),
+            Expected::Pointer => write!(f, "a pointer"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::layout::Layout;
+
+    fn expected(format: &str) -> Result<Vec<String>, String> {
+        parse_format(format, &DataModel::LP64).map(|conversions| conversions.iter().map(|c| c.expected.to_string()).collect())
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(expected("100%% done\n"), Ok(vec![]));
+        assert_eq!(
+            expected("%d %-5u %lx %zu %c %s %p %8.3f %n"),
+            Ok(["'i32'", "'i32'", "'i64'", "'usize'", "'i32'", "'char*'", "a pointer", "'f64'", "'i32*'"]
+                .map(String::from)
+                .to_vec()),
+        );
+        assert_eq!(expected("%*.*s"), Ok(["'i32'", "'i32'", "'char*'"].map(String::from).to_vec()));
+        assert_eq!(expected("%q"), Err("Unknown conversion '%q' in format string".to_string()));
+        assert_eq!(expected("%Lf"), Err("Unknown conversion '%Lf' in format string".to_string()));
+        assert_eq!(expected("50%"), Err("Format string ends in the middle of '%'".to_string()));
+    }
+
+    #[test]
+    fn test_accepts() {
+        let string = Type::Pointer(Box::new(Type::Char), vec![]);
+        assert!(Expected::Value(Type::I32).accepts(&Type::U32, &DataModel::LP64));
+        assert!(!Expected::Value(Type::I32).accepts(&Type::I64, &DataModel::LP64));
+        assert!(!Expected::Value(Type::I64).accepts(&Type::F64, &DataModel::LP64));
+        assert!(Expected::Value(Type::F64).accepts(&Type::F64, &DataModel::LP64));
+        assert!(Expected::Value(string.clone()).accepts(&Type::Array(Box::new(Type::U8), Some(4)), &DataModel::LP64));
+        assert!(Expected::Value(string.clone()).accepts(&Type::Pointer(Box::new(Type::Void), vec![]), &DataModel::LP64));
+        assert!(!Expected::Value(string).accepts(&Type::Pointer(Box::new(Type::I32), vec![]), &DataModel::LP64));
+        assert!(Expected::Pointer.accepts(&Type::Pointer(Box::new(Type::I32), vec![]), &DataModel::LP64));
+        assert!(!Expected::Pointer.accepts(&Type::I64, &DataModel::LP64));
+
+        // `long` and `size_t` are 32 bits wide on ILP32 targets
+        let ilp32 = DataModel { pointer: Layout::new(4, 4), long_size: 4, ..DataModel::LP64 };
+        assert!(Expected::Value(Type::USize).accepts(&Type::U32, &ilp32));
+        assert!(!Expected::Value(Type::USize).accepts(&Type::U64, &ilp32));
+        assert_eq!(parse_format("%ld", &ilp32).unwrap()[0].expected, Expected::Value(Type::I32));
+    }
+}
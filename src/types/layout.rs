@@ -0,0 +1,195 @@
+// Memory layout of types, following the C rules with the sizes and
+// alignments of the target
+use crate::ast::Type;
+use std::collections::HashMap;
+
+/// Size and alignment of a type in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub size: u64,
+    pub align: u64,
+}
+
+/// Layout of a struct or union and the offset of each of its fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordLayout {
+    pub layout: Layout,
+    pub offsets: Vec<u64>,
+}
+
+/// What the target's data layout decides about types: the size of a
+/// pointer and the alignment of each scalar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataModel {
+    /// Pointers, and `isize` and `usize`, which are as wide
+    pub pointer: Layout,
+    pub i16_align: u64,
+    pub i32_align: u64,
+    pub i64_align: u64,
+    pub f32_align: u64,
+    pub f64_align: u64,
+    /// Size of C's `long`, which `%ld` takes
+    pub long_size: u64,
+}
+
+impl Layout {
+    pub fn new(size: u64, align: u64) -> Self {
+        Self { size, align }
+    }
+}
+
+impl DataModel {
+    /// 64-bit Unix targets like x86-64 Linux
+    pub const LP64: DataModel = DataModel {
+        pointer: Layout { size: 8, align: 8 },
+        i16_align: 2,
+        i32_align: 4,
+        i64_align: 8,
+        f32_align: 4,
+        f64_align: 8,
+        long_size: 8,
+    };
+    
+    /// Width in bits of pointers, `isize` and `usize`
+    pub fn pointer_bits(&self) -> u32 {
+        self.pointer.size as u32 * 8
+    }
+}
+
+impl Default for DataModel {
+    fn default() -> Self {
+        Self::LP64
+    }
+}
+
+/// Lay out fields in declaration order, padding each to its natural
+/// alignment, or to `packing` if that is smaller. Union fields all start
+/// at offset zero. The total size is rounded up to the alignment of the
+/// strictest field.
+pub fn record_layout(fields: &[Layout], is_union: bool, packing: Option<u64>) -> RecordLayout {
+    let mut offsets = Vec::with_capacity(fields.len());
+    let mut size = 0;
+    let mut align = 1;
+
+    for field in fields {
+        let field = Layout::new(field.size, packing.map_or(field.align, |pack| field.align.min(pack)));
+        align = align.max(field.align);
+        if is_union {
+            offsets.push(0);
+            size = size.max(field.size);
+        } else {
+            let offset = align_to(size, field.align);
+            offsets.push(offset);
+            size = offset + field.size;
+        }
+    }
+
+    RecordLayout {
+        layout: Layout::new(align_to(size, align), align),
+        offsets,
+    }
+}
+
+/// Layout of `ty` on a target with `model`, or None for types without
+/// a size such as `void`, functions, unsized arrays and records that
+/// haven't been laid out
+pub fn layout_of(ty: &Type, records: &HashMap<String, RecordLayout>, model: &DataModel) -> Option<Layout> {
+    match ty {
+        Type::Bool | Type::Char | Type::I8 | Type::U8 => Some(Layout::new(1, 1)),
+        Type::I16 | Type::U16 => Some(Layout::new(2, model.i16_align)),
+        Type::I32 | Type::U32 => Some(Layout::new(4, model.i32_align)),
+        Type::F32 => Some(Layout::new(4, model.f32_align)),
+        Type::I64 | Type::U64 => Some(Layout::new(8, model.i64_align)),
+        Type::F64 => Some(Layout::new(8, model.f64_align)),
+        Type::ISize | Type::USize | Type::Pointer(..) => Some(model.pointer),
+        Type::Array(element, Some(len)) => {
+            let element = layout_of(element, records, model)?;
+            Some(Layout::new(element.size * *len as u64, element.align))
+        }
+        Type::Struct(name) | Type::Union(name) => records.get(name).map(|r| r.layout),
+        _ => None,
+    }
+}
+
+fn align_to(offset: u64, align: u64) -> u64 {
+    offset.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_layout() {
+        let char_ = Layout::new(1, 1);
+        let int = Layout::new(4, 4);
+        let long = Layout::new(8, 8);
+
+        // struct { char; i32; char; } needs padding after both chars
+        let padded = record_layout(&[char_, int, char_], false, None);
+        assert_eq!(padded.offsets, [0, 4, 8]);
+        assert_eq!(padded.layout, Layout::new(12, 4));
+
+        let packed = record_layout(&[long, int, char_, char_], false, None);
+        assert_eq!(packed.offsets, [0, 8, 12, 13]);
+        assert_eq!(packed.layout, Layout::new(16, 8));
+
+        let union = record_layout(&[char_, Layout::new(12, 4), long], true, None);
+        assert_eq!(union.offsets, [0, 0, 0]);
+        assert_eq!(union.layout, Layout::new(16, 8));
+
+        assert_eq!(record_layout(&[], false, None).layout, Layout::new(0, 1));
+    }
+
+    #[test]
+    fn test_packed_record_layout() {
+        let char_ = Layout::new(1, 1);
+        let int = Layout::new(4, 4);
+        let long = Layout::new(8, 8);
+
+        let packed = record_layout(&[char_, int, char_], false, Some(1));
+        assert_eq!(packed.offsets, [0, 1, 5]);
+        assert_eq!(packed.layout, Layout::new(6, 1));
+
+        // Fields are aligned to at most two bytes
+        let packed = record_layout(&[char_, long, char_], false, Some(2));
+        assert_eq!(packed.offsets, [0, 2, 10]);
+        assert_eq!(packed.layout, Layout::new(12, 2));
+
+        // Packing never raises an alignment
+        let loose = record_layout(&[char_, int], false, Some(8));
+        assert_eq!(loose, record_layout(&[char_, int], false, None));
+
+        let union = record_layout(&[char_, Layout::new(6, 2), long], true, Some(4));
+        assert_eq!(union.layout, Layout::new(8, 4));
+    }
+
+    #[test]
+    fn test_target_layouts() {
+        // 32-bit ARM: four byte pointers, but `i64` is still 8-aligned
+        let arm = DataModel { pointer: Layout::new(4, 4), long_size: 4, ..DataModel::LP64 };
+        // 32-bit x86 only aligns `i64` and `f64` to four bytes
+        let i386 = DataModel { i64_align: 4, f64_align: 4, ..arm };
+        let records = HashMap::new();
+        let pointer = Type::Pointer(Box::new(Type::Void), vec![]);
+
+        assert_eq!(layout_of(&pointer, &records, &DataModel::LP64), Some(Layout::new(8, 8)));
+        assert_eq!(layout_of(&pointer, &records, &arm), Some(Layout::new(4, 4)));
+        assert_eq!(layout_of(&Type::USize, &records, &arm), Some(Layout::new(4, 4)));
+        assert_eq!(layout_of(&Type::I64, &records, &arm), Some(Layout::new(8, 8)));
+        assert_eq!(layout_of(&Type::F64, &records, &i386), Some(Layout::new(8, 4)));
+
+        // struct { char; u64; void*; }
+        let fields = |model: &DataModel| -> Vec<Layout> {
+            [Type::Char, Type::U64, pointer.clone()].iter()
+                .map(|ty| layout_of(ty, &records, model).unwrap())
+                .collect()
+        };
+        let lp64 = record_layout(&fields(&DataModel::LP64), false, None);
+        assert_eq!((lp64.offsets, lp64.layout), (vec![0, 8, 16], Layout::new(24, 8)));
+        let arm = record_layout(&fields(&arm), false, None);
+        assert_eq!((arm.offsets, arm.layout), (vec![0, 8, 16], Layout::new(24, 8)));
+        let i386 = record_layout(&fields(&i386), false, None);
+        assert_eq!((i386.offsets, i386.layout), (vec![0, 4, 12], Layout::new(16, 4)));
+    }
+}
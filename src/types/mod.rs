@@ -1,81 +1,152 @@
-use crate::ast::{Type, Program, Item, Expr, Stmt, Block};
-use std::collections::HashMap;
+pub mod asm;
+pub mod attribute;
+pub mod constant;
+pub mod format;
+pub mod layout;
+
+use crate::ast::{Type, Program, Item, Expr, Stmt, Block, Switch, InlineAsm, Span, BinaryOp, UnaryOp};
+use crate::error::FeraError;
+use crate::types::constant::Declarations;
+use crate::types::layout::DataModel;
+use crate::lint::{Lint, LintConfig, LintLevel};
+use std::collections::{HashMap, HashSet};
 
 pub struct TypeChecker {
-    types: HashMap<String, Type>,
+    /// Struct, union, enum and typedef names, the fields of records and
+    /// enum constants
+    decls: Declarations,
     functions: HashMap<String, (Type, Vec<Type>)>,
-    variables: HashMap<String, Type>,
+    /// Messages of the functions marked `[[deprecated]]`
+    deprecated: HashMap<String, String>,
+    /// Functions declared with `...`
+    variadic: HashSet<String>,
+    globals: HashMap<String, Type>,
+    /// Variables of the function being checked, innermost block last
+    scopes: Vec<HashMap<String, Local>>,
     current_function_return_type: Option<Type>,
+    /// Loops and switches around the statement being checked, which
+    /// `break` and `continue` leave
+    enclosing_loops: usize,
+    enclosing_switches: usize,
+    /// Labels of the function being checked
+    labels: HashMap<String, JumpPoint>,
+    /// `goto`s of the function being checked and their targets, resolved
+    /// once every label is known
+    gotos: Vec<(String, JumpPoint)>,
+    lints: LintConfig,
+    errors: Vec<FeraError>,
+}
+
+/// A parameter or local variable in scope
+struct Local {
+    ty: Type,
+    span: Span,
+    is_param: bool,
+    /// Declared with an initializer, which a `goto` can jump past
+    initialized: bool,
+    used: bool,
+}
+
+/// A label or `goto`, with the initialized locals in scope there
+struct JumpPoint {
+    span: Span,
+    initialized: Vec<(String, Span)>,
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
-        let mut checker = Self {
-            types: HashMap::new(),
-            functions: HashMap::new(),
-            variables: HashMap::new(),
+        Self {
+            decls: Declarations::default(),
+            functions: builtin_functions(),
+            deprecated: HashMap::new(),
+            variadic: HashSet::new(),
+            globals: HashMap::new(),
+            scopes: Vec::new(),
             current_function_return_type: None,
-        };
-        
-        // Register built-in functions
-        checker.register_builtins();
-        
-        checker
-    }
-    
-    fn register_builtins(&mut self) {
-        // I/O functions
-        self.functions.insert("print".to_string(), (Type::Void, vec![Type::Pointer(Box::new(Type::Char), vec![])]));
-        self.functions.insert("println".to_string(), (Type::Void, vec![Type::Pointer(Box::new(Type::Char), vec![])]));
-        self.functions.insert("print_i32".to_string(), (Type::Void, vec![Type::I32]));
-        self.functions.insert("print_i64".to_string(), (Type::Void, vec![Type::I64]));
-        self.functions.insert("print_f32".to_string(), (Type::Void, vec![Type::F32]));
-        self.functions.insert("print_f64".to_string(), (Type::Void, vec![Type::F64]));
-        self.functions.insert("print_bool".to_string(), (Type::Void, vec![Type::I32]));
-        self.functions.insert("println_i32".to_string(), (Type::Void, vec![Type::I32]));
-        self.functions.insert("println_i64".to_string(), (Type::Void, vec![Type::I64]));
-        self.functions.insert("println_f32".to_string(), (Type::Void, vec![Type::F32]));
-        self.functions.insert("println_f64".to_string(), (Type::Void, vec![Type::F64]));
-        self.functions.insert("println_bool".to_string(), (Type::Void, vec![Type::I32]));
-        
-        // Math functions
-        self.functions.insert("abs_i32".to_string(), (Type::I32, vec![Type::I32]));
-        self.functions.insert("abs_i64".to_string(), (Type::I64, vec![Type::I64]));
-        self.functions.insert("min_i32".to_string(), (Type::I32, vec![Type::I32, Type::I32]));
-        self.functions.insert("max_i32".to_string(), (Type::I32, vec![Type::I32, Type::I32]));
-        self.functions.insert("sqrt_f32".to_string(), (Type::F32, vec![Type::F32]));
-        self.functions.insert("sqrt_f64".to_string(), (Type::F64, vec![Type::F64]));
-        self.functions.insert("pow_f32".to_string(), (Type::F32, vec![Type::F32, Type::F32]));
-        self.functions.insert("pow_f64".to_string(), (Type::F64, vec![Type::F64, Type::F64]));
-        self.functions.insert("sin_f32".to_string(), (Type::F32, vec![Type::F32]));
-        self.functions.insert("sin_f64".to_string(), (Type::F64, vec![Type::F64]));
-        self.functions.insert("cos_f32".to_string(), (Type::F32, vec![Type::F32]));
-        self.functions.insert("cos_f64".to_string(), (Type::F64, vec![Type::F64]));
-        self.functions.insert("gcd_i32".to_string(), (Type::I32, vec![Type::I32, Type::I32]));
-        self.functions.insert("gcd_i64".to_string(), (Type::I64, vec![Type::I64, Type::I64]));
-    }
-    
-    pub fn check_program(&mut self, program: &Program) -> Result<(), String> {
-        // First pass: collect type definitions and function signatures
+            enclosing_loops: 0,
+            enclosing_switches: 0,
+            labels: HashMap::new(),
+            gotos: Vec::new(),
+            lints: LintConfig::new(),
+            errors: Vec::new(),
+        }
+    }
+    
+    /// Type check every function body, returning all errors and lint
+    /// warnings found
+    pub fn check_program(&mut self, program: &Program) -> Vec<FeraError> {
+        // First pass: collect type definitions, so the signatures and
+        // fields collected next can use records defined after them
         for item in &program.items {
             match item {
                 Item::Struct(s) => {
-                    self.types.insert(s.name.clone(), Type::Struct(s.name.clone()));
+                    self.decls.types.insert(s.name.clone(), Type::Struct(s.name.clone()));
                 }
                 Item::Union(u) => {
-                    self.types.insert(u.name.clone(), Type::Union(u.name.clone()));
+                    self.decls.types.insert(u.name.clone(), Type::Union(u.name.clone()));
                 }
                 Item::Enum(e) => {
-                    self.types.insert(e.name.clone(), Type::Enum(e.name.clone()));
+                    self.decls.types.insert(e.name.clone(), Type::Enum(e.name.clone()));
                 }
                 Item::TypeDef(td) => {
-                    self.types.insert(td.name.clone(), td.ty.clone());
+                    let ty = resolve_type(&td.ty, &self.decls.types);
+                    self.decls.types.insert(td.name.clone(), ty);
                 }
+                _ => {}
+            }
+        }
+        
+        for item in &program.items {
+            let (name, fields, attributes) = match item {
+                Item::Struct(s) => (&s.name, &s.fields, &s.attributes),
+                Item::Union(u) => (&u.name, &u.fields, &u.attributes),
                 Item::Function(f) => {
-                    let param_types = f.params.iter().map(|p| p.ty.clone()).collect();
-                    self.functions.insert(f.name.clone(), (f.return_type.clone(), param_types));
+                    let param_types = f.params.iter().map(|p| resolve_type(&p.ty, &self.decls.types)).collect();
+                    let return_type = resolve_type(&f.return_type, &self.decls.types);
+                    self.functions.insert(f.name.clone(), (return_type, param_types));
+                    if f.is_variadic {
+                        self.variadic.insert(f.name.clone());
+                    }
+                    self.errors.extend(attribute::validate(&f.attributes, attribute::Target::Function));
+                    if let Some(message) = attribute::deprecation(&f.attributes) {
+                        self.deprecated.insert(f.name.clone(), message.to_string());
+                    }
+                    if f.is_variadic && f.body.is_some() {
+                        self.error(FeraError::type_error(
+                            format!("Variadic function '{}' can only be declared", f.name),
+                            f.span.clone(),
+                        ).with_suggestion("Fera can't read the extra arguments yet, so define it in C"));
+                    }
+                    continue;
+                }
+                Item::Enum(e) => {
+                    if let Err(error) = self.decls.declare_enum(e) {
+                        self.errors.push(error);
+                    }
+                    continue;
+                }
+                Item::GlobalVar(g) => {
+                    self.errors.extend(attribute::validate(&g.attributes, attribute::Target::Global));
+                    let ty = constant::complete_type(&resolve_type(&g.ty, &self.decls.types), g.init.as_ref());
+                    self.globals.insert(g.name.clone(), ty);
+                    continue;
+                }
+                _ => continue,
+            };
+            self.errors.extend(attribute::validate(attributes, attribute::Target::Record));
+            let fields = fields.iter()
+                .map(|f| (f.name.clone(), resolve_type(&f.ty, &self.decls.types)))
+                .collect();
+            self.decls.declare_record(name, fields, attributes);
+        }
+        
+        // Globals are initialized before the program starts, so their
+        // initializers must be constants
+        for item in &program.items {
+            if let Item::GlobalVar(g) = item {
+                if let Some(init) = &g.init {
+                    self.check_global_init(&g.name, init);
                 }
-                _ => {}
             }
         }
         
@@ -84,175 +155,1865 @@ impl TypeChecker {
             if let Item::Function(f) = item {
                 if let Some(body) = &f.body {
                     // Set up function context
-                    self.current_function_return_type = Some(f.return_type.clone());
-                    self.variables.clear();
+                    self.current_function_return_type = Some(resolve_type(&f.return_type, &self.decls.types));
                     
-                    // Add parameters to variable scope
+                    // Parameters live in a scope around the body
+                    self.scopes.push(HashMap::new());
                     for param in &f.params {
-                        self.variables.insert(param.name.clone(), param.ty.clone());
+                        let ty = resolve_type(&param.ty, &self.decls.types);
+                        self.declare(&param.name, ty, param.span.clone(), true, true);
+                    }
+                    
+                    let returns = self.check_block(body);
+                    self.resolve_gotos();
+                    if !returns && f.return_type != Type::Void && f.name != "main" {
+                        // Point at the closing brace, where control falls out
+                        let end = body.span.end;
+                        self.lint(
+                            Lint::MissingReturn,
+                            format!("Function '{}' can reach its end without returning a value", f.name),
+                            end.saturating_sub(1)..end,
+                        );
                     }
                     
-                    self.check_block(body)?;
+                    self.pop_scope();
                 }
             }
         }
         
-        Ok(())
+        // Unused-variable warnings are found when scopes close, so put
+        // everything back in source order
+        let mut diagnostics = std::mem::take(&mut self.errors);
+        diagnostics.sort_by_key(|d| d.span().map_or(0, |s| s.start));
+        diagnostics
+    }
+    
+    pub fn set_lints(&mut self, lints: LintConfig) {
+        self.lints = lints;
+    }
+    
+    /// Check against the sizes of `model`'s scalar types
+    pub fn set_data_model(&mut self, model: DataModel) {
+        self.decls.model = model;
+    }
+    
+    fn error(&mut self, error: FeraError) {
+        self.errors.push(error);
+    }
+    
+    fn lint(&mut self, lint: Lint, message: String, span: Span) {
+        let level = self.lints.level(lint);
+        if level != LintLevel::Allow {
+            self.errors.push(FeraError::warning(lint, level, message, span));
+        }
+    }
+    
+    fn declare(&mut self, name: &str, ty: Type, span: Span, is_param: bool, initialized: bool) {
+        if self.scopes.iter().any(|scope| scope.contains_key(name)) {
+            self.lint(Lint::Shadowing, format!("'{}' shadows an earlier declaration", name), span.clone());
+        }
+        
+        let local = Local { ty, span, is_param, initialized, used: false };
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), local);
+        }
     }
     
-    fn check_block(&mut self, block: &Block) -> Result<(), String> {
+    /// Whether `name` is a variable in scope or a global, which hide
+    /// functions of the same name
+    fn is_variable(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains_key(name)) || self.globals.contains_key(name)
+    }
+    
+    /// Whether `expr` names a function
+    fn is_function_name(&self, expr: &Expr) -> bool {
+        matches!(expr, Expr::Identifier(name, _) if !self.is_variable(name) && self.functions.contains_key(name))
+    }
+    
+    /// Look up a variable, marking it as used
+    fn use_variable(&mut self, name: &str) -> Option<Type> {
+        let local = self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name))?;
+        local.used = true;
+        Some(local.ty.clone())
+    }
+
+    /// Check the initializer of global `name`, then evaluate it
+    fn check_global_init(&mut self, name: &str, init: &Expr) {
+        let ty = self.globals[name].clone();
+        let errors = self.errors.len();
+        self.check_initializer(name, &ty, init);
+        // An initializer with a type error isn't also reported as not constant
+        if self.errors[errors..].iter().any(|e| e.is_error()) {
+            return;
+        }
+        if let Err(error) = constant::eval_constant(init, &ty, &self.decls, &self.globals, &self.functions) {
+            self.error(error);
+        }
+    }
+    
+    /// Check that `init` can initialize a `ty`, element by element for
+    /// braced lists. Whether a list fits its type is left to evaluation.
+    fn check_initializer(&mut self, name: &str, ty: &Type, init: &Expr) {
+        match (ty, init) {
+            (Type::Array(element, _), Expr::InitList(elements, _)) => {
+                for element_init in elements {
+                    self.check_initializer(name, element, element_init);
+                }
+            }
+            (Type::Struct(record) | Type::Union(record), Expr::InitList(elements, _)) => {
+                let fields = self.decls.records.get(record).cloned().unwrap_or_default();
+                for (field_init, (_, field)) in elements.iter().zip(&fields) {
+                    self.check_initializer(name, field, field_init);
+                }
+            }
+            (_, Expr::InitList(..)) => {}
+            (Type::Array(element, _), Expr::StringLiteral(..)) if matches!(**element, Type::Char | Type::I8 | Type::U8) => {}
+            _ => {
+                let found = self.infer_type(init);
+                if !self.assignable(ty, &found, init) {
+                    self.error(FeraError::type_error(
+                        format!("Type mismatch in global '{}'", name),
+                        init.span(),
+                    ).with_expected(ty, &found));
+                }
+            }
+        }
+    }
+    
+    /// Infer the type of an operand. Enums compute as their backing type.
+    fn infer_operand(&mut self, expr: &Expr) -> Type {
+        let ty = self.infer_type(expr);
+        self.decls.underlying(&ty)
+    }
+
+    /// Type of `pointer + offset` for a pointer to `element`
+    fn pointer_offset(&mut self, element: Type, offset_ty: &Type, offset: &Expr) -> Type {
+        if *offset_ty != Type::Error && integer_width(offset_ty, &self.decls.model).is_none() {
+            self.error(FeraError::type_error(
+                format!("Pointer offset must be an integer, found '{}'", offset_ty),
+                offset.span(),
+            ));
+        }
+        if element == Type::Void {
+            self.error(FeraError::type_error("Cannot do arithmetic on 'void*'", offset.span())
+                .with_suggestion("cast to 'u8*' to offset by bytes"));
+        }
+        if matches!(element, Type::Function(..)) {
+            self.error(FeraError::type_error("Cannot do arithmetic on a function pointer", offset.span()));
+        }
+        Type::Pointer(Box::new(element), vec![])
+    }
+
+    /// Report an error unless `expr` designates a storage location
+    fn require_lvalue(&mut self, expr: &Expr, operator: &str) {
+        let is_lvalue = matches!(
+            expr,
+            Expr::Identifier(..) | Expr::Unary(UnaryOp::Deref, ..) | Expr::Index(..) | Expr::Field(..)
+        );
+        if !is_lvalue {
+            self.error(FeraError::type_error(
+                format!("Operand of '{}' must be an lvalue", operator),
+                expr.span(),
+            ).with_suggestion("only variables, dereferenced pointers, array elements and fields can be assigned"));
+        }
+    }
+    
+    fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else { return };
+        
+        for (name, local) in scope {
+            if local.used || name.starts_with('_') {
+                continue;
+            }
+            let (lint, what) = if local.is_param {
+                (Lint::UnusedParameter, "parameter")
+            } else {
+                (Lint::UnusedVariable, "variable")
+            };
+            self.lint(lint, format!("Unused {} '{}'", what, name), local.span);
+        }
+    }
+    
+    /// Check a block, returning whether control never reaches its end
+    fn check_block(&mut self, block: &Block) -> bool {
+        self.scopes.push(HashMap::new());
+        
+        let mut diverges = false;
+        let mut warned = false;
         for stmt in &block.stmts {
-            self.check_stmt(stmt)?;
+            // A label can be jumped to, so what follows it is reachable
+            if matches!(stmt, Stmt::Label(..)) {
+                diverges = false;
+            }
+            // Only the first unreachable statement of a block is reported
+            if diverges && !warned {
+                self.lint(Lint::UnreachableCode, "Unreachable statement".to_string(), stmt.span());
+                warned = true;
+            }
+            diverges |= self.check_stmt(stmt);
         }
-        Ok(())
+        
+        self.pop_scope();
+        diverges
     }
     
-    fn check_stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
+    /// Check a statement, returning whether control can't continue past it
+    fn check_stmt(&mut self, stmt: &Stmt) -> bool {
         match stmt {
             Stmt::Let(let_stmt) => {
+                let ty = resolve_type(&let_stmt.ty, &self.decls.types);
                 if let Some(init) = &let_stmt.init {
-                    let expr_type = self.infer_type(init)?;
-                    if !self.types_compatible(&let_stmt.ty, &expr_type) {
-                        return Err(format!(
-                            "Type mismatch in variable '{}': expected {:?}, found {:?}",
-                            let_stmt.name, let_stmt.ty, expr_type
-                        ));
+                    let expr_type = self.infer_type(init);
+                    if !self.assignable(&ty, &expr_type, init) {
+                        self.error(FeraError::type_error(
+                            format!("Type mismatch in variable '{}'", let_stmt.name),
+                            init.span(),
+                        ).with_expected(&ty, &expr_type));
                     }
                 }
-                self.variables.insert(let_stmt.name.clone(), let_stmt.ty.clone());
-                Ok(())
+                self.declare(&let_stmt.name, ty, let_stmt.span.clone(), false, let_stmt.init.is_some());
+                false
             }
             Stmt::Expr(expr) => {
-                self.infer_type(expr)?;
-                Ok(())
+                self.check_expr_stmt(expr);
+                false
             }
-            Stmt::Return(expr_opt, _) => {
+            Stmt::Return(expr_opt, span) => {
                 let return_type = if let Some(expr) = expr_opt {
-                    self.infer_type(expr)?
+                    self.infer_type(expr)
                 } else {
                     Type::Void
                 };
                 
-                if let Some(expected) = &self.current_function_return_type {
-                    if !self.types_compatible(expected, &return_type) {
-                        return Err(format!(
-                            "Return type mismatch: expected {:?}, found {:?}",
-                            expected, return_type
-                        ));
+                if let Some(expected) = self.current_function_return_type.clone() {
+                    let compatible = match expr_opt {
+                        Some(expr) => self.assignable(&expected, &return_type, expr),
+                        None => self.types_compatible(&expected, &return_type),
+                    };
+                    if !compatible {
+                        let span = expr_opt.as_ref().map_or(span.clone(), |e| e.span());
+                        self.error(FeraError::type_error("Return type mismatch", span)
+                            .with_expected(&expected, &return_type));
                     }
                 }
-                Ok(())
+                true
             }
             Stmt::If(if_stmt) => {
-                let cond_type = self.infer_type(&if_stmt.condition)?;
-                if !matches!(cond_type, Type::Bool | Type::I32) {
-                    return Err(format!(
-                        "If condition must be boolean or integer, found {:?}",
-                        cond_type
+                let cond_type = self.infer_type(&if_stmt.condition);
+                if !is_scalar(&cond_type) {
+                    self.error(FeraError::type_error(
+                        format!("If condition must be a number or pointer, found {}", cond_type),
+                        if_stmt.condition.span(),
                     ));
                 }
-                self.check_stmt(&if_stmt.then_branch)?;
-                if let Some(else_branch) = &if_stmt.else_branch {
-                    self.check_stmt(else_branch)?;
-                }
-                Ok(())
+                let then_diverges = self.check_stmt(&if_stmt.then_branch);
+                let else_diverges = match &if_stmt.else_branch {
+                    Some(else_branch) => self.check_stmt(else_branch),
+                    None => false,
+                };
+                then_diverges && else_diverges
             }
             Stmt::While(while_stmt) => {
-                let cond_type = self.infer_type(&while_stmt.condition)?;
-                if !matches!(cond_type, Type::Bool | Type::I32) {
-                    return Err(format!(
-                        "While condition must be boolean or integer, found {:?}",
-                        cond_type
-                    ));
+                self.check_condition(&while_stmt.condition, "While");
+                self.check_loop_body(&while_stmt.body);
+                
+                // `while (1)` only ends through a break
+                always_true(&while_stmt.condition) && !contains_break(&while_stmt.body)
+            }
+            Stmt::DoWhile(do_while) => {
+                self.check_loop_body(&do_while.body);
+                self.check_condition(&do_while.condition, "Do-while");
+                always_true(&do_while.condition) && !contains_break(&do_while.body)
+            }
+            Stmt::For(for_stmt) => {
+                // Variables declared in the initializer belong to the loop
+                self.scopes.push(HashMap::new());
+                if let Some(init) = &for_stmt.init {
+                    self.check_stmt(init);
+                }
+                if let Some(condition) = &for_stmt.condition {
+                    self.check_condition(condition, "For");
+                }
+                if let Some(increment) = &for_stmt.increment {
+                    self.check_expr_stmt(increment);
                 }
-                self.check_stmt(&while_stmt.body)?;
-                Ok(())
+                self.check_loop_body(&for_stmt.body);
+                self.pop_scope();
+                
+                // `for (;;)` only ends through a break
+                let endless = for_stmt.condition.as_ref().is_none_or(always_true);
+                endless && !contains_break(&for_stmt.body)
             }
+            Stmt::Switch(switch) => self.check_switch(switch),
             Stmt::Block(block) => self.check_block(block),
-            _ => Ok(()), // Other statements not fully implemented
+            Stmt::Break(span) => {
+                if self.enclosing_loops + self.enclosing_switches == 0 {
+                    self.error(FeraError::type_error("'break' outside of a loop or switch", span.clone()));
+                }
+                true
+            }
+            Stmt::Label(name, span) => {
+                if self.labels.contains_key(name) {
+                    self.error(FeraError::type_error(format!("Duplicate label '{}'", name), span.clone()));
+                } else {
+                    let label = self.jump_point(span.clone());
+                    self.labels.insert(name.clone(), label);
+                }
+                false
+            }
+            Stmt::Goto(name, span) => {
+                let goto = self.jump_point(span.clone());
+                self.gotos.push((name.clone(), goto));
+                true
+            }
+            Stmt::Continue(span) => {
+                if self.enclosing_loops == 0 {
+                    self.error(FeraError::type_error("'continue' outside of a loop", span.clone()));
+                }
+                true
+            }
+            Stmt::Asm(inline_asm) => {
+                self.check_asm(inline_asm);
+                false
+            }
+        }
+    }
+    
+    /// Check the constraints of an asm statement against its operands,
+    /// and the operands its template refers to
+    fn check_asm(&mut self, inline_asm: &InlineAsm) {
+        let outputs = inline_asm.outputs.len();
+        let operands = inline_asm.outputs.iter().map(|o| (o, true)).chain(inline_asm.inputs.iter().map(|i| (i, false)));
+        for (operand, output) in operands {
+            let ty = self.infer_operand(&operand.expr);
+            let constraint = match asm::parse_constraint(operand, output, outputs) {
+                Ok(constraint) => constraint,
+                Err(error) => {
+                    self.error(error);
+                    continue;
+                }
+            };
+            if output || constraint.kind == asm::Kind::Memory {
+                self.require_lvalue(&operand.expr, "asm");
+            }
+            match constraint.kind {
+                asm::Kind::Immediate if constant::eval_int(&operand.expr, &self.decls).is_err() => {
+                    self.error(FeraError::type_error(
+                        format!("Asm constraint '{}' needs an integer constant", operand.constraint),
+                        operand.expr.span(),
+                    ));
+                }
+                asm::Kind::Register | asm::Kind::Tied(_) if !is_scalar(&ty) => {
+                    self.error(FeraError::type_error(
+                        format!("Asm operand with constraint '{}' must be a number or pointer", operand.constraint),
+                        operand.expr.span(),
+                    ).with_expected("number or pointer", &ty));
+                }
+                _ => {}
+            }
+        }
+        
+        let basic = inline_asm.outputs.is_empty() && inline_asm.inputs.is_empty() && inline_asm.clobbers.is_empty();
+        let operands = outputs + inline_asm.inputs.len();
+        if let Err(error) = asm::llvm_template(&inline_asm.template, basic, operands, &inline_asm.span) {
+            self.error(error);
+        }
+    }
+    
+    /// Check the arguments of a call to a printf-family function against
+    /// its format string, when that's a literal
+    fn check_format(&mut self, function: &str, args: &[Expr], arg_types: &[Type]) {
+        let Some(position) = format::format_position(function) else {
+            return;
+        };
+        let Some(Expr::StringLiteral(text, span)) = args.get(position) else {
+            return;
+        };
+        let conversions = match format::parse_format(text, &self.decls.model) {
+            Ok(conversions) => conversions,
+            Err(message) => {
+                self.lint(Lint::Format, message, span.clone());
+                return;
+            }
+        };
+        
+        let values = &args[position + 1..];
+        for (i, conversion) in conversions.iter().enumerate() {
+            let Some(value) = values.get(i) else {
+                self.lint(Lint::Format, format!("Format '{}' has no matching argument", conversion.spec), span.clone());
+                return;
+            };
+            let ty = &arg_types[position + 1 + i];
+            if !conversion.expected.accepts(&argument_promotion(&self.decls.underlying(ty)), &self.decls.model) {
+                self.lint(
+                    Lint::Format,
+                    format!(
+                        "Format '{}' expects {}, but argument {} has type '{}'",
+                        conversion.spec, conversion.expected, position + 2 + i, ty,
+                    ),
+                    value.span(),
+                );
+            }
+        }
+        if let Some(extra) = values.get(conversions.len()) {
+            self.lint(
+                Lint::Format,
+                format!("Format string takes {} arguments, but {} were provided", conversions.len(), values.len()),
+                extra.span(),
+            );
+        }
+    }
+    
+    /// Note where a label or `goto` is and which initialized locals are
+    /// in scope there
+    fn jump_point(&self, span: Span) -> JumpPoint {
+        let initialized = self.scopes.iter()
+            .flat_map(|scope| scope.iter())
+            .filter(|(_, local)| local.initialized && !local.is_param)
+            .map(|(name, local)| (name.clone(), local.span.clone()))
+            .collect();
+        JumpPoint { span, initialized }
+    }
+    
+    /// Match the `goto`s of a function body with its labels. A jump to a
+    /// label in the scope of a local the `goto` can't see yet skips the
+    /// local's initializer.
+    fn resolve_gotos(&mut self) {
+        let labels = std::mem::take(&mut self.labels);
+        for (name, goto) in std::mem::take(&mut self.gotos) {
+            let Some(label) = labels.get(&name) else {
+                self.error(FeraError::type_error(format!("Undefined label '{}'", name), goto.span)
+                    .with_suggestion("labels are local to the function they are declared in"));
+                continue;
+            };
+            let mut skipped: Vec<_> = label.initialized.iter()
+                .filter(|local| !goto.initialized.contains(local))
+                .collect();
+            skipped.sort_by_key(|(_, span)| span.start);
+            if let Some((local, _)) = skipped.first() {
+                self.lint(
+                    Lint::SkippedInitialization,
+                    format!("Jump to label '{}' skips the initialization of '{}'", name, local),
+                    goto.span,
+                );
+            }
+        }
+    }
+    
+    fn check_condition(&mut self, condition: &Expr, statement: &str) {
+        let cond_type = self.infer_type(condition);
+        if !is_scalar(&cond_type) {
+            self.error(FeraError::type_error(
+                format!("{} condition must be a number or pointer, found {}", statement, cond_type),
+                condition.span(),
+            ));
+        }
+    }
+    
+    fn check_loop_body(&mut self, body: &Stmt) {
+        self.enclosing_loops += 1;
+        self.check_stmt(body);
+        self.enclosing_loops -= 1;
+    }
+    
+    /// Check a switch, returning whether control can't continue past it:
+    /// there is a `default`, nothing breaks out, and the statements of the
+    /// last label never reach its end
+    fn check_switch(&mut self, switch: &Switch) -> bool {
+        let value_type = self.infer_type(&switch.value);
+        if value_type != Type::Error && !is_integer(&self.decls.underlying(&value_type)) {
+            self.error(FeraError::type_error(
+                format!("Switch value must be an integer, found '{}'", value_type),
+                switch.value.span(),
+            ));
+        }
+        
+        let mut seen = HashSet::new();
+        let mut has_default = false;
+        let mut diverges = false;
+        let mut breaks = false;
+        
+        self.enclosing_switches += 1;
+        self.scopes.push(HashMap::new());
+        for case in &switch.cases {
+            match &case.pattern {
+                Some(pattern) => match constant::eval_int(pattern, &self.decls) {
+                    Ok(value) => {
+                        if !seen.insert(value) {
+                            self.error(FeraError::type_error(format!("Duplicate case value {}", value), pattern.span()));
+                        }
+                    }
+                    Err(error) => self.error(error),
+                },
+                None => {
+                    if has_default {
+                        self.error(FeraError::type_error("Multiple 'default' labels in one switch", case.span.clone()));
+                    }
+                    has_default = true;
+                }
+            }
+            
+            // Each label can be jumped to, so reachability starts over
+            diverges = false;
+            let mut warned = false;
+            for stmt in &case.stmts {
+                if matches!(stmt, Stmt::Label(..)) {
+                    diverges = false;
+                }
+                if diverges && !warned {
+                    self.lint(Lint::UnreachableCode, "Unreachable statement".to_string(), stmt.span());
+                    warned = true;
+                }
+                diverges |= self.check_stmt(stmt);
+                breaks |= contains_break(stmt);
+            }
+        }
+        self.pop_scope();
+        self.enclosing_switches -= 1;
+        
+        if let (Type::Enum(name), false) = (&value_type, has_default) {
+            self.check_exhaustive(name, &seen, switch.value.span());
+        }
+        
+        has_default && !breaks && diverges
+    }
+    
+    /// Warn when a switch without `default` over enum `name` has no case
+    /// for some of its constants
+    fn check_exhaustive(&mut self, name: &str, seen: &HashSet<i64>, span: Span) {
+        let Some((_, constants)) = self.decls.enums.get(name) else { return };
+        let missing: Vec<_> = constants.iter()
+            .filter(|constant| self.decls.constants.get(*constant).is_some_and(|(value, _)| !seen.contains(value)))
+            .map(|constant| format!("'{}'", constant))
+            .collect();
+        if !missing.is_empty() {
+            self.lint(
+                Lint::NonExhaustiveSwitch,
+                format!("Switch over enum '{}' does not handle {}", name, missing.join(", ")),
+                span,
+            );
+        }
+    }
+    
+    /// Whether a value of type `found` (computed by `expr`) can be stored
+    /// in a `expected`. Integers convert implicitly, with a lint when the
+    /// conversion can lose information.
+    fn assignable(&mut self, expected: &Type, found: &Type, expr: &Expr) -> bool {
+        // Enums and integers convert implicitly, like in C
+        let (expected, found) = (&self.decls.underlying(expected), &self.decls.underlying(found));
+        match (integer_width(expected, &self.decls.model), integer_width(found, &self.decls.model)) {
+            (Some(to), Some(from)) => {
+                let fits = integer_literal(expr).is_some_and(|value| literal_fits(value, expected, &self.decls.model));
+                if to < from && !fits {
+                    self.lint(
+                        Lint::ImplicitNarrowing,
+                        format!("Implicit conversion from '{}' to '{}' may lose information", found, expected),
+                        expr.span(),
+                    );
+                }
+                true
+            }
+            // Like C, floating types convert implicitly
+            _ if is_float(expected) && is_float(found) => true,
+            // A `bool` is the number 0 or 1
+            _ if is_arithmetic(expected) && *found == Type::Bool => true,
+            // A literal zero is the null pointer
+            (None, Some(_)) if matches!(expected, Type::Pointer(..)) && integer_literal(expr) == Some(0) => true,
+            _ => self.types_compatible(expected, found),
+        }
+    }
+    
+    /// Whether `left == right` and the other comparisons are defined:
+    /// between numbers, and between pointers to compatible types or a
+    /// pointer and the null constant
+    fn comparable(&self, left_ty: &Type, left: &Expr, right_ty: &Type, right: &Expr) -> bool {
+        match (pointee(left_ty), pointee(right_ty)) {
+            _ if is_arithmetic(left_ty) && is_arithmetic(right_ty) => true,
+            (Some(a), Some(b)) => {
+                self.types_compatible(&Type::Pointer(Box::new(a), vec![]), &Type::Pointer(Box::new(b), vec![]))
+            }
+            (Some(_), None) => integer_literal(right) == Some(0),
+            (None, Some(_)) => integer_literal(left) == Some(0),
+            (None, None) => false,
         }
     }
     
     fn types_compatible(&self, expected: &Type, found: &Type) -> bool {
         // Simple type compatibility check
         match (expected, found) {
-            (Type::I32, Type::I32) => true,
-            (Type::I64, Type::I64) => true,
-            (Type::F32, Type::F32) => true,
-            (Type::F64, Type::F64) => true,
-            (Type::Bool, Type::Bool) => true,
-            (Type::Char, Type::Char) => true,
+            // Already reported where the error type was produced
+            (Type::Error, _) | (_, Type::Error) => true,
             (Type::Void, Type::Void) => true,
+            (a, b) if is_arithmetic(a) => a == b,
+            // `void*` converts to and from any object pointer
+            (Type::Pointer(a, _), Type::Pointer(b, _)) if **a == Type::Void || **b == Type::Void => true,
             (Type::Pointer(a, _), Type::Pointer(b, _)) => self.types_compatible(a, b),
+            // Arrays decay to a pointer to their first element
+            (Type::Pointer(a, _), Type::Array(b, _)) => self.types_compatible(a, b),
+            (Type::Struct(a), Type::Struct(b)) | (Type::Union(a), Type::Union(b)) => a == b,
+            (Type::Function(..), Type::Function(..)) => expected == found,
             _ => false,
         }
     }
     
-    pub fn infer_type(&self, expr: &Expr) -> Result<Type, String> {
+    /// Check a call and return its result type, which is void for a
+    /// call that can only be a statement
+    fn check_call(&mut self, func: &Expr, args: &[Expr], span: &Span) -> Type {
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.infer_type(arg)).collect();
+        
+        let (callee, ret_ty, param_types, variadic) = match func {
+            Expr::Identifier(name, _) if !self.is_variable(name) => {
+                let Some((ret_ty, param_types)) = self.functions.get(name).cloned() else {
+                    self.error(FeraError::type_error(format!("Unknown function '{}'", name), func.span())
+                        .with_suggestion("did you forget to define it?"));
+                    return Type::Error;
+                };
+                
+                if let Some(message) = self.deprecated.get(name).cloned() {
+                    let mut warning = format!("Function '{}' is deprecated", name);
+                    if !message.is_empty() {
+                        warning = format!("{}: {}", warning, message);
+                    }
+                    self.lint(Lint::Deprecated, warning, func.span());
+                }
+                let variadic = self.variadic.contains(name);
+                if variadic {
+                    self.check_format(name, args, &arg_types);
+                }
+                (format!("function '{}'", name), ret_ty, param_types, variadic)
+            }
+            _ => {
+                let ty = self.infer_type(func);
+                let Some((ret_ty, param_types, variadic)) = function_signature(&ty) else {
+                    if ty != Type::Error {
+                        self.error(FeraError::type_error(format!("Cannot call '{}'", ty), func.span())
+                            .with_suggestion("only functions and function pointers can be called"));
+                    }
+                    return Type::Error;
+                };
+                let callee = match func {
+                    Expr::Identifier(name, _) => format!("function pointer '{}'", name),
+                    _ => "function pointer".to_string(),
+                };
+                (callee, ret_ty.clone(), param_types.to_vec(), variadic)
+            }
+        };
+        
+        // Check argument count
+        if args.len() < param_types.len() || (args.len() > param_types.len() && !variadic) {
+            let at_least = if variadic { "at least " } else { "" };
+            let mut message = format!(
+                "{} expects {}{} arguments, but {} were provided",
+                callee, at_least, param_types.len(), args.len()
+            );
+            message[..1].make_ascii_uppercase();
+            self.error(FeraError::type_error(message, span.clone()));
+        }
+        
+        // The extra arguments of a variadic function are passed
+        // as they are, after the default argument promotions
+        for (arg, arg_ty) in args.iter().zip(&arg_types).skip(param_types.len()) {
+            let value = self.decls.underlying(arg_ty);
+            if !is_scalar(&value) && !matches!(value, Type::Array(..)) {
+                self.error(FeraError::type_error(
+                    format!("Cannot pass '{}' as a variadic argument of {}", arg_ty, callee),
+                    arg.span(),
+                ).with_suggestion("pass a pointer to it instead"));
+            }
+        }
+        
+        // Check argument types
+        for (i, ((arg, arg_ty), expected_ty)) in args.iter().zip(&arg_types).zip(&param_types).enumerate() {
+            if !self.assignable(expected_ty, arg_ty, arg) {
+                self.error(FeraError::type_error(
+                    format!("Type mismatch in argument {} of {}", i + 1, callee),
+                    arg.span(),
+                ).with_expected(expected_ty, arg_ty));
+            }
+        }
+        
+        ret_ty
+    }
+    
+    /// Check an expression whose value is discarded, which may be a void
+    /// call or a cast to void
+    fn check_expr_stmt(&mut self, expr: &Expr) {
         match expr {
-            Expr::IntLiteral(_, _) => Ok(Type::I32),
-            Expr::FloatLiteral(_, _) => Ok(Type::F64),
-            Expr::StringLiteral(_, _) => Ok(Type::Pointer(
+            Expr::Call(func, args, span) => {
+                self.check_call(func, args, span);
+            }
+            Expr::Cast(ty, operand, _) if resolve_type(ty, &self.decls.types) == Type::Void => {
+                self.check_expr_stmt(operand);
+            }
+            expr => {
+                self.infer_type(expr);
+            }
+        }
+    }
+    
+    /// Infer the type of `expr`, recording any errors in it. Expressions
+    /// that can't be typed get `Type::Error`.
+    pub fn infer_type(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::IntLiteral(_, ty, _) => ty.clone(),
+            Expr::FloatLiteral(_, _) => Type::F64,
+            Expr::StringLiteral(_, _) => Type::Pointer(
                 Box::new(Type::Char),
                 vec![crate::ast::TypeQualifier::Const],
-            )),
-            Expr::CharLiteral(_, _) => Ok(Type::Char),
-            Expr::BoolLiteral(_, _) => Ok(Type::Bool),
-            Expr::Identifier(name, _) => {
-                // First check variables, then types
-                if let Some(ty) = self.variables.get(name) {
-                    Ok(ty.clone())
-                } else if let Some(ty) = self.types.get(name) {
-                    Ok(ty.clone())
+            ),
+            Expr::CharLiteral(_, _) => Type::Char,
+            Expr::BoolLiteral(_, _) => Type::Bool,
+            Expr::Identifier(name, span) => {
+                // First check variables, then globals, functions, enum
+                // constants and types
+                if let Some(ty) = self.use_variable(name) {
+                    ty
+                } else if let Some(ty) = self.globals.get(name) {
+                    ty.clone()
+                } else if let Some((ret, params)) = self.functions.get(name) {
+                    function_pointer(ret, params, self.variadic.contains(name))
+                } else if let Some((_, ty)) = self.decls.constants.get(name) {
+                    ty.clone()
+                } else if let Some(ty) = self.decls.types.get(name) {
+                    ty.clone()
                 } else {
-                    Err(format!("Unknown identifier '{}'. Did you forget to declare it?", name))
-                }
-            }
-            Expr::Binary(_, left, right, _) => {
-                let left_ty = self.infer_type(left)?;
-                let right_ty = self.infer_type(right)?;
-                // TODO: Proper type coercion rules
-                Ok(left_ty)
-            }
-            Expr::Unary(_, expr, _) => self.infer_type(expr),
-            Expr::Call(func, args, _) => {
-                if let Expr::Identifier(name, _) = func.as_ref() {
-                    if let Some((ret_ty, param_types)) = self.functions.get(name) {
-                        // Check argument count
-                        if args.len() != param_types.len() {
-                            return Err(format!(
-                                "Function '{}' expects {} arguments, but {} were provided",
-                                name, param_types.len(), args.len()
-                            ));
+                    self.error(FeraError::type_error(format!("Unknown identifier '{}'", name), span.clone())
+                        .with_suggestion("did you forget to declare it?"));
+                    // Declare it so later uses aren't reported again
+                    if let Some(scope) = self.scopes.last_mut() {
+                        let local = Local { ty: Type::Error, span: span.clone(), is_param: false, initialized: false, used: true };
+                        scope.insert(name.clone(), local);
+                    }
+                    Type::Error
+                }
+            }
+            Expr::Binary(op, left, right, span) => {
+                let left_ty = self.infer_operand(left);
+                let right_ty = self.infer_operand(right);
+                match (op, pointee(&left_ty), pointee(&right_ty)) {
+                    (BinaryOp::Add | BinaryOp::Sub, Some(element), None) => {
+                        self.pointer_offset(element, &right_ty, right)
+                    }
+                    (BinaryOp::Add, None, Some(element)) => self.pointer_offset(element, &left_ty, left),
+                    (BinaryOp::Sub, Some(a), Some(b)) => {
+                        if !self.types_compatible(&a, &b) {
+                            self.error(FeraError::type_error("Cannot subtract pointers to different types", span.clone())
+                                .with_expected(Type::Pointer(Box::new(a), vec![]), &right_ty));
+                        }
+                        Type::ISize
+                    }
+                    // Comparisons and logical operators produce a `bool`
+                    _ if is_comparison(op) => {
+                        if left_ty == Type::Error || right_ty == Type::Error {
+                            return Type::Bool;
+                        }
+                        if matches!(op, BinaryOp::LogicalAnd | BinaryOp::LogicalOr) {
+                            for (ty, operand) in [(&left_ty, left), (&right_ty, right)] {
+                                if !is_scalar(ty) {
+                                    self.error(FeraError::type_error(
+                                        format!("Operands of '{}' must be numbers or pointers, found '{}'", op.symbol(), ty),
+                                        operand.span(),
+                                    ));
+                                }
+                            }
+                        } else if !self.comparable(&left_ty, left, &right_ty, right) {
+                            self.error(FeraError::type_error(
+                                format!("Cannot compare '{}' with '{}'", left_ty, right_ty),
+                                span.clone(),
+                            ).with_expected(&left_ty, &right_ty));
                         }
-                        
-                        // Check argument types
-                        for (i, (arg, expected_ty)) in args.iter().zip(param_types.iter()).enumerate() {
-                            let arg_ty = self.infer_type(arg)?;
-                            if !self.types_compatible(expected_ty, &arg_ty) {
-                                return Err(format!(
-                                    "Type mismatch in argument {} of function '{}': expected {:?}, found {:?}",
-                                    i + 1, name, expected_ty, arg_ty
+                        Type::Bool
+                    }
+                    _ if left_ty == Type::Error || right_ty == Type::Error => Type::Error,
+                    _ => {
+                        let integer_only = !matches!(op, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div);
+                        for (ty, operand) in [(&left_ty, left), (&right_ty, right)] {
+                            let allowed = if integer_only { is_integer(ty) } else { is_arithmetic(ty) };
+                            if !allowed {
+                                let kind = if integer_only { "integers" } else { "numbers" };
+                                self.error(FeraError::type_error(
+                                    format!("Operands of '{}' must be {}, found '{}'", op.symbol(), kind, ty),
+                                    operand.span(),
                                 ));
+                                return Type::Error;
                             }
                         }
-                        
-                        Ok(ret_ty.clone())
-                    } else {
-                        Err(format!("Unknown function '{}'. Did you forget to define it?", name))
+                        binary_result_type(op, &left_ty, &right_ty, &self.decls.model)
                     }
-                } else {
-                    Err("Function call on non-identifier not supported".to_string())
                 }
             }
+            Expr::Index(base, index, _) => {
+                let base_ty = self.infer_type(base);
+                let index_ty = self.infer_operand(index);
+                if index_ty != Type::Error && integer_width(&index_ty, &self.decls.model).is_none() {
+                    self.error(FeraError::type_error(
+                        format!("Array index must be an integer, found '{}'", index_ty),
+                        index.span(),
+                    ));
+                }
+                match pointee(&base_ty) {
+                    Some(element) => element,
+                    None if base_ty == Type::Error => Type::Error,
+                    None => {
+                        self.error(FeraError::type_error(format!("Cannot index into '{}'", base_ty), base.span())
+                            .with_suggestion("only arrays and pointers can be indexed"));
+                        Type::Error
+                    }
+                }
+            }
+            Expr::Unary(op @ (UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec), operand, _) => {
+                let symbol = if matches!(op, UnaryOp::PreInc | UnaryOp::PostInc) { "++" } else { "--" };
+                let ty = self.infer_operand(operand);
+                self.require_lvalue(operand, symbol);
+                if ty != Type::Error && integer_width(&ty, &self.decls.model).is_none() && !matches!(ty, Type::Pointer(..)) {
+                    self.error(FeraError::type_error(
+                        format!("Operand of '{}' must be an integer or pointer, found '{}'", symbol, ty),
+                        operand.span(),
+                    ));
+                    return Type::Error;
+                }
+                ty
+            }
+            Expr::Unary(UnaryOp::Deref, operand, _) => match self.infer_type(operand) {
+                // `*f` designates the function, which decays right back
+                ty if function_signature(&ty).is_some() => ty,
+                Type::Pointer(pointee, _) => *pointee,
+                Type::Error => Type::Error,
+                ty => {
+                    self.error(FeraError::type_error(format!("Cannot dereference '{}'", ty), operand.span())
+                        .with_suggestion("only pointers can be dereferenced"));
+                    Type::Error
+                }
+            },
+            Expr::Unary(UnaryOp::AddrOf, operand, _) => {
+                let ty = self.infer_type(operand);
+                // A function's address is what its name decays to
+                if self.is_function_name(operand) {
+                    return ty;
+                }
+                self.require_lvalue(operand, "&");
+                match ty {
+                    Type::Error => Type::Error,
+                    ty => Type::Pointer(Box::new(ty), vec![]),
+                }
+            }
+            Expr::Unary(UnaryOp::Not, operand, _) => {
+                let ty = self.infer_operand(operand);
+                if !is_scalar(&ty) {
+                    self.error(FeraError::type_error(
+                        format!("Operand of '!' must be a number or pointer, found '{}'", ty),
+                        operand.span(),
+                    ));
+                }
+                Type::Bool
+            }
+            Expr::Unary(op, operand, _) => {
+                let ty = self.infer_operand(operand);
+                let allowed = if *op == UnaryOp::BitNot { is_integer(&ty) } else { is_arithmetic(&ty) };
+                if ty != Type::Error && !allowed {
+                    let (symbol, kind) = if *op == UnaryOp::BitNot { ("~", "an integer") } else { ("-", "a number") };
+                    self.error(FeraError::type_error(
+                        format!("Operand of '{}' must be {}, found '{}'", symbol, kind, ty),
+                        operand.span(),
+                    ));
+                    return Type::Error;
+                }
+                promote(&ty)
+            }
+            Expr::Field(base, field, span) => {
+                let base_ty = self.infer_type(base);
+                let fields = match &base_ty {
+                    Type::Struct(name) | Type::Union(name) => self.decls.records.get(name),
+                    Type::Error => return Type::Error,
+                    _ => None,
+                };
+                let Some(fields) = fields else {
+                    self.error(FeraError::type_error(format!("Type '{}' has no fields", base_ty), base.span())
+                        .with_suggestion("use '->' to access fields through a pointer"));
+                    return Type::Error;
+                };
+                match fields.iter().find(|(name, _)| name == field) {
+                    Some((_, ty)) => ty.clone(),
+                    None => {
+                        let known: Vec<_> = fields.iter().map(|(name, _)| name.as_str()).collect();
+                        self.error(FeraError::type_error(
+                            format!("No field '{}' on type '{}'", field, base_ty),
+                            span.clone(),
+                        ).with_suggestion(format!("available fields: {}", known.join(", "))));
+                        Type::Error
+                    }
+                }
+            }
+            Expr::Call(func, args, span) => {
+                let ty = self.check_call(func, args, span);
+                if ty == Type::Void {
+                    let message = match func.as_ref() {
+                        Expr::Identifier(name, _) => format!("Cannot use the result of '{}', which returns void", name),
+                        _ => "Cannot use the result of a call that returns void".to_string(),
+                    };
+                    self.error(FeraError::type_error(message, span.clone())
+                        .with_suggestion("a void call can only be a statement of its own"));
+                    return Type::Error;
+                }
+                ty
+            }
             Expr::Assign(lhs, rhs, _) => {
-                let rhs_ty = self.infer_type(rhs)?;
-                // For now, just return the rhs type
-                // TODO: Check that lhs is assignable
-                Ok(rhs_ty)
+                let lhs_ty = self.infer_type(lhs);
+                let rhs_ty = self.infer_type(rhs);
+                self.require_lvalue(lhs, "=");
+                if !self.assignable(&lhs_ty, &rhs_ty, rhs) {
+                    self.error(FeraError::type_error("Type mismatch in assignment", rhs.span())
+                        .with_expected(&lhs_ty, &rhs_ty));
+                }
+                lhs_ty
+            }
+            Expr::Ternary(cond, then_expr, else_expr, _) => {
+                self.infer_type(cond);
+                let then_ty = self.infer_type(then_expr);
+                let else_ty = self.infer_type(else_expr);
+                // Branches of the same enum type keep it
+                if matches!(then_ty, Type::Enum(_)) && then_ty == else_ty {
+                    return then_ty;
+                }
+                let (then_ty, else_ty) = (self.decls.underlying(&then_ty), self.decls.underlying(&else_ty));
+                if is_arithmetic(&then_ty) && is_arithmetic(&else_ty) {
+                    return arithmetic_conversion(&then_ty, &else_ty, &self.decls.model);
+                }
+                match (&then_ty, &else_ty) {
+                    _ if then_ty == else_ty || else_ty == Type::Error => then_ty,
+                    (Type::Error, _) => else_ty,
+                    _ => {
+                        self.error(FeraError::type_error("Branches of '?:' have different types", else_expr.span())
+                            .with_expected(&then_ty, &else_ty));
+                        Type::Error
+                    }
+                }
+            }
+            Expr::Cast(ty, operand, span) => {
+                let ty = resolve_type(ty, &self.decls.types);
+                if ty == Type::Void {
+                    self.check_expr_stmt(operand);
+                    self.error(FeraError::type_error("Cannot use the value of a cast to 'void'", span.clone())
+                        .with_suggestion("a cast to void can only be a statement of its own"));
+                    return Type::Error;
+                }
+                let operand_ty = self.infer_type(operand);
+                if operand_ty != Type::Error && !castable(&operand_ty, &ty) {
+                    self.error(FeraError::type_error(
+                        format!("Cannot cast '{}' to '{}'", operand_ty, ty),
+                        span.clone(),
+                    ).with_suggestion("casts convert between numbers, enums and pointers"));
+                    return Type::Error;
+                }
+                ty
+            }
+            Expr::Sizeof(ty, span) | Expr::Alignof(ty, span) => {
+                if let Err(error) = self.decls.layout(ty, span.clone()) {
+                    self.error(error);
+                }
+                Type::USize
+            }
+            Expr::SizeofValue(operand, span) => {
+                let ty = self.infer_type(operand);
+                if ty != Type::Error {
+                    if let Err(error) = self.decls.layout(&ty, span.clone()) {
+                        self.error(error);
+                    }
+                }
+                Type::USize
+            }
+            Expr::InitList(_, span) => {
+                self.error(FeraError::type_error("A braced list can only initialize a global", span.clone()));
+                Type::Error
+            }
+            Expr::CompoundAssign(op, lhs, rhs, _) => {
+                let lhs_ty = self.infer_operand(lhs);
+                let rhs_ty = self.infer_operand(rhs);
+                let symbol = format!("{}=", op.symbol());
+                self.require_lvalue(lhs, &symbol);
+                let arithmetic = matches!(op, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div);
+                if let (Type::Pointer(element, _), BinaryOp::Add | BinaryOp::Sub) = (&lhs_ty, op) {
+                    self.pointer_offset(element.as_ref().clone(), &rhs_ty, rhs);
+                    return lhs_ty;
+                }
+                for (ty, operand) in [(&lhs_ty, lhs), (&rhs_ty, rhs)] {
+                    let allowed = integer_width(ty, &self.decls.model).is_some() || (arithmetic && is_float(ty));
+                    if *ty != Type::Error && !allowed {
+                        let kind = if arithmetic { "numbers" } else { "integers" };
+                        self.error(FeraError::type_error(
+                            format!("Operands of '{}' must be {}, found '{}'", symbol, kind, ty),
+                            operand.span(),
+                        ));
+                    }
+                }
+                lhs_ty
             }
-            _ => Err("Type inference not implemented for this expression".to_string()),
         }
     }
 }
 
+/// Return and parameter types of the functions the standard library
+/// provides without a declaration
+pub fn builtin_functions() -> HashMap<String, (Type, Vec<Type>)> {
+    let mut functions = HashMap::new();
+    // I/O functions
+    functions.insert("print".to_string(), (Type::Void, vec![Type::Pointer(Box::new(Type::Char), vec![])]));
+    functions.insert("println".to_string(), (Type::Void, vec![Type::Pointer(Box::new(Type::Char), vec![])]));
+    functions.insert("print_i32".to_string(), (Type::Void, vec![Type::I32]));
+    functions.insert("print_i64".to_string(), (Type::Void, vec![Type::I64]));
+    functions.insert("print_f32".to_string(), (Type::Void, vec![Type::F32]));
+    functions.insert("print_f64".to_string(), (Type::Void, vec![Type::F64]));
+    functions.insert("print_bool".to_string(), (Type::Void, vec![Type::I32]));
+    functions.insert("println_i32".to_string(), (Type::Void, vec![Type::I32]));
+    functions.insert("println_i64".to_string(), (Type::Void, vec![Type::I64]));
+    functions.insert("println_f32".to_string(), (Type::Void, vec![Type::F32]));
+    functions.insert("println_f64".to_string(), (Type::Void, vec![Type::F64]));
+    functions.insert("println_bool".to_string(), (Type::Void, vec![Type::I32]));
+
+    // Math functions
+    functions.insert("abs_i32".to_string(), (Type::I32, vec![Type::I32]));
+    functions.insert("abs_i64".to_string(), (Type::I64, vec![Type::I64]));
+    functions.insert("min_i32".to_string(), (Type::I32, vec![Type::I32, Type::I32]));
+    functions.insert("max_i32".to_string(), (Type::I32, vec![Type::I32, Type::I32]));
+    functions.insert("sqrt_f32".to_string(), (Type::F32, vec![Type::F32]));
+    functions.insert("sqrt_f64".to_string(), (Type::F64, vec![Type::F64]));
+    functions.insert("pow_f32".to_string(), (Type::F32, vec![Type::F32, Type::F32]));
+    functions.insert("pow_f64".to_string(), (Type::F64, vec![Type::F64, Type::F64]));
+    functions.insert("sin_f32".to_string(), (Type::F32, vec![Type::F32]));
+    functions.insert("sin_f64".to_string(), (Type::F64, vec![Type::F64]));
+    functions.insert("cos_f32".to_string(), (Type::F32, vec![Type::F32]));
+    functions.insert("cos_f64".to_string(), (Type::F64, vec![Type::F64]));
+    functions.insert("gcd_i32".to_string(), (Type::I32, vec![Type::I32, Type::I32]));
+    functions.insert("gcd_i64".to_string(), (Type::I64, vec![Type::I64, Type::I64]));
+
+    functions
+}
+
+/// Replace type names with the types they stand for, using the struct,
+/// union, enum and typedef names in `types`
+pub fn resolve_type(ty: &Type, types: &HashMap<String, Type>) -> Type {
+    match ty {
+        Type::Named(name) | Type::Enum(name) => types.get(name).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Pointer(inner, qualifiers) => Type::Pointer(Box::new(resolve_type(inner, types)), qualifiers.clone()),
+        Type::Array(inner, len) => Type::Array(Box::new(resolve_type(inner, types)), *len),
+        Type::Function(ret, params, variadic) => Type::Function(
+            Box::new(resolve_type(ret, types)),
+            params.iter().map(|p| resolve_type(p, types)).collect(),
+            *variadic,
+        ),
+        _ => ty.clone(),
+    }
+}
+
+pub fn is_float(ty: &Type) -> bool {
+    matches!(ty, Type::F32 | Type::F64)
+}
+
+/// Integer types, including `bool` and `char`
+pub fn is_integer(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::Bool | Type::Char | Type::I8 | Type::U8 | Type::I16 | Type::U16 | Type::I32 | Type::U32
+            | Type::I64 | Type::U64 | Type::ISize | Type::USize
+    )
+}
+
+/// Types that take part in the usual arithmetic conversions
+pub fn is_arithmetic(ty: &Type) -> bool {
+    is_integer(ty) || is_float(ty)
+}
+
+/// The integer promotions: types narrower than `i32` compute as `i32`
+pub fn promote(ty: &Type) -> Type {
+    match ty {
+        Type::Bool | Type::Char | Type::I8 | Type::U8 | Type::I16 | Type::U16 => Type::I32,
+        _ => ty.clone(),
+    }
+}
+
+/// The default argument promotions, applied to the arguments a variadic
+/// function takes after its parameters: integer promotion, and `f32`
+/// becomes `f64`
+pub fn argument_promotion(ty: &Type) -> Type {
+    match ty {
+        Type::F32 => Type::F64,
+        _ => promote(ty),
+    }
+}
+
+/// The usual arithmetic conversions: the common type two operands are
+/// converted to before an arithmetic, bitwise or comparison operator.
+/// Floats win over integers; otherwise both are promoted and the wider
+/// wins. At equal widths the unsigned type wins.
+pub fn arithmetic_conversion(a: &Type, b: &Type, model: &DataModel) -> Type {
+    match (a, b) {
+        (Type::F64, _) | (_, Type::F64) => return Type::F64,
+        (Type::F32, _) | (_, Type::F32) => return Type::F32,
+        _ => {}
+    }
+    
+    let (a, b) = (promote(a), promote(b));
+    match (integer_width(&a, model), integer_width(&b, model)) {
+        (Some(a_width), Some(b_width)) if a_width > b_width => a,
+        (Some(a_width), Some(b_width)) if b_width > a_width => b,
+        _ if !is_signed(&b) => b,
+        _ => a,
+    }
+}
+
+/// The type both arithmetic operands of `left op right` are converted
+/// to before the operation. Shifts take the promoted type of their left
+/// operand, which the right one is converted to as well since LLVM
+/// shifts need equal widths.
+pub fn operand_type(op: &BinaryOp, left: &Type, right: &Type, model: &DataModel) -> Type {
+    match op {
+        BinaryOp::LeftShift | BinaryOp::RightShift => promote(left),
+        _ => arithmetic_conversion(left, right, model),
+    }
+}
+
+/// Type of `left op right` for arithmetic operands: the operand type,
+/// except that comparisons produce `bool`
+pub fn binary_result_type(op: &BinaryOp, left: &Type, right: &Type, model: &DataModel) -> Type {
+    if is_comparison(op) {
+        Type::Bool
+    } else {
+        operand_type(op, left, right, model)
+    }
+}
+
+/// Whether an explicit cast can convert a `from` to a `to`: between
+/// numbers and enums, between integers and pointers, between pointers,
+/// and from anything to `void`
+pub fn castable(from: &Type, to: &Type) -> bool {
+    let integral = |ty: &Type| is_integer(ty) || matches!(ty, Type::Enum(_));
+    let pointer = |ty: &Type| matches!(ty, Type::Pointer(..) | Type::Array(..));
+    match to {
+        Type::Void => true,
+        _ if from == to => true,
+        _ if integral(to) || is_float(to) => integral(from) || is_float(from) || (pointer(from) && integral(to)),
+        Type::Pointer(..) => pointer(from) || integral(from),
+        _ => false,
+    }
+}
+
+/// The type a function's name decays to, a pointer to the function
+pub fn function_pointer(ret: &Type, params: &[Type], variadic: bool) -> Type {
+    Type::Pointer(Box::new(Type::Function(Box::new(ret.clone()), params.to_vec(), variadic)), vec![])
+}
+
+/// The return and parameter types of a function pointer, and whether
+/// it takes more arguments after the parameters
+pub fn function_signature(ty: &Type) -> Option<(&Type, &[Type], bool)> {
+    match ty {
+        Type::Pointer(pointee, _) => match pointee.as_ref() {
+            Type::Function(ret, params, variadic) => Some((ret, params, *variadic)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a value of `ty` can be tested against zero
+pub fn is_scalar(ty: &Type) -> bool {
+    is_integer(ty) || is_float(ty) || matches!(ty, Type::Enum(_) | Type::Pointer(..) | Type::Error)
+}
+
+/// The element type a pointer or (decayed) array points to
+pub fn pointee(ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Pointer(element, _) | Type::Array(element, _) => Some(element.as_ref().clone()),
+        _ => None,
+    }
+}
+
+/// Comparisons and logical operators, whose result is a `bool`
+pub fn is_comparison(op: &BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Less | BinaryOp::Greater
+            | BinaryOp::LessEqual | BinaryOp::GreaterEqual | BinaryOp::LogicalAnd | BinaryOp::LogicalOr
+    )
+}
+
+/// Width in bits of an integer type, or None for anything else
+pub fn integer_width(ty: &Type, model: &DataModel) -> Option<u32> {
+    match ty {
+        Type::Char | Type::I8 | Type::U8 => Some(8),
+        Type::I16 | Type::U16 => Some(16),
+        Type::I32 | Type::U32 => Some(32),
+        Type::I64 | Type::U64 => Some(64),
+        Type::ISize | Type::USize => Some(model.pointer_bits()),
+        _ => None,
+    }
+}
+
+pub fn is_signed(ty: &Type) -> bool {
+    matches!(ty, Type::Char | Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::ISize)
+}
+
+/// The value of an integer constant like `42` or `-1`
+fn integer_literal(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::IntLiteral(value, _, _) => Some(*value),
+        Expr::Unary(UnaryOp::Neg, inner, _) => integer_literal(inner).map(|v| v.wrapping_neg()),
+        _ => None,
+    }
+}
+
+/// Whether `value` is in the range of the integer type `ty`
+fn literal_fits(value: i64, ty: &Type, model: &DataModel) -> bool {
+    let Some(bits) = integer_width(ty, model) else { return false };
+    if bits == 64 {
+        return is_signed(ty) || value >= 0;
+    }
+    if is_signed(ty) {
+        let max = (1i64 << (bits - 1)) - 1;
+        (-max - 1..=max).contains(&value)
+    } else {
+        (0..1i64 << bits).contains(&value)
+    }
+}
+
+/// Whether `condition` is a constant that is never zero, as in `while (1)`
+fn always_true(condition: &Expr) -> bool {
+    matches!(condition, Expr::IntLiteral(v, _, _) if *v != 0)
+        || matches!(condition, Expr::BoolLiteral(true, _))
+}
+
+/// Whether a `break` in `stmt` would leave the loop whose body it is
+fn contains_break(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Break(_) => true,
+        Stmt::Block(block) => block.stmts.iter().any(contains_break),
+        Stmt::If(if_stmt) => {
+            contains_break(&if_stmt.then_branch)
+                || if_stmt.else_branch.as_deref().is_some_and(contains_break)
+        }
+        // Breaks inside nested loops and switches belong to those
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    
+    fn check(source: &str) -> Vec<FeraError> {
+        let tokens = Lexer::new(source).tokenize();
+        let (program, errors) = Parser::new(tokens).parse_program();
+        assert!(errors.is_empty(), "unexpected parse errors: {:?}", errors);
+        TypeChecker::new().check_program(&program)
+    }
+    
+    #[test]
+    fn test_reports_every_error_once() {
+        let errors = check("
+            export i32 main() {
+                i32 a = 1.5;
+                i32 b = missing + 1;
+                i32 c = missing * 2;
+                print_i32(missing);
+                bool d = b;
+                return a;
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter()
+            .filter(|e| e.is_error())
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(messages, [
+            "Type error: Type mismatch in variable 'a'",
+            "Type error: Unknown identifier 'missing'",
+            "Type error: Type mismatch in variable 'd'",
+        ]);
+    }
+    
+    #[test]
+    fn test_assignment_requires_lvalue() {
+        let errors = check("
+            export i32 main() {
+                i32 a = 1;
+                f64 f = 1.5;
+                a += 2;
+                a++;
+                --a;
+                (a + 1)++;
+                5 = a;
+                a <<= 1;
+                f++;
+                return a;
+            }
+        ");
+
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Operand of '++' must be an lvalue",
+            "Type error: Operand of '=' must be an lvalue",
+            "Type error: Operand of '++' must be an integer or pointer, found 'f64'",
+        ]);
+    }
+
+    #[test]
+    fn test_assignment_types() {
+        let errors = check("
+            struct Point { i32 x; i32 y; };
+            
+            i32 assign(i32* p, Point s, f64 f, u8 small) {
+                p = 0;
+                small = 300;
+                f = 2.5;
+                p = 1.5;
+                s = 3;
+                s.x = s.y;
+                return *p;
+            }
+        ");
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Warning: Implicit conversion from 'i32' to 'u8' may lose information",
+            "Type error: Type mismatch in assignment",
+            "Type error: Type mismatch in assignment",
+        ]);
+    }
+    
+    #[test]
+    fn test_ternary_types() {
+        let errors = check("
+            export i32 main() {
+                i32 n = 1;
+                i64 wide = 2;
+                i64 a = n > 0 ? n : wide;
+                i32 b = n && wide ? 1 : 0;
+                f64 c = n ? 1 : 2.5;
+                i32* d = n ? &n : 2.5;
+                return b;
+            }
+        ");
+
+        let messages: Vec<_> = errors.iter().filter(|e| e.is_error()).map(|e| e.to_string()).collect();
+        assert_eq!(messages, ["Type error: Branches of '?:' have different types"]);
+    }
+
+    #[test]
+    fn test_field_access() {
+        let errors = check("
+            struct Point { i32 x; i32 y; };
+            union Value { i64 wide; f64 real; };
+            typedef Point* PointPtr;
+
+            i32 sum(PointPtr p, Value v) {
+                i64 w = v.wide;
+                f64 r = v.real;
+                i32 bad = p.x;
+                return p->x + p->y + p->z;
+            }
+        ");
+
+        let messages: Vec<_> = errors.iter().filter(|e| e.is_error()).map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Type 'struct Point*' has no fields",
+            "Type error: No field 'z' on type 'struct Point'",
+        ]);
+    }
+
+    #[test]
+    fn test_pointer_arithmetic_and_indexing() {
+        let errors = check("
+            i64 span(i32* start, i32* end, f64* other, void* raw) {
+                i32 values[4];
+                i32* p = values + 1;
+                i32* q = 2 + p;
+                i32* null = 0;
+                p += 1;
+                p++;
+                i32 first = values[0] + p[1];
+                i64 bad = end - other;
+                i32 wrong = first[0];
+                i32 also = values[1.5];
+                void* stepped = raw + 1;
+                return end - start;
+            }
+        ");
+
+        let messages: Vec<_> = errors.iter().filter(|e| e.is_error()).map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Cannot subtract pointers to different types",
+            "Type error: Cannot index into 'i32'",
+            "Type error: Array index must be an integer, found 'f64'",
+            "Type error: Cannot do arithmetic on 'void*'",
+        ]);
+    }
+
+    #[test]
+    fn test_usual_arithmetic_conversions() {
+        let cases = [
+            (Type::U8, Type::U8, Type::I32),
+            (Type::Char, Type::I16, Type::I32),
+            (Type::I32, Type::I64, Type::I64),
+            (Type::U32, Type::I32, Type::U32),
+            (Type::I32, Type::U32, Type::U32),
+            (Type::U32, Type::I64, Type::I64),
+            (Type::U64, Type::I64, Type::U64),
+            (Type::Bool, Type::U16, Type::I32),
+            (Type::I64, Type::F32, Type::F32),
+            (Type::F32, Type::F64, Type::F64),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(arithmetic_conversion(&a, &b, &DataModel::LP64), expected, "{} and {}", a, b);
+        }
+        
+        let errors = check("
+            struct Point { i32 x; i32 y; };
+            
+            u8 mix(u8 a, u8 b, Point p, f64 f) {
+                u8 sum = a + b;
+                i32 _bad = p + 1;
+                i32 _also = f % 2;
+                i32 _flipped = ~f;
+                return sum;
+            }
+        ");
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Warning: Implicit conversion from 'i32' to 'u8' may lose information",
+            "Type error: Operands of '+' must be numbers, found 'struct Point'",
+            "Type error: Operands of '%' must be integers, found 'f64'",
+            "Type error: Operand of '~' must be an integer, found 'f64'",
+        ]);
+    }
+    
+    #[test]
+    fn test_bool_conversions() {
+        let errors = check("
+            struct Point { i32 x; i32 y; };
+            
+            bool positive(i32 n) {
+                return n > 0;
+            }
+            
+            i32 count(i32 a, f64 b, u8* p, Point s) {
+                bool both = a && b;
+                bool present = p != 0;
+                bool negated = !p;
+                bool _bad = s;
+                i32 sum = both + present + negated;
+                return sum + (a < b);
+            }
+        ");
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, ["Type error: Type mismatch in variable '_bad'"]);
+    }
+    
+    #[test]
+    fn test_comparison_operands() {
+        let errors = check("
+            struct Point { i32 x; i32 y; };
+            
+            bool compare(Point a, Point b, i32* p, u8* bytes, void* any, f64 x) {
+                bool fine = p == 0 && p != any && x < 1 || !p;
+                bool _same = a == b;
+                bool _both = a && x;
+                bool _not = !a;
+                bool _offset = p < 5;
+                bool _real = p == 1.0;
+                bool _mixed = p == bytes;
+                return fine;
+            }
+        ");
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Cannot compare 'struct Point' with 'struct Point'",
+            "Type error: Operands of '&&' must be numbers or pointers, found 'struct Point'",
+            "Type error: Operand of '!' must be a number or pointer, found 'struct Point'",
+            "Type error: Cannot compare 'i32*' with 'i32'",
+            "Type error: Cannot compare 'i32*' with 'f64'",
+            "Type error: Cannot compare 'i32*' with 'u8*'",
+        ]);
+    }
+    
+    #[test]
+    fn test_casts() {
+        let errors = check("
+            struct Point { i32 x; i32 y; };
+            typedef u32* Register;
+            
+            i32 convert(f64 real, i32* ptr, Point p) {
+                i32 whole = (i32)real;
+                f32 narrow = (f32)whole;
+                u64 address = (u64)ptr;
+                Register reg = (Register)0x40020000;
+                u8* bytes = (u8*)ptr;
+                (void)narrow;
+                Point same = (Point)p;
+                i32 bad = (i32)p;
+                f64* wrong = (f64*)real;
+                f64 also = (f64)ptr;
+                return whole + *bytes;
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().filter(|e| e.is_error()).map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Cannot cast 'struct Point' to 'i32'",
+            "Type error: Cannot cast 'f64' to 'f64*'",
+            "Type error: Cannot cast 'i32*' to 'f64'",
+        ]);
+    }
+    
+    #[test]
+    fn test_sizeof_and_alignof() {
+        let errors = check("
+            struct Pair { u8 tag; f64 value; };
+            
+            usize sizes(Pair p, i32* ptr) {
+                usize total = sizeof(Pair) + alignof(Pair) + sizeof p + sizeof *ptr;
+                u8 narrow = sizeof(i32);
+                usize none = sizeof(void);
+                return total + sizeof ptr[0] + alignof(struct Missing);
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().filter(|e| e.is_error()).map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Cannot take the size of 'void'",
+            "Type error: Cannot take the size of 'struct Missing'",
+        ]);
+    }
+    
+    #[test]
+    fn test_loops_and_switch() {
+        let errors = check("
+            i32 f(i32 n, f64 x) {
+                for (i32 i = 0; i < n; i++) {
+                    switch (i) {
+                        case 1: continue;
+                        case 2: break;
+                        case 1: n++;
+                        default: default: break;
+                        case n: break;
+                    }
+                }
+                do { if (n) break; } while (n--);
+                switch (x) { }
+                break;
+                continue;
+                for (;;) { }
+            }
+            
+            i32 endless(i32 n) {
+                while (1) { switch (n) { case 0: break; } }
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().filter(|e| e.is_error()).map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Duplicate case value 1",
+            "Type error: Multiple 'default' labels in one switch",
+            "Type error: Expected an integer constant expression",
+            "Type error: Switch value must be an integer, found 'f64'",
+            "Type error: 'break' outside of a loop or switch",
+            "Type error: 'continue' outside of a loop",
+        ]);
+        // Neither function can reach its end
+        assert!(errors.iter().all(|e| !e.to_string().contains("without returning")));
+    }
+    
+    #[test]
+    fn test_labels_and_goto() {
+        let errors = check("
+            i32 open_device(i32 id) {
+                if (id < 0) goto fail;
+                i32 handle = id * 2;
+                if (handle > 100) goto fail;
+                {
+                    i32 retries = 3;
+                retry:
+                    retries--;
+                    if (retries > 0) goto retry;
+                }
+                goto retry;
+                return handle;
+            fail:
+                return -1;
+            fail:
+                goto done;
+            }
+            
+            void other() {
+                goto fail;
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Warning: Jump to label 'fail' skips the initialization of 'handle'",
+            "Warning: Jump to label 'retry' skips the initialization of 'retries'",
+            "Warning: Unreachable statement",
+            "Type error: Duplicate label 'fail'",
+            "Type error: Undefined label 'done'",
+            "Type error: Undefined label 'fail'",
+        ]);
+    }
+    
+    #[test]
+    fn test_enum_constants() {
+        let errors = check("
+            enum Color { RED, GREEN = 4, BLUE };
+            enum Small : u8 { LOW = 255, HIGH };
+            enum Mode : f32 { FAST };
+            enum Clash { RED };
+            
+            i32 paint(Color c) {
+                Color next = BLUE;
+                i32 code = c + GREEN * 2;
+                u8 bytes[BLUE];
+                switch (c) {
+                    case RED: return code;
+                    case GREEN: return BLUE;
+                }
+                switch (next) {
+                    case RED: return 1;
+                    default: break;
+                }
+                switch (c) {
+                    case BLUE: break;
+                    case 5: break;
+                }
+                return (i32)sizeof(bytes);
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Value 256 of 'HIGH' does not fit in 'u8'",
+            "Type error: Backing type of enum 'Mode' must be an integer, found 'f32'",
+            "Type error: Duplicate enum constant 'RED'",
+            "Warning: Switch over enum 'Color' does not handle 'BLUE'",
+            "Warning: Switch over enum 'Color' does not handle 'RED', 'GREEN'",
+            "Type error: Duplicate case value 5",
+        ]);
+    }
+    
+    #[test]
+    fn test_global_initializers() {
+        let errors = check("
+            struct Pair { i32 a; i32 b; };
+            i32 seed = 4;
+            i32 copy = seed;
+            f64 ratio = \"x\";
+            Pair pair = {1, 2.5};
+            Pair* pair_ptr = &pair;
+            char label[] = \"pairs\";
+            
+            i32 total() {
+                return seed + pair_ptr->a + (i32)sizeof(label);
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Expected an integer constant expression",
+            "Type error: Type mismatch in global 'ratio'",
+            "Type error: Type mismatch in global 'pair'",
+        ]);
+    }
+    
+    #[test]
+    fn test_attributes() {
+        let errors = check("
+            [[packed(3)]] struct Header { u8 tag; };
+            [[deprecated(\"use start_v2\")]] void start() { }
+            [[deprecated]] void stop() { }
+            [[noreturn, inline]] void halt() { }
+            [[always_inline, noinline]] void spin() { }
+            [[noreturn]] i32 counter;
+            
+            void main() {
+                start();
+                stop();
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Attribute 'packed' expects a power of two alignment",
+            "Type error: Unknown attribute 'inline'",
+            "Type error: Attributes 'always_inline' and 'noinline' conflict",
+            "Type error: Attribute 'noreturn' cannot be applied to a global",
+            "Warning: Function 'start' is deprecated: use start_v2",
+            "Warning: Function 'stop' is deprecated",
+        ]);
+    }
+    
+    #[test]
+    fn test_function_pointers() {
+        let errors = check("
+            typedef i32 (*BinOp)(i32, i32);
+            struct Device { void (*reset)(void); };
+            
+            i32 add(i32 a, i32 b) { return a + b; }
+            void halt(void) { }
+            BinOp ops[2] = {add, &add};
+            
+            i32 main(Device* device, i32 n) {
+                BinOp op = add;
+                void (*stop)(void) = halt;
+                device->reset = stop;
+                device->reset();
+                n = op(1, 2) + (*op)(3, 4) + ops[1](5, 6);
+                op = halt;
+                op(1);
+                n(2);
+                op + 1;
+                return n;
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Type mismatch in assignment",
+            "Type error: Function pointer 'op' expects 2 arguments, but 1 were provided",
+            "Type error: Cannot call 'i32'",
+            "Type error: Cannot do arithmetic on a function pointer",
+        ]);
+    }
+    
+    #[test]
+    fn test_void_calls() {
+        let errors = check("
+            void tick() { }
+            
+            i32 main(i32 n) {
+                void (*handler)() = tick;
+                tick();
+                handler();
+                (void)tick();
+                (void)n;
+                for (n = 0; n < 3; tick()) { n++; }
+                n = tick();
+                n = handler() + 1;
+                n = (void)n;
+                return tick();
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Cannot use the result of 'tick', which returns void",
+            "Type error: Cannot use the result of 'handler', which returns void",
+            "Type error: Cannot use the value of a cast to 'void'",
+            "Type error: Cannot use the result of 'tick', which returns void",
+        ]);
+    }
+    
+    #[test]
+    fn test_variadic_functions() {
+        let errors = check("
+            struct Point { i32 x; i32 y; };
+            i32 printf(char* format, ...);
+            i32 snprintf(char* buffer, usize size, char* format, ...);
+            i32 count(i32 n, ...) { return n; }
+            
+            void main(Point p, f32 half, u8 small, i64 big) {
+                char buffer[16];
+                i32 (*log)(char*, ...) = printf;
+                printf(\"%d %c %f %ld %s %p %%\\n\", small, 'x', half, big, buffer, &p);
+                snprintf(buffer, 16, \"%*d %zu\", 4, 7, sizeof(Point));
+                log(\"%d\", half);
+                printf();
+                printf(\"%d %s\", 1.5, 3);
+                printf(\"%d\");
+                printf(\"done\", 1);
+                printf(\"%k\", p);
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Variadic function 'count' can only be declared",
+            "Type error: Function 'printf' expects at least 1 arguments, but 0 were provided",
+            "Warning: Format '%d' expects 'i32', but argument 2 has type 'f64'",
+            "Warning: Format '%s' expects 'char*', but argument 3 has type 'i32'",
+            "Warning: Format '%d' has no matching argument",
+            "Warning: Format string takes 0 arguments, but 1 were provided",
+            "Warning: Unknown conversion '%k' in format string",
+            "Type error: Cannot pass 'struct Point' as a variadic argument of function 'printf'",
+        ]);
+    }
+    
+    #[test]
+    fn test_inline_asm() {
+        let errors = check("
+            struct Regs { u64 cr0; };
+            
+            void main(u64 value, Regs regs) {
+                asm volatile(\"cli\");
+                asm volatile(\"mov %%cr0, %0\" : \"=r\"(value));
+                asm(\"mov %1, %0\" : \"+r\"(value) : \"rm\"(value + 1), \"0\"(value) : \"cc\");
+                asm(\"mov %1, %0\" : \"r\"(value) : \"i\"(value));
+                asm(\"mov %1, %0\" : \"=r\"(value + 1) : \"m\"(regs));
+                asm(\"mov %1, %0\" : \"=r\"(value) : \"r\"(regs), \"1\"(value));
+                asm(\"mov %2, %0\" : \"=r\"(value) : \"q\"(value));
+            }
+        ");
+        
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "Type error: Asm output constraint 'r' must start with '=' or '+'",
+            "Type error: Asm constraint 'i' needs an integer constant",
+            "Type error: Operand of 'asm' must be an lvalue",
+            "Type error: Asm operand with constraint 'r' must be a number or pointer",
+            "Type error: Asm constraint '1' refers to output 1, but there are only 1 outputs",
+            "Type error: Asm operand '%2' is out of range, there are 2 operands",
+        ]);
+    }
+    
+    fn lints(source: &str, config: LintConfig) -> Vec<String> {
+        let tokens = Lexer::new(source).tokenize();
+        let (program, _) = Parser::new(tokens).parse_program();
+        let mut checker = TypeChecker::new();
+        checker.set_lints(config);
+        checker.check_program(&program).iter()
+            .filter_map(|d| match d {
+                FeraError::Warning(w) => Some(format!("{}: {}", w.lint, w.message)),
+                _ => None,
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn test_lints() {
+        let source = "
+            i32 pick(i32 flag, i32 unused, i64 wide) {
+                i32 narrow = wide;
+                i8 small = 100;
+                if (flag) {
+                    i32 flag = 2;
+                    return flag;
+                    print_i32(narrow);
+                }
+            }
+            
+            i32 both(i32 x) {
+                if (x) { return 1; } else { return 2; }
+            }
+        ";
+        
+        assert_eq!(lints(source, LintConfig::new()), [
+            "unused-parameter: Unused parameter 'unused'",
+            "implicit-narrowing: Implicit conversion from 'i64' to 'i32' may lose information",
+            "unused-variable: Unused variable 'small'",
+            "unreachable-code: Unreachable statement",
+            "missing-return: Function 'pick' can reach its end without returning a value",
+        ]);
+        
+        let mut config = LintConfig::new();
+        config.set_by_name("all", LintLevel::Allow).unwrap();
+        config.set(Lint::Shadowing, LintLevel::Warn);
+        assert_eq!(lints(source, config), ["shadowing: 'flag' shadows an earlier declaration"]);
+    }
+}
@@ -40,6 +40,560 @@ fn test_build_hello_world() {
         .success();
 }
 
+#[test]
+fn test_build_structs_and_unions() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("records.fera");
+
+    fs::write(&source_path, r#"
+        Shape unit;
+
+        i32 width(Shape s) {
+            return s.sizes[1].w;
+        }
+
+        struct Vec2 { f32 x; f32 y; };
+        struct Body { char tag; Vec2 pos; i64 id; };
+        union Bits { f32 f; i32 i; };
+
+        Vec2 vec2(f32 x, f32 y) {
+            Vec2 v;
+            v.x = x;
+            v.y = y;
+            return v;
+        }
+
+        void move(Body* b, Vec2 by) {
+            b->pos.x += by.x;
+            b->pos.y += by.y;
+        }
+
+        export i32 main() {
+            Body b;
+            b.pos = vec2(1.0f, 2.0f);
+            move(&b, vec2(0.5f, 0.5f));
+            Bits bits;
+            bits.f = b.pos.y;
+            return bits.i + width(unit);
+        }
+
+        // Defined after the function and global that use them
+        struct Shape { Size sizes[2]; Shape* next; };
+        struct Size { i32 w; i64 h; };
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("records.ll")).unwrap();
+    let unit = ir.lines().find(|line| line.starts_with("@unit")).unwrap();
+    assert!(unit.ends_with("align 8"), "unaligned global: {}", unit);
+}
+
+#[test]
+fn test_build_pointer_operations() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("pointers.fera");
+
+    fs::write(&source_path, r#"
+        void swap(i32* a, i32* b) {
+            if (!a || !b) return;
+            i32 temp = *a;
+            *a = *b;
+            *b = temp;
+        }
+
+        i32 sum(const i32* values, usize len) {
+            i32 total = 0;
+            const i32* end = values + len;
+            while (values != end) {
+                total += *values++;
+            }
+            return total;
+        }
+
+        export i32 main() {
+            i32 numbers[4];
+            numbers[0] = 1;
+            numbers[3] = 4;
+            swap(&numbers[0], &numbers[3]);
+            i32* last = &numbers[3];
+            return sum(numbers, last - numbers + 1);
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_build_for_32_bit_target() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("i686.fera");
+
+    fs::write(&source_path, r#"
+        struct Entry { i32 key; i64 value; };
+
+        usize entry_size() {
+            return sizeof(Entry);
+        }
+
+        usize pointer_size() {
+            return sizeof(i32*);
+        }
+
+        isize distance(i32* a, i32* b) {
+            return a - b;
+        }
+
+        export i32 main() {
+            return 0;
+        }
+    "#).unwrap();
+
+    // Linking against the host's libraries may fail, but the IR is
+    // written before that
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .arg("--target")
+        .arg("i686-unknown-linux-gnu")
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+
+    let ir = fs::read_to_string(temp_dir.path().join("i686.ll")).unwrap();
+    assert!(ir.contains("target triple = \"i686-unknown-linux-gnu\""), "{}", ir);
+    assert!(ir.contains("target datalayout = \"e-m:e-p:32:32"), "{}", ir);
+    // i64 is only 4-byte aligned on i386, so `Entry` has no padding
+    assert!(ir.contains("ret i32 12\n"), "{}", ir);
+    assert!(ir.contains("ret i32 4\n"), "{}", ir);
+    assert!(ir.contains("define i32 @distance("), "{}", ir);
+}
+
+#[test]
+fn test_build_signedness_and_wrapv() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("signedness.fera");
+
+    fs::write(&source_path, r#"
+        u32 unsigned_ops(u32 a, u32 b) {
+            if (a < b) {
+                return a / b;
+            }
+            return (a % b) + (a >> 1);
+        }
+
+        i32 signed_ops(i32 a, i32 b) {
+            if (a < b) {
+                return a / b;
+            }
+            return (a % b) + (a >> 1) * b;
+        }
+
+        export i32 main() {
+            i32 u = unsigned_ops(7, 2);
+            return signed_ops(7, 2) + u;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("signedness.ll")).unwrap();
+    for instruction in ["icmp ult", "udiv", "urem", "lshr", "icmp slt", "sdiv", "srem", "ashr", "add nsw", "mul nsw"] {
+        assert!(ir.contains(instruction), "missing `{}` in:\n{}", instruction, ir);
+    }
+
+    // -fwrapv drops the no-signed-wrap flags
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg("-fwrapv")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("signedness.ll")).unwrap();
+    assert!(!ir.contains("nsw"), "unexpected `nsw` in:\n{}", ir);
+}
+
+#[test]
+fn test_build_implicit_conversions() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("conversions.fera");
+
+    fs::write(&source_path, r#"
+        i64 widen(i32 signed_value, u16 unsigned_value) {
+            return signed_value + unsigned_value;
+        }
+
+        f64 average(i32 total, u32 count) {
+            return total / 1.0 / count;
+        }
+
+        bool positive(i32 n) {
+            return n > 0;
+        }
+
+        export i32 main() {
+            i64 big = widen(-1, 65535) + 5000000000;
+            u8 low = big;
+            f64 avg = average(10, 4u);
+            f64 x = -1.5;
+            f64 y = -avg;
+            f32 flipped = -(f32)avg;
+            bool nonzero = avg != 0.0;
+            bool both = positive(low) && nonzero;
+            i32 count = both + positive(-1);
+            return low + count;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("conversions.ll")).unwrap();
+    let expected = [
+        "sext i32", "zext i16", "trunc i64", "sitofp i32", "uitofp i32", "5000000000", "zext i1",
+        "double -1.5", "fneg double", "fneg float",
+    ];
+    for instruction in expected {
+        assert!(ir.contains(instruction), "missing `{}` in:\n{}", instruction, ir);
+    }
+}
+
+#[test]
+fn test_build_casts() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("casts.fera");
+
+    fs::write(&source_path, r#"
+        typedef u32* Register;
+
+        void set_bit(u64 address, u32 bit) {
+            Register reg = (Register)address;
+            *reg = *reg | (u32)1 << bit;
+        }
+
+        export i32 main() {
+            u32 word = 0;
+            set_bit((u64)&word, 3);
+            f64 scaled = (f64)word * 1.5;
+            return (i32)scaled;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("casts.ll")).unwrap();
+    for instruction in ["inttoptr i64", "ptrtoint", "uitofp i32", "fptosi double"] {
+        assert!(ir.contains(instruction), "missing `{}` in:\n{}", instruction, ir);
+    }
+}
+
+#[test]
+fn test_build_volatile_accesses() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("volatile.fera");
+
+    fs::write(&source_path, r#"
+        struct Uart { u32 data; u32 status; };
+        typedef volatile u32* Register;
+
+        void wait(volatile Uart* uart) {
+            while ((uart->status & 1) == 0) { }
+            uart->data = 65;
+        }
+
+        export i32 main() {
+            u32 word = 0;
+            Register reg = (Register)&word;
+            *reg = 1;
+            *reg |= 2;
+            u32* plain = &word;
+            *plain = *reg + 1;
+            return (i32)*plain;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("volatile.ll")).unwrap();
+    for expected in ["load volatile i32", "store volatile i32 65", "store volatile i32 1,", "store i32 %"] {
+        assert!(ir.contains(expected), "missing `{}` in:\n{}", expected, ir);
+    }
+}
+
+#[test]
+fn test_build_loops_and_switch() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("control.fera");
+
+    fs::write(&source_path, r#"
+        i32 weekday(i32 day) {
+            switch (day) {
+                case 0:
+                case 6: return 0;
+                case 1: case 2: case 3: case 4: case 5: return 1;
+                default: return -1;
+            }
+        }
+
+        i32 code(i32 status) {
+            switch (status) {
+                case 200: return 0;
+                case 404: return 1;
+                case 500: return 2;
+            }
+            return 3;
+        }
+
+        i64 shadowed() {
+            i64 i = 10;
+            for (u8 i = 0; i < 3; i++) {}
+            {
+                i16 i = 5;
+                i++;
+            }
+            return i;
+        }
+
+        export i32 main() {
+            i32 total = (i32)shadowed();
+            for (i32 i = 0; i < 7; i++) {
+                if (i == 2) continue;
+                total += weekday(i);
+            }
+            do {
+                total--;
+                if (total == 1) break;
+            } while (total > 0);
+            return total + code(404);
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    // Dense cases make a switch instruction, sparse ones a compare chain
+    let ir = fs::read_to_string(temp_dir.path().join("control.ll")).unwrap();
+    for instruction in ["switch i32", "icmp eq i32 %status", "for.inc", "do.cond", "load i64"] {
+        assert!(ir.contains(instruction), "missing `{}` in:\n{}", instruction, ir);
+    }
+}
+
+#[test]
+fn test_build_goto_cleanup() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("cleanup.fera");
+
+    fs::write(&source_path, r#"
+        i32 acquire(i32 id) { return id; }
+        void release(i32 handle) { }
+
+        i32 init(i32 a, i32 b) {
+            i32 first = acquire(a);
+            if (first < 0) goto err;
+            i32 second = acquire(b);
+            if (second < 0) goto err_first;
+            return 0;
+        err_first:
+            release(first);
+        err:
+            return -1;
+        }
+
+        export i32 main() {
+            return init(1, -1) + 1;
+        }
+    "#).unwrap();
+
+    // The first goto skips `second`, which is only a warning
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Jump to label 'err' skips the initialization of 'second'"));
+
+    let ir = fs::read_to_string(temp_dir.path().join("cleanup.ll")).unwrap();
+    for label in ["err_first:", "err:", "br label %err"] {
+        assert!(ir.contains(label), "missing `{}` in:\n{}", label, ir);
+    }
+}
+
+#[test]
+fn test_build_enum_switch() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("enums.fera");
+
+    fs::write(&source_path, r#"
+        enum Level : u8 { LOW = 1, MID, HIGH = 200 };
+
+        i32 weight(Level level) {
+            switch (level) {
+                case LOW: return 1;
+                case HIGH: return 3;
+            }
+            return 0;
+        }
+
+        export i32 main() {
+            Level level = MID;
+            return weight(level) + weight(HIGH);
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Switch over enum 'Level' does not handle 'MID'"));
+
+    // Enums are stored as their backing type
+    let ir = fs::read_to_string(temp_dir.path().join("enums.ll")).unwrap();
+    for expected in ["define i32 @weight(i8", "store i8 2", "i8 -56"] {
+        assert!(ir.contains(expected), "missing `{}` in:\n{}", expected, ir);
+    }
+}
+
+#[test]
+fn test_build_global_initializers() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("globals.fera");
+
+    fs::write(&source_path, r#"
+        struct Point { i32 x; i32 y; };
+
+        f64 SCALE = 2.5;
+        char* NAME = "origin";
+        i32 primes[4] = {2, 3};
+        i32 counter;
+        i32* counter_ptr = &counter;
+        Point origin = {1, -2};
+
+        export i32 main() {
+            *counter_ptr = primes[1];
+            return counter + origin.y;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("globals.ll")).unwrap();
+    for expected in [
+        "@SCALE = global double 2.500000e+00",
+        "c\"origin\\00\"",
+        "@primes = global [4 x i32] [i32 2, i32 3, i32 0, i32 0]",
+        "@counter = global i32 0",
+        "@counter, align 8",
+        "@origin = global %struct.Point { i32 1, i32 -2 }",
+    ] {
+        assert!(ir.contains(expected), "missing `{}` in:\n{}", expected, ir);
+    }
+}
+
+#[test]
+fn test_check_rejects_non_constant_global() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("global.fera");
+
+    fs::write(&source_path, r#"
+        i32 seed = 4;
+        i32* next = &seed + 1;
+        export i32 main() { return 0; }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg(&source_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Initializer of a global must be a constant"));
+}
+
+#[test]
+fn test_build_attributes() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("attributes.fera");
+
+    fs::write(&source_path, r#"
+        [[packed]] struct Header { u8 tag; u32 len; };
+        [[section(".data.boot")]] i32 boot_count = 3;
+        [[weak]] i32 hook_level;
+
+        [[deprecated("use log_v2")]]
+        void log(i32 level) { }
+
+        [[cold, noinline]]
+        i32 slow_path(i32 x) { return x * 3; }
+
+        export i32 main() {
+            Header header;
+            header.len = 2;
+            log(hook_level);
+            return slow_path(boot_count) + header.len;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Function 'log' is deprecated: use log_v2"));
+
+    let ir = fs::read_to_string(temp_dir.path().join("attributes.ll")).unwrap();
+    for expected in [
+        "%struct.Header = type <{ i8, i32 }>",
+        "section \".data.boot\"",
+        "@hook_level = weak global i32 0",
+        "{ cold noinline }",
+    ] {
+        assert!(ir.contains(expected), "missing `{}` in:\n{}", expected, ir);
+    }
+}
+
 #[test]
 fn test_check_valid_code() {
     let temp_dir = TempDir::new().unwrap();
@@ -74,3 +628,390 @@ fn test_clean() {
         .success();
 }
 
+
+#[test]
+fn test_check_with_preprocessor() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("config.h"), r#"
+        #pragma once
+        #define LIMIT 10
+        #define SQUARE(x) ((x) * (x))
+    "#).unwrap();
+    
+    let source_path = temp_dir.path().join("macros.fera");
+    fs::write(&source_path, r#"
+        #include "config.h"
+        #include "config.h"
+        
+        #ifdef LIMIT
+        export i32 main() {
+            return SQUARE(LIMIT);
+        }
+        #else
+        this does not parse
+        #endif
+    "#).unwrap();
+    
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg(&source_path)
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_check_reports_error_location() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("mismatch.fera");
+    
+    fs::write(&source_path, r#"export i32 main() {
+    i32 count = 1.5;
+    return count;
+}
+"#).unwrap();
+    
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Type mismatch in variable 'count'"))
+        .stderr(predicate::str::contains("mismatch.fera:2:17"))
+        .stderr(predicate::str::contains("expected `i32`, found `f64`"));
+}
+
+#[test]
+fn test_check_reports_all_parse_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("typos.fera");
+    
+    fs::write(&source_path, r#"export i32 main() {
+    i32 a = 1 +;
+    i32 b = (2;
+    return a
+}
+"#).unwrap();
+    
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("typos.fera:2:16"))
+        .stderr(predicate::str::contains("typos.fera:3:15"))
+        .stderr(predicate::str::contains("typos.fera:5:1"))
+        .stderr(predicate::str::contains("due to 3 previous errors"));
+}
+
+#[test]
+fn test_check_reports_all_type_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("types.fera");
+    
+    fs::write(&source_path, r#"i32 twice(i32 n) {
+    return n * 2;
+}
+
+export i32 main() {
+    i32 a = 1.5;
+    i32 b = twice(1, 2);
+    f64 c = twice(3);
+    return a + b;
+}
+"#).unwrap();
+    
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Type mismatch in variable 'a'"))
+        .stderr(predicate::str::contains("Function 'twice' expects 1 arguments, but 2 were provided"))
+        .stderr(predicate::str::contains("Type mismatch in variable 'c'"))
+        .stderr(predicate::str::contains("due to 3 previous errors"));
+}
+
+#[test]
+fn test_build_missing_return() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("missing_return.fera");
+
+    fs::write(&source_path, r#"
+        struct Pair { i32 a; i32 b; };
+
+        i32 sign(i32 n) {
+            if (n < 0) return -1;
+            if (n > 0) return 1;
+        }
+
+        Pair pair(bool swap) {
+            Pair p;
+            if (swap) return p;
+        }
+
+        export i32 main() {
+            Pair p = pair(false);
+            return sign(0) + p.a;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Function 'sign' can reach its end without returning a value"));
+
+    // Falling off the end is undefined, so nothing is returned there
+    let ir = fs::read_to_string(temp_dir.path().join("missing_return.ll")).unwrap();
+    assert!(ir.contains("  unreachable\n"), "missing `unreachable` in:\n{}", ir);
+}
+
+#[test]
+fn test_lint_levels_from_flags_and_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("lints.fera");
+    
+    fs::write(&source_path, r#"export i32 main() {
+    i32 unused = 1;
+    return 0;
+}
+"#).unwrap();
+    
+    // Warnings don't fail the check
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("warning: Unused variable 'unused'"));
+    
+    // -D turns the lint into an error
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg("-D").arg("unused-variable")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error: Unused variable 'unused'"));
+    
+    // Later flags override earlier ones
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg("-D").arg("all")
+        .arg("-A").arg("unused-variable")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Unused variable").not());
+    
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg("-A").arg("unused-variable")
+        .arg("-D").arg("all")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error: Unused variable 'unused'"));
+    
+    // fera.toml can silence it
+    fs::write(temp_dir.path().join("fera.toml"), "[lints]\nunused-variable = \"allow\"\n").unwrap();
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Unused variable").not());
+}
+
+#[test]
+fn test_build_inline_asm() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("inline_asm.fera");
+
+    fs::write(&source_path, r#"
+        u64 read_cr0() {
+            u64 value;
+            asm volatile("mov %%cr0, %0" : "=r"(value));
+            return value;
+        }
+
+        export i32 main() {
+            i32 count = 40;
+            i64 total = 1;
+            asm("addl %1, %0" : "+r"(count) : "i"(2));
+            asm("addq $5, %0" : "+m"(total) :: "cc");
+            asm volatile("cli" ::: "memory");
+            return count;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("inline_asm.ll")).unwrap();
+    for expected in [
+        "asm sideeffect \"mov %cr0, $0\", \"=r\"()",
+        "asm \"addl $1, $0\", \"=r,i,0\"(i32 2, i32",
+        "asm \"addq $$5, $0\", \"=*m,~{cc}\"",
+        "elementtype(i64)",
+        "asm sideeffect \"cli\", \"~{memory}\"()",
+    ] {
+        assert!(ir.contains(expected), "missing `{}` in:\n{}", expected, ir);
+    }
+}
+
+#[test]
+fn test_build_function_pointers() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("function_pointers.fera");
+
+    fs::write(&source_path, r#"
+        typedef i32 (*BinOp)(i32, i32);
+        struct Driver { void (*start)(void); BinOp combine; };
+
+        void isr_timer(void);
+        i32 add(i32 a, i32 b) { return a + b; }
+        void boot(void) { }
+
+        void (*vectors[2])(void) = {isr_timer, 0};
+        Driver driver = {boot, &add};
+
+        export i32 main() {
+            BinOp op = driver.combine;
+            driver.start();
+            vectors[0]();
+            return op(2, 3) + (*op)(4, 5);
+        }
+
+        void isr_timer(void) { }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("function_pointers.ll")).unwrap();
+    for expected in [
+        "define void @isr_timer()",
+        "@vectors = global [2 x",
+        "@isr_timer, ",
+        "@driver = global %struct.Driver {",
+        "@boot, ",
+        "@add }",
+        "call i32 %",
+    ] {
+        assert!(ir.contains(expected), "missing `{}` in:\n{}", expected, ir);
+    }
+}
+
+#[test]
+fn test_build_variadic_calls() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("variadic.fera");
+
+    fs::write(&source_path, r#"
+        i32 printf(char* format, ...);
+
+        export i32 main() {
+            f32 half = 0.5;
+            u8 small = 200;
+            i32 (*print)(char*, ...) = printf;
+            printf("%f %u\n", half, small);
+            return print("%d\n", 42);
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("variadic.ll")).unwrap();
+    for expected in [
+        "declare i32 @printf(",
+        ", ...)",
+        "fpext float",
+        "zext i8",
+        ", double %",
+        ", i32 42)",
+    ] {
+        assert!(ir.contains(expected), "missing `{}` in:\n{}", expected, ir);
+    }
+}
+
+#[test]
+fn test_build_void_calls() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("void_calls.fera");
+
+    fs::write(&source_path, r#"
+        i32 ticks;
+        void tick() { ticks++; }
+
+        export i32 main() {
+            void (*handler)() = tick;
+            for (i32 i = 0; i < 3; tick()) {
+                i++;
+            }
+            handler();
+            (void)tick();
+            (void)handler;
+            return ticks;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("build")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    let ir = fs::read_to_string(temp_dir.path().join("void_calls.ll")).unwrap();
+    for expected in ["call void @tick()", "call void %"] {
+        assert!(ir.contains(expected), "missing `{}` in:\n{}", expected, ir);
+    }
+    assert!(!ir.contains("= call void"), "void call given a name in:\n{}", ir);
+}
+
+#[test]
+fn test_check_rejects_void_values() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_path = temp_dir.path().join("void_value.fera");
+
+    fs::write(&source_path, r#"
+        void tick() { }
+
+        export i32 main() {
+            i32 x = tick();
+            return x;
+        }
+    "#).unwrap();
+
+    let mut cmd = Command::cargo_bin("fera").unwrap();
+    cmd.arg("check")
+        .arg(&source_path)
+        .current_dir(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Cannot use the result of 'tick', which returns void"));
+}